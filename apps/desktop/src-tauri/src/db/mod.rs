@@ -1,14 +1,66 @@
 use anyhow::Result;
-use sqlx::{sqlite::{SqlitePool, SqlitePoolOptions}, Pool, Sqlite};
+use sqlx::{
+    sqlite::{SqlitePool, SqlitePoolOptions},
+    Pool, Sqlite,
+};
 use std::path::Path;
 
+// Budgets, alerts and events belong to either a provider or a group. Tables
+// from before groups required a provider and are rebuilt from these.
+const BUDGETS_TABLE: &str = r#"
+    CREATE TABLE IF NOT EXISTS budgets (
+        id TEXT PRIMARY KEY,
+        provider_id TEXT,
+        group_id TEXT,
+        period TEXT NOT NULL,
+        soft_limit REAL,
+        hard_limit REAL,
+        notes TEXT,
+        created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+        updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (provider_id) REFERENCES providers(id),
+        FOREIGN KEY (group_id) REFERENCES provider_groups(id)
+    )
+    "#;
+
+const ALERTS_TABLE: &str = r#"
+    CREATE TABLE IF NOT EXISTS alerts (
+        id TEXT PRIMARY KEY,
+        provider_id TEXT,
+        group_id TEXT,
+        rule_json TEXT NOT NULL,
+        last_fired_at TIMESTAMP,
+        status TEXT NOT NULL DEFAULT 'active',
+        notify_desktop BOOLEAN NOT NULL DEFAULT 1,
+        snoozed_until TIMESTAMP,
+        channels_json TEXT,
+        created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (provider_id) REFERENCES providers(id),
+        FOREIGN KEY (group_id) REFERENCES provider_groups(id)
+    )
+    "#;
+
+const EVENTS_TABLE: &str = r#"
+    CREATE TABLE IF NOT EXISTS events (
+        id TEXT PRIMARY KEY,
+        provider_id TEXT,
+        group_id TEXT,
+        timestamp TIMESTAMP NOT NULL,
+        kind TEXT NOT NULL,
+        payload TEXT,
+        created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (provider_id) REFERENCES providers(id),
+        FOREIGN KEY (group_id) REFERENCES provider_groups(id)
+    )
+    "#;
+
 pub struct Database {
     pool: Pool<Sqlite>,
 }
 
 impl Database {
     pub async fn new(db_path: &Path) -> Result<Self> {
-        let db_url = format!("sqlite:{}", db_path.display());
+        let db_url = format!("sqlite:{}?mode=rwc", db_path.display());
 
         let pool = SqlitePoolOptions::new()
             .max_connections(5)
@@ -20,6 +72,16 @@ impl Database {
         Ok(db)
     }
 
+    /// An empty database that lives only as long as it is open, for tests.
+    #[cfg(test)]
+    pub async fn in_memory() -> Result<Self> {
+        let db = Self {
+            pool: memory_pool().await?,
+        };
+        db.run_migrations().await?;
+        Ok(db)
+    }
+
     async fn run_migrations(&self) -> Result<()> {
         sqlx::query(
             r#"
@@ -29,6 +91,7 @@ impl Database {
                 provider_type TEXT NOT NULL,
                 api_key_ref TEXT,
                 enabled BOOLEAN DEFAULT 1,
+                settings_json TEXT,
                 created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
             )
             "#,
@@ -63,16 +126,27 @@ impl Database {
         .execute(&self.pool)
         .await?;
 
+        // Adapters report whole days again on every poll; this turns repeat
+        // reports of the same datapoint into an update.
+        if !self.has_index("idx_metrics_series").await? {
+            self.remove_duplicate_metrics().await?;
+        }
         sqlx::query(
             r#"
-            CREATE TABLE IF NOT EXISTS budgets (
-                id TEXT PRIMARY KEY,
-                provider_id TEXT NOT NULL,
-                period TEXT NOT NULL,
-                soft_limit REAL,
-                hard_limit REAL,
-                notes TEXT,
-                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_metrics_series
+            ON metrics(provider_id, metric_type, timestamp, dimensions)
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS backfill_checkpoints (
+                provider_id TEXT PRIMARY KEY,
+                fetched_back_to TIMESTAMP NOT NULL,
+                target TIMESTAMP NOT NULL,
+                completed_at TIMESTAMP,
                 updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
                 FOREIGN KEY (provider_id) REFERENCES providers(id)
             )
@@ -81,15 +155,13 @@ impl Database {
         .execute(&self.pool)
         .await?;
 
+        // The last successful poll of each provider, shown while offline.
         sqlx::query(
             r#"
-            CREATE TABLE IF NOT EXISTS alerts (
-                id TEXT PRIMARY KEY,
-                provider_id TEXT NOT NULL,
-                rule_json TEXT NOT NULL,
-                last_fired_at TIMESTAMP,
-                status TEXT NOT NULL DEFAULT 'active',
-                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            CREATE TABLE IF NOT EXISTS usage_snapshots (
+                provider_id TEXT PRIMARY KEY,
+                usage_json TEXT NOT NULL,
+                synced_at TIMESTAMP NOT NULL,
                 FOREIGN KEY (provider_id) REFERENCES providers(id)
             )
             "#,
@@ -99,29 +171,307 @@ impl Database {
 
         sqlx::query(
             r#"
-            CREATE TABLE IF NOT EXISTS events (
+            CREATE TABLE IF NOT EXISTS provider_groups (
                 id TEXT PRIMARY KEY,
-                provider_id TEXT NOT NULL,
-                timestamp TIMESTAMP NOT NULL,
-                kind TEXT NOT NULL,
-                payload TEXT,
-                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-                FOREIGN KEY (provider_id) REFERENCES providers(id)
+                name TEXT NOT NULL,
+                all_providers BOOLEAN NOT NULL DEFAULT 0,
+                provider_ids_json TEXT NOT NULL,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(BUDGETS_TABLE).execute(&self.pool).await?;
+
+        sqlx::query(ALERTS_TABLE).execute(&self.pool).await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS notification_channels (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                config_json TEXT NOT NULL,
+                enabled BOOLEAN DEFAULT 1,
+                last_digest_at TIMESTAMP,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(EVENTS_TABLE).execute(&self.pool).await?;
+
+        self.upgrade_tables().await?;
+
+        Ok(())
+    }
+
+    /// Brings tables created by earlier versions up to date; `CREATE TABLE IF
+    /// NOT EXISTS` leaves them as they were.
+    async fn upgrade_tables(&self) -> Result<()> {
+        for (table, definition) in [
+            ("budgets", BUDGETS_TABLE),
+            ("alerts", ALERTS_TABLE),
+            ("events", EVENTS_TABLE),
+        ] {
+            if self.is_not_null(table, "provider_id").await? {
+                self.rebuild_table(table, definition).await?;
+            }
+        }
+
+        self.add_missing_columns("providers", &[("settings_json", "TEXT")])
+            .await?;
+        self.add_missing_columns(
+            "budgets",
+            &[("group_id", "TEXT REFERENCES provider_groups(id)")],
+        )
+        .await?;
+        self.add_missing_columns(
+            "alerts",
+            &[
+                ("group_id", "TEXT REFERENCES provider_groups(id)"),
+                ("notify_desktop", "BOOLEAN NOT NULL DEFAULT 1"),
+                ("snoozed_until", "TIMESTAMP"),
+                ("channels_json", "TEXT"),
+            ],
+        )
+        .await?;
+        self.add_missing_columns("notification_channels", &[("last_digest_at", "TIMESTAMP")])
+            .await?;
+        self.add_missing_columns(
+            "events",
+            &[("group_id", "TEXT REFERENCES provider_groups(id)")],
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn add_missing_columns(&self, table: &str, columns: &[(&str, &str)]) -> Result<()> {
+        for (name, definition) in columns {
+            let existing: Option<(String,)> =
+                sqlx::query_as("SELECT name FROM pragma_table_info(?) WHERE name = ?")
+                    .bind(table)
+                    .bind(name)
+                    .fetch_optional(&self.pool)
+                    .await?;
+            if existing.is_none() {
+                sqlx::query(&format!(
+                    "ALTER TABLE {} ADD COLUMN {} {}",
+                    table, name, definition
+                ))
+                .execute(&self.pool)
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn is_not_null(&self, table: &str, column: &str) -> Result<bool> {
+        let row: Option<(bool,)> =
+            sqlx::query_as(r#"SELECT "notnull" FROM pragma_table_info(?) WHERE name = ?"#)
+                .bind(table)
+                .bind(column)
+                .fetch_optional(&self.pool)
+                .await?;
+
+        Ok(row.is_some_and(|(not_null,)| not_null))
+    }
+
+    /// Recreates `table` from `definition`, keeping its rows and the columns
+    /// both versions have. SQLite cannot relax a constraint in place.
+    async fn rebuild_table(&self, table: &str, definition: &str) -> Result<()> {
+        let old_table = format!("{}_old", table);
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(&format!("ALTER TABLE {} RENAME TO {}", table, old_table))
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(definition).execute(&mut *tx).await?;
+
+        let columns: Vec<(String,)> = sqlx::query_as(
+            "SELECT name FROM pragma_table_info(?) WHERE name IN (SELECT name FROM pragma_table_info(?))",
+        )
+        .bind(table)
+        .bind(&old_table)
+        .fetch_all(&mut *tx)
+        .await?;
+        let columns = columns
+            .into_iter()
+            .map(|(name,)| name)
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        sqlx::query(&format!(
+            "INSERT INTO {} ({}) SELECT {} FROM {}",
+            table, columns, columns, old_table
+        ))
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(&format!("DROP TABLE {}", old_table))
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn has_index(&self, name: &str) -> Result<bool> {
+        let row: Option<(String,)> =
+            sqlx::query_as("SELECT name FROM sqlite_master WHERE type = 'index' AND name = ?")
+                .bind(name)
+                .fetch_optional(&self.pool)
+                .await?;
+
+        Ok(row.is_some())
+    }
+
+    /// Earlier versions inserted every report of a datapoint as a new row,
+    /// with dimensions in no particular order. Sorts the dimensions the way
+    /// `insert_metric` does now, then keeps the latest row of each series.
+    async fn remove_duplicate_metrics(&self) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            r#"
+            UPDATE metrics
+            SET dimensions = (
+                SELECT json_group_object(key, value)
+                FROM (SELECT key, value FROM json_each(metrics.dimensions) ORDER BY key)
+            )
+            WHERE json_valid(dimensions) AND json_type(dimensions) = 'object'
+            "#,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            r#"
+            DELETE FROM metrics
+            WHERE rowid NOT IN (
+                SELECT MAX(rowid) FROM metrics
+                GROUP BY provider_id, metric_type, timestamp, dimensions
             )
             "#,
         )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn insert_provider(&self, provider: &crate::models::Provider) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO providers (id, name, provider_type, api_key_ref, enabled, settings_json, created_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&provider.id)
+        .bind(&provider.name)
+        .bind(serde_json::to_string(&provider.provider_type)?)
+        .bind(&provider.api_key_ref)
+        .bind(provider.enabled)
+        .bind(serde_json::to_string(&provider.settings)?)
+        .bind(provider.created_at)
         .execute(&self.pool)
         .await?;
 
         Ok(())
     }
 
+    pub async fn get_providers(&self) -> Result<Vec<crate::models::Provider>> {
+        let rows = sqlx::query_as::<_, ProviderRow>(
+            r#"
+            SELECT id, name, provider_type, api_key_ref, enabled, settings_json, created_at
+            FROM providers
+            ORDER BY created_at
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(|row| row.into_provider()).collect()
+    }
+
+    pub async fn set_provider_enabled(&self, provider_id: &str, enabled: bool) -> Result<()> {
+        sqlx::query("UPDATE providers SET enabled = ? WHERE id = ?")
+            .bind(enabled)
+            .bind(provider_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_provider(&self, provider_id: &str) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM metrics WHERE provider_id = ?")
+            .bind(provider_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("DELETE FROM backfill_checkpoints WHERE provider_id = ?")
+            .bind(provider_id)
+            .execute(&mut *tx)
+            .await?;
+
+        // Events are the alert delivery log; they outlive their provider.
+        sqlx::query("UPDATE events SET provider_id = NULL WHERE provider_id = ?")
+            .bind(provider_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("DELETE FROM alerts WHERE provider_id = ?")
+            .bind(provider_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("DELETE FROM usage_snapshots WHERE provider_id = ?")
+            .bind(provider_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("DELETE FROM budgets WHERE provider_id = ?")
+            .bind(provider_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query(
+            r#"
+            UPDATE provider_groups
+            SET provider_ids_json = (
+                SELECT json_group_array(value) FROM json_each(provider_ids_json) WHERE value != ?
+            )
+            "#,
+        )
+        .bind(provider_id)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query("DELETE FROM providers WHERE id = ?")
+            .bind(provider_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
     pub async fn insert_metric(&self, metric: &crate::models::Metric) -> Result<()> {
-        let dimensions_json = serde_json::to_string(&metric.dimensions)?;
+        // Sorted so the same dimensions always serialize identically.
+        let dimensions: std::collections::BTreeMap<_, _> = metric.dimensions.iter().collect();
+        let dimensions_json = serde_json::to_string(&dimensions)?;
 
         sqlx::query(
             r#"
-            INSERT INTO metrics (id, provider_id, metric_type, value, unit, timestamp, dimensions)
+            INSERT OR REPLACE INTO metrics (id, provider_id, metric_type, value, unit, timestamp, dimensions)
             VALUES (?, ?, ?, ?, ?, ?, ?)
             "#,
         )
@@ -141,7 +491,7 @@ impl Database {
     pub async fn get_recent_metrics(
         &self,
         provider_id: &str,
-        hours: i64
+        hours: i64,
     ) -> Result<Vec<crate::models::Metric>> {
         use chrono::{Duration, Utc};
 
@@ -160,66 +510,1116 @@ impl Database {
         .fetch_all(&self.pool)
         .await?;
 
-        let metrics = rows.into_iter()
+        let metrics = rows
+            .into_iter()
             .map(|row| row.into_metric())
             .collect::<Result<Vec<_>>>()?;
 
         Ok(metrics)
     }
 
-    pub async fn cleanup_old_metrics(&self, days: i64) -> Result<()> {
-        use chrono::{Duration, Utc};
+    pub async fn get_backfill_checkpoint(
+        &self,
+        provider_id: &str,
+    ) -> Result<Option<BackfillCheckpoint>> {
+        let checkpoint = sqlx::query_as::<_, BackfillCheckpoint>(
+            r#"
+            SELECT provider_id, fetched_back_to, target, completed_at
+            FROM backfill_checkpoints
+            WHERE provider_id = ?
+            "#,
+        )
+        .bind(provider_id)
+        .fetch_optional(&self.pool)
+        .await?;
 
-        let cutoff = Utc::now() - Duration::days(days);
+        Ok(checkpoint)
+    }
 
-        sqlx::query("DELETE FROM metrics WHERE timestamp < ?")
-            .bind(cutoff)
-            .execute(&self.pool)
-            .await?;
+    pub async fn save_backfill_checkpoint(&self, checkpoint: &BackfillCheckpoint) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT OR REPLACE INTO backfill_checkpoints (provider_id, fetched_back_to, target, completed_at, updated_at)
+            VALUES (?, ?, ?, ?, CURRENT_TIMESTAMP)
+            "#,
+        )
+        .bind(&checkpoint.provider_id)
+        .bind(checkpoint.fetched_back_to)
+        .bind(checkpoint.target)
+        .bind(checkpoint.completed_at)
+        .execute(&self.pool)
+        .await?;
 
         Ok(())
     }
-}
 
-#[derive(sqlx::FromRow)]
-struct MetricRow {
-    id: String,
-    provider_id: String,
-    metric_type: String,
-    value: f64,
-    unit: String,
-    timestamp: chrono::DateTime<chrono::Utc>,
-    dimensions: Option<String>,
-}
+    pub async fn save_usage_snapshot(&self, usage: &crate::models::ProviderUsage) -> Result<()> {
+        let synced_at = usage.last_synced_at.unwrap_or_else(chrono::Utc::now);
 
-impl MetricRow {
-    fn into_metric(self) -> Result<crate::models::Metric> {
-        use crate::models::MetricType;
-        use std::collections::HashMap;
+        sqlx::query(
+            r#"
+            INSERT OR REPLACE INTO usage_snapshots (provider_id, usage_json, synced_at)
+            VALUES (?, ?, ?)
+            "#,
+        )
+        .bind(&usage.provider.id)
+        .bind(serde_json::to_string(usage)?)
+        .bind(synced_at)
+        .execute(&self.pool)
+        .await?;
 
-        let metric_type = match self.metric_type.as_str() {
-            "TokensIn" => MetricType::TokensIn,
-            "TokensOut" => MetricType::TokensOut,
-            "TokensCached" => MetricType::TokensCached,
-            "CostUsd" => MetricType::CostUsd,
-            "CreditsRemaining" => MetricType::CreditsRemaining,
-            "Balance" => MetricType::Balance,
-            _ => MetricType::CostUsd,
-        };
+        Ok(())
+    }
 
-        let dimensions = self.dimensions
-            .map(|json| serde_json::from_str(&json))
-            .transpose()?
-            .unwrap_or_else(HashMap::new);
+    /// The last successful poll of every provider that has one, keyed by
+    /// provider id.
+    pub async fn get_usage_snapshots(
+        &self,
+    ) -> Result<std::collections::HashMap<String, crate::models::ProviderUsage>> {
+        let rows: Vec<(String, String, chrono::DateTime<chrono::Utc>)> =
+            sqlx::query_as("SELECT provider_id, usage_json, synced_at FROM usage_snapshots")
+                .fetch_all(&self.pool)
+                .await?;
 
-        Ok(crate::models::Metric {
-            id: self.id,
-            provider_id: self.provider_id,
-            metric_type,
-            value: self.value,
-            unit: self.unit,
-            timestamp: self.timestamp,
-            dimensions,
-        })
+        rows.into_iter()
+            .map(|(provider_id, json, synced_at)| {
+                let mut usage: crate::models::ProviderUsage = serde_json::from_str(&json)?;
+                usage.last_synced_at = Some(synced_at);
+                Ok((provider_id, usage))
+            })
+            .collect()
+    }
+
+    pub async fn insert_alert(&self, alert: &crate::models::Alert) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO alerts (id, provider_id, group_id, rule_json, last_fired_at, status, notify_desktop, snoozed_until, channels_json)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&alert.id)
+        .bind(&alert.provider_id)
+        .bind(&alert.group_id)
+        .bind(serde_json::to_string(&alert.rule)?)
+        .bind(alert.last_fired_at)
+        .bind(enum_to_sql(&alert.status)?)
+        .bind(alert.notify_desktop)
+        .bind(alert.snoozed_until)
+        .bind(serde_json::to_string(&alert.channel_ids)?)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Saves everything about an alert except its rule and what it watches.
+    pub async fn update_alert(&self, alert: &crate::models::Alert) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE alerts
+            SET last_fired_at = ?, status = ?, notify_desktop = ?, snoozed_until = ?, channels_json = ?
+            WHERE id = ?
+            "#,
+        )
+        .bind(alert.last_fired_at)
+        .bind(enum_to_sql(&alert.status)?)
+        .bind(alert.notify_desktop)
+        .bind(alert.snoozed_until)
+        .bind(serde_json::to_string(&alert.channel_ids)?)
+        .bind(&alert.id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_alert(&self, alert_id: &str) -> Result<Option<crate::models::Alert>> {
+        let row = sqlx::query_as::<_, AlertRow>(
+            r#"
+            SELECT id, provider_id, group_id, rule_json, last_fired_at, status, notify_desktop, snoozed_until, channels_json
+            FROM alerts
+            WHERE id = ?
+            "#,
+        )
+        .bind(alert_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|row| row.into_alert()).transpose()
     }
-}
\ No newline at end of file
+
+    /// Alerts, optionally only a provider's or a group's.
+    pub async fn get_alerts(
+        &self,
+        provider_id: Option<&str>,
+        group_id: Option<&str>,
+    ) -> Result<Vec<crate::models::Alert>> {
+        let rows = sqlx::query_as::<_, AlertRow>(
+            r#"
+            SELECT id, provider_id, group_id, rule_json, last_fired_at, status, notify_desktop, snoozed_until, channels_json
+            FROM alerts
+            WHERE (? IS NULL OR provider_id = ?) AND (? IS NULL OR group_id = ?)
+            ORDER BY created_at
+            "#,
+        )
+        .bind(provider_id)
+        .bind(provider_id)
+        .bind(group_id)
+        .bind(group_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(|row| row.into_alert()).collect()
+    }
+
+    /// Saves the provider's or group's budget for `budget.period`, replacing
+    /// any existing one; each has at most one budget per period.
+    pub async fn upsert_budget(&self, budget: &crate::models::Budget) -> Result<()> {
+        let owner_id = budget
+            .provider_id
+            .as_deref()
+            .or(budget.group_id.as_deref())
+            .unwrap_or_default();
+
+        sqlx::query(
+            r#"
+            INSERT OR REPLACE INTO budgets (id, provider_id, group_id, period, soft_limit, hard_limit, notes, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, CURRENT_TIMESTAMP)
+            "#,
+        )
+        .bind(budget_id(owner_id, budget.period)?)
+        .bind(&budget.provider_id)
+        .bind(&budget.group_id)
+        .bind(enum_to_sql(&budget.period)?)
+        .bind(budget.soft_limit)
+        .bind(budget.hard_limit)
+        .bind(&budget.notes)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// `owner_id` is a provider or group id.
+    pub async fn get_budget(
+        &self,
+        owner_id: &str,
+        period: crate::models::BudgetPeriod,
+    ) -> Result<Option<crate::models::Budget>> {
+        let row = sqlx::query_as::<_, BudgetRow>(
+            r#"
+            SELECT provider_id, group_id, period, soft_limit, hard_limit, notes
+            FROM budgets
+            WHERE id = ?
+            "#,
+        )
+        .bind(budget_id(owner_id, period)?)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|row| row.into_budget()).transpose()
+    }
+
+    pub async fn get_budgets(
+        &self,
+        provider_id: Option<&str>,
+        group_id: Option<&str>,
+    ) -> Result<Vec<crate::models::Budget>> {
+        let rows = sqlx::query_as::<_, BudgetRow>(
+            r#"
+            SELECT provider_id, group_id, period, soft_limit, hard_limit, notes
+            FROM budgets
+            WHERE (? IS NULL OR provider_id = ?) AND (? IS NULL OR group_id = ?)
+            ORDER BY created_at
+            "#,
+        )
+        .bind(provider_id)
+        .bind(provider_id)
+        .bind(group_id)
+        .bind(group_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(|row| row.into_budget()).collect()
+    }
+
+    /// `owner_id` is a provider or group id.
+    pub async fn delete_budget(
+        &self,
+        owner_id: &str,
+        period: crate::models::BudgetPeriod,
+    ) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM budgets WHERE id = ?")
+            .bind(budget_id(owner_id, period)?)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn insert_group(&self, group: &crate::models::ProviderGroup) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO provider_groups (id, name, all_providers, provider_ids_json, created_at)
+            VALUES (?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&group.id)
+        .bind(&group.name)
+        .bind(group.all_providers)
+        .bind(serde_json::to_string(&group.provider_ids)?)
+        .bind(group.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Saves a group's name and members.
+    pub async fn update_group(&self, group: &crate::models::ProviderGroup) -> Result<()> {
+        sqlx::query("UPDATE provider_groups SET name = ?, all_providers = ?, provider_ids_json = ? WHERE id = ?")
+            .bind(&group.name)
+            .bind(group.all_providers)
+            .bind(serde_json::to_string(&group.provider_ids)?)
+            .bind(&group.id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_groups(&self) -> Result<Vec<crate::models::ProviderGroup>> {
+        let rows = sqlx::query_as::<_, GroupRow>(
+            r#"
+            SELECT id, name, all_providers, provider_ids_json, created_at
+            FROM provider_groups
+            ORDER BY created_at
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(|row| row.into_group()).collect()
+    }
+
+    pub async fn get_group(&self, group_id: &str) -> Result<Option<crate::models::ProviderGroup>> {
+        let row = sqlx::query_as::<_, GroupRow>(
+            r#"
+            SELECT id, name, all_providers, provider_ids_json, created_at
+            FROM provider_groups
+            WHERE id = ?
+            "#,
+        )
+        .bind(group_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|row| row.into_group()).transpose()
+    }
+
+    /// Deletes a group with its budgets and alerts. Their events stay in the
+    /// delivery log without the group. Returns whether it existed.
+    pub async fn delete_group(&self, group_id: &str) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("UPDATE events SET group_id = NULL WHERE group_id = ?")
+            .bind(group_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("DELETE FROM alerts WHERE group_id = ?")
+            .bind(group_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("DELETE FROM budgets WHERE group_id = ?")
+            .bind(group_id)
+            .execute(&mut *tx)
+            .await?;
+
+        let result = sqlx::query("DELETE FROM provider_groups WHERE id = ?")
+            .bind(group_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn insert_channel(&self, channel: &crate::models::NotificationChannel) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO notification_channels (id, name, config_json, enabled, created_at)
+            VALUES (?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&channel.id)
+        .bind(&channel.name)
+        .bind(serde_json::to_string(&channel.config)?)
+        .bind(channel.enabled)
+        .bind(channel.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_channels(&self) -> Result<Vec<crate::models::NotificationChannel>> {
+        let rows = sqlx::query_as::<_, ChannelRow>(
+            r#"
+            SELECT id, name, config_json, enabled, created_at
+            FROM notification_channels
+            ORDER BY created_at
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(|row| row.into_channel()).collect()
+    }
+
+    pub async fn get_channel(
+        &self,
+        channel_id: &str,
+    ) -> Result<Option<crate::models::NotificationChannel>> {
+        let row = sqlx::query_as::<_, ChannelRow>(
+            r#"
+            SELECT id, name, config_json, enabled, created_at
+            FROM notification_channels
+            WHERE id = ?
+            "#,
+        )
+        .bind(channel_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|row| row.into_channel()).transpose()
+    }
+
+    pub async fn delete_channel(&self, channel_id: &str) -> Result<()> {
+        sqlx::query("DELETE FROM notification_channels WHERE id = ?")
+            .bind(channel_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_last_digest_at(
+        &self,
+        channel_id: &str,
+    ) -> Result<Option<chrono::DateTime<chrono::Utc>>> {
+        let row: Option<(Option<chrono::DateTime<chrono::Utc>>,)> =
+            sqlx::query_as("SELECT last_digest_at FROM notification_channels WHERE id = ?")
+                .bind(channel_id)
+                .fetch_optional(&self.pool)
+                .await?;
+
+        Ok(row.and_then(|(at,)| at))
+    }
+
+    pub async fn set_last_digest_at(
+        &self,
+        channel_id: &str,
+        at: chrono::DateTime<chrono::Utc>,
+    ) -> Result<()> {
+        sqlx::query("UPDATE notification_channels SET last_digest_at = ? WHERE id = ?")
+            .bind(at)
+            .bind(channel_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// USD spend per provider id between `from` and `to`. Cost reported in
+    /// credits is left out: those metrics are running totals, not spend
+    /// within a period.
+    pub async fn get_spend_by_provider(
+        &self,
+        from: chrono::DateTime<chrono::Utc>,
+        to: chrono::DateTime<chrono::Utc>,
+    ) -> Result<std::collections::HashMap<String, f64>> {
+        let rows: Vec<(String, f64)> = sqlx::query_as(
+            r#"
+            SELECT provider_id, SUM(value)
+            FROM metrics
+            WHERE metric_type = 'CostUsd' AND unit = 'usd' AND timestamp >= ? AND timestamp < ?
+            GROUP BY provider_id
+            "#,
+        )
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().collect())
+    }
+
+    /// USD spend per UTC day for one provider, oldest first. Days without
+    /// spend are missing.
+    pub async fn get_daily_spend(
+        &self,
+        provider_id: &str,
+        from: chrono::DateTime<chrono::Utc>,
+        to: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Vec<(chrono::NaiveDate, f64)>> {
+        let rows: Vec<(String, f64)> = sqlx::query_as(
+            r#"
+            SELECT date(timestamp), SUM(value)
+            FROM metrics
+            WHERE provider_id = ? AND metric_type = 'CostUsd' AND unit = 'usd' AND timestamp >= ? AND timestamp < ?
+            GROUP BY date(timestamp)
+            ORDER BY date(timestamp)
+            "#,
+        )
+        .bind(provider_id)
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|(day, spend)| Ok((day.parse()?, spend)))
+            .collect()
+    }
+
+    /// USD spend and tokens per model for one provider between `from` and
+    /// `to`, as `(model, cost, tokens)`.
+    pub async fn get_usage_by_model(
+        &self,
+        provider_id: &str,
+        from: chrono::DateTime<chrono::Utc>,
+        to: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Vec<(Option<String>, f64, f64)>> {
+        let rows = sqlx::query_as(
+            r#"
+            SELECT
+                json_extract(dimensions, '$.model') AS model,
+                SUM(CASE WHEN metric_type = 'CostUsd' AND unit = 'usd' THEN value ELSE 0.0 END),
+                SUM(CASE WHEN metric_type IN ('TokensIn', 'TokensOut') THEN value ELSE 0.0 END)
+            FROM metrics
+            WHERE provider_id = ? AND timestamp >= ? AND timestamp < ?
+            GROUP BY model
+            "#,
+        )
+        .bind(provider_id)
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Growth in spend and tokens recorded by polls, per UTC hour and model
+    /// across the providers, as `(hour, model, cost, tokens)`. Hours without
+    /// growth are missing.
+    pub async fn get_hourly_usage(
+        &self,
+        provider_ids: &[String],
+        from: chrono::DateTime<chrono::Utc>,
+        to: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Vec<(chrono::DateTime<chrono::Utc>, Option<String>, f64, f64)>> {
+        let rows: Vec<(String, Option<String>, f64, f64)> = sqlx::query_as(
+            r#"
+            SELECT
+                strftime('%Y-%m-%d %H:00:00', timestamp) AS hour,
+                json_extract(dimensions, '$.model') AS model,
+                SUM(CASE WHEN metric_type = 'SpendDelta' THEN value ELSE 0.0 END),
+                SUM(CASE WHEN metric_type = 'TokensDelta' THEN value ELSE 0.0 END)
+            FROM metrics
+            WHERE provider_id IN (SELECT value FROM json_each(?))
+              AND metric_type IN ('SpendDelta', 'TokensDelta') AND timestamp >= ? AND timestamp < ?
+            GROUP BY hour, model
+            ORDER BY hour
+            "#,
+        )
+        .bind(serde_json::to_string(provider_ids)?)
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|(hour, model, cost, tokens)| {
+                let hour =
+                    chrono::NaiveDateTime::parse_from_str(&hour, "%Y-%m-%d %H:%M:%S")?.and_utc();
+                Ok((hour, model, cost, tokens))
+            })
+            .collect()
+    }
+
+    /// Total of the given metric types across the providers between `from`
+    /// and `to`, keeping rows whose dimensions match every `(key, value)` filter.
+    pub async fn sum_metrics(
+        &self,
+        provider_ids: &[String],
+        metric_types: &[&str],
+        unit: Option<&str>,
+        filters: &[(String, String)],
+        from: chrono::DateTime<chrono::Utc>,
+        to: chrono::DateTime<chrono::Utc>,
+    ) -> Result<f64> {
+        let mut sql = format!(
+            "SELECT COALESCE(SUM(value), 0.0) FROM metrics \
+             WHERE provider_id IN (SELECT value FROM json_each(?)) \
+             AND metric_type IN ({}) AND timestamp >= ? AND timestamp < ?",
+            vec!["?"; metric_types.len()].join(", ")
+        );
+        if unit.is_some() {
+            sql.push_str(" AND unit = ?");
+        }
+        for _ in filters {
+            sql.push_str(" AND json_extract(dimensions, ?) = ?");
+        }
+
+        let mut query =
+            sqlx::query_scalar::<_, f64>(&sql).bind(serde_json::to_string(provider_ids)?);
+        for metric_type in metric_types {
+            query = query.bind(*metric_type);
+        }
+        query = query.bind(from).bind(to);
+        if let Some(unit) = unit {
+            query = query.bind(unit);
+        }
+        for (key, value) in filters {
+            query = query.bind(format!("$.{}", key)).bind(value);
+        }
+
+        Ok(query.fetch_one(&self.pool).await?)
+    }
+
+    /// The most recent value before `before` of any of the metric types,
+    /// with the same dimension filters as `sum_metrics`.
+    pub async fn last_metric(
+        &self,
+        provider_id: &str,
+        metric_types: &[&str],
+        filters: &[(String, String)],
+        before: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Option<f64>> {
+        let mut sql = format!(
+            "SELECT value FROM metrics WHERE provider_id = ? AND metric_type IN ({}) AND timestamp <= ?",
+            vec!["?"; metric_types.len()].join(", ")
+        );
+        for _ in filters {
+            sql.push_str(" AND json_extract(dimensions, ?) = ?");
+        }
+        sql.push_str(" ORDER BY timestamp DESC LIMIT 1");
+
+        let mut query = sqlx::query_scalar::<_, f64>(&sql).bind(provider_id);
+        for metric_type in metric_types {
+            query = query.bind(*metric_type);
+        }
+        query = query.bind(before);
+        for (key, value) in filters {
+            query = query.bind(format!("$.{}", key)).bind(value);
+        }
+
+        Ok(query.fetch_optional(&self.pool).await?)
+    }
+
+    /// Every recorded value of one metric for a provider, oldest first.
+    pub async fn get_metric_values(
+        &self,
+        provider_id: &str,
+        metric_type: &crate::models::MetricType,
+        from: chrono::DateTime<chrono::Utc>,
+        to: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Vec<(chrono::DateTime<chrono::Utc>, f64)>> {
+        let rows = sqlx::query_as(
+            r#"
+            SELECT timestamp, value
+            FROM metrics
+            WHERE provider_id = ? AND metric_type = ? AND timestamp >= ? AND timestamp < ?
+            ORDER BY timestamp
+            "#,
+        )
+        .bind(provider_id)
+        .bind(format!("{:?}", metric_type))
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    pub async fn get_events(
+        &self,
+        kind: &str,
+        from: chrono::DateTime<chrono::Utc>,
+        to: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Vec<EventRecord>> {
+        let events = sqlx::query_as::<_, EventRecord>(
+            r#"
+            SELECT id, provider_id, group_id, timestamp, kind, payload
+            FROM events
+            WHERE kind = ? AND timestamp >= ? AND timestamp < ?
+            ORDER BY timestamp
+            "#,
+        )
+        .bind(kind)
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(events)
+    }
+
+    /// Appends to the audit trail of alerts fired and delivered, under the
+    /// alert's provider or group.
+    pub async fn insert_event(
+        &self,
+        provider_id: Option<&str>,
+        group_id: Option<&str>,
+        kind: &str,
+        payload: &impl serde::Serialize,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO events (id, provider_id, group_id, timestamp, kind, payload)
+            VALUES (?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(uuid::Uuid::new_v4().to_string())
+        .bind(provider_id)
+        .bind(group_id)
+        .bind(chrono::Utc::now())
+        .bind(kind)
+        .bind(serde_json::to_string(payload)?)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn cleanup_old_metrics(&self, days: i64) -> Result<()> {
+        use chrono::{Duration, Utc};
+
+        let cutoff = Utc::now() - Duration::days(days);
+
+        sqlx::query("DELETE FROM metrics WHERE timestamp < ?")
+            .bind(cutoff)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// How far back a provider's history has been fetched, so an interrupted
+/// backfill resumes where it stopped.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct BackfillCheckpoint {
+    pub provider_id: String,
+    pub fetched_back_to: chrono::DateTime<chrono::Utc>,
+    pub target: chrono::DateTime<chrono::Utc>,
+    pub completed_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// A row of the `events` audit trail. `payload` is JSON whose shape depends
+/// on `kind`.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct EventRecord {
+    pub id: String,
+    pub provider_id: Option<String>,
+    pub group_id: Option<String>,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub kind: String,
+    pub payload: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct ProviderRow {
+    id: String,
+    name: String,
+    provider_type: String,
+    api_key_ref: Option<String>,
+    enabled: bool,
+    settings_json: Option<String>,
+    created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl ProviderRow {
+    fn into_provider(self) -> Result<crate::models::Provider> {
+        let settings = self
+            .settings_json
+            .map(|json| serde_json::from_str(&json))
+            .transpose()?
+            .unwrap_or_default();
+
+        Ok(crate::models::Provider {
+            id: self.id,
+            name: self.name,
+            provider_type: serde_json::from_str(&self.provider_type)?,
+            api_key_ref: self.api_key_ref,
+            enabled: self.enabled,
+            created_at: self.created_at,
+            settings,
+        })
+    }
+}
+
+// Unit enums are stored bare (`active`, `monthly`) to match the column defaults.
+fn enum_to_sql(value: &impl serde::Serialize) -> Result<String> {
+    Ok(serde_json::to_value(value)?
+        .as_str()
+        .unwrap_or_default()
+        .to_string())
+}
+
+fn enum_from_sql<T: serde::de::DeserializeOwned>(value: String) -> Result<T> {
+    Ok(serde_json::from_value(serde_json::Value::String(value))?)
+}
+
+// Provider and group ids never collide, so either keys its budgets.
+fn budget_id(owner_id: &str, period: crate::models::BudgetPeriod) -> Result<String> {
+    Ok(format!("{}:{}", owner_id, enum_to_sql(&period)?))
+}
+
+#[derive(sqlx::FromRow)]
+struct BudgetRow {
+    provider_id: Option<String>,
+    group_id: Option<String>,
+    period: String,
+    soft_limit: Option<f64>,
+    hard_limit: Option<f64>,
+    notes: Option<String>,
+}
+
+impl BudgetRow {
+    fn into_budget(self) -> Result<crate::models::Budget> {
+        Ok(crate::models::Budget {
+            provider_id: self.provider_id,
+            group_id: self.group_id,
+            period: enum_from_sql(self.period)?,
+            soft_limit: self.soft_limit,
+            hard_limit: self.hard_limit,
+            notes: self.notes,
+        })
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct AlertRow {
+    id: String,
+    provider_id: Option<String>,
+    group_id: Option<String>,
+    rule_json: String,
+    last_fired_at: Option<chrono::DateTime<chrono::Utc>>,
+    status: String,
+    notify_desktop: bool,
+    snoozed_until: Option<chrono::DateTime<chrono::Utc>>,
+    channels_json: Option<String>,
+}
+
+impl AlertRow {
+    fn into_alert(self) -> Result<crate::models::Alert> {
+        Ok(crate::models::Alert {
+            id: self.id,
+            provider_id: self.provider_id,
+            group_id: self.group_id,
+            rule: serde_json::from_str(&self.rule_json)?,
+            last_fired_at: self.last_fired_at,
+            status: enum_from_sql(self.status)?,
+            notify_desktop: self.notify_desktop,
+            snoozed_until: self.snoozed_until,
+            channel_ids: self
+                .channels_json
+                .map(|json| serde_json::from_str(&json))
+                .transpose()?
+                .unwrap_or_default(),
+        })
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct GroupRow {
+    id: String,
+    name: String,
+    all_providers: bool,
+    provider_ids_json: String,
+    created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl GroupRow {
+    fn into_group(self) -> Result<crate::models::ProviderGroup> {
+        Ok(crate::models::ProviderGroup {
+            id: self.id,
+            name: self.name,
+            all_providers: self.all_providers,
+            provider_ids: serde_json::from_str(&self.provider_ids_json)?,
+            created_at: self.created_at,
+        })
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct ChannelRow {
+    id: String,
+    name: String,
+    config_json: String,
+    enabled: bool,
+    created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl ChannelRow {
+    fn into_channel(self) -> Result<crate::models::NotificationChannel> {
+        Ok(crate::models::NotificationChannel {
+            id: self.id,
+            name: self.name,
+            config: serde_json::from_str(&self.config_json)?,
+            enabled: self.enabled,
+            created_at: self.created_at,
+        })
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct MetricRow {
+    id: String,
+    provider_id: String,
+    metric_type: String,
+    value: f64,
+    unit: String,
+    timestamp: chrono::DateTime<chrono::Utc>,
+    dimensions: Option<String>,
+}
+
+impl MetricRow {
+    fn into_metric(self) -> Result<crate::models::Metric> {
+        use crate::models::MetricType;
+        use std::collections::HashMap;
+
+        let metric_type = match self.metric_type.as_str() {
+            "TokensIn" => MetricType::TokensIn,
+            "TokensOut" => MetricType::TokensOut,
+            "TokensCached" => MetricType::TokensCached,
+            "CostUsd" => MetricType::CostUsd,
+            "CreditsRemaining" => MetricType::CreditsRemaining,
+            "Balance" => MetricType::Balance,
+            "TpmRemaining" => MetricType::TpmRemaining,
+            "Requests" => MetricType::Requests,
+            "ModelLoaded" => MetricType::ModelLoaded,
+            "SpendDelta" => MetricType::SpendDelta,
+            "TokensDelta" => MetricType::TokensDelta,
+            _ => MetricType::CostUsd,
+        };
+
+        let dimensions = self
+            .dimensions
+            .map(|json| serde_json::from_str(&json))
+            .transpose()?
+            .unwrap_or_else(HashMap::new);
+
+        Ok(crate::models::Metric {
+            id: self.id,
+            provider_id: self.provider_id,
+            metric_type,
+            value: self.value,
+            unit: self.unit,
+            timestamp: self.timestamp,
+            dimensions,
+        })
+    }
+}
+
+/// Every connection to `sqlite::memory:` opens its own database, so the pool
+/// keeps exactly one and never lets it go.
+#[cfg(test)]
+async fn memory_pool() -> Result<Pool<Sqlite>> {
+    Ok(SqlitePoolOptions::new()
+        .max_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect("sqlite::memory:")
+        .await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{AlertRule, BudgetPeriod, ProviderGroup};
+    use chrono::Utc;
+
+    /// The tables as the first release created them.
+    const FIRST_RELEASE: &[&str] = &[
+        "CREATE TABLE providers (id TEXT PRIMARY KEY, name TEXT NOT NULL, provider_type TEXT NOT NULL, \
+         api_key_ref TEXT, enabled BOOLEAN DEFAULT 1, created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP)",
+        "CREATE TABLE metrics (id TEXT PRIMARY KEY, provider_id TEXT NOT NULL, metric_type TEXT NOT NULL, \
+         value REAL NOT NULL, unit TEXT NOT NULL, timestamp TIMESTAMP NOT NULL, dimensions TEXT, \
+         created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP, FOREIGN KEY (provider_id) REFERENCES providers(id))",
+        "CREATE TABLE budgets (id TEXT PRIMARY KEY, provider_id TEXT NOT NULL, period TEXT NOT NULL, \
+         soft_limit REAL, hard_limit REAL, notes TEXT, created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP, \
+         updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP, FOREIGN KEY (provider_id) REFERENCES providers(id))",
+        "CREATE TABLE alerts (id TEXT PRIMARY KEY, provider_id TEXT NOT NULL, rule_json TEXT NOT NULL, \
+         last_fired_at TIMESTAMP, status TEXT NOT NULL DEFAULT 'active', \
+         created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP, FOREIGN KEY (provider_id) REFERENCES providers(id))",
+        "CREATE TABLE events (id TEXT PRIMARY KEY, provider_id TEXT NOT NULL, timestamp TIMESTAMP NOT NULL, \
+         kind TEXT NOT NULL, payload TEXT, created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP, \
+         FOREIGN KEY (provider_id) REFERENCES providers(id))",
+    ];
+
+    #[tokio::test]
+    async fn upgrades_a_first_release_database() {
+        let pool = memory_pool().await.unwrap();
+        for statement in FIRST_RELEASE {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+        sqlx::query(
+            "INSERT INTO providers (id, name, provider_type) VALUES ('p1', 'OpenAI', '\"openai\"')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        // The same datapoint reported twice, with dimensions in either order.
+        let timestamp = Utc::now() - chrono::Duration::hours(1);
+        for (id, value, dimensions) in [
+            ("m1", 1.0, r#"{"project":"web","model":"gpt-4o"}"#),
+            ("m2", 2.0, r#"{"model":"gpt-4o","project":"web"}"#),
+            ("m3", 5.0, r#"{"model":"o1"}"#),
+        ] {
+            sqlx::query("INSERT INTO metrics (id, provider_id, metric_type, value, unit, timestamp, dimensions) VALUES (?, 'p1', 'CostUsd', ?, 'usd', ?, ?)")
+                .bind(id)
+                .bind(value)
+                .bind(timestamp)
+                .bind(dimensions)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+
+        sqlx::query(
+            "INSERT INTO budgets (id, provider_id, period, hard_limit) VALUES (?, 'p1', ?, 100.0)",
+        )
+        .bind(budget_id("p1", BudgetPeriod::Monthly).unwrap())
+        .bind(enum_to_sql(&BudgetPeriod::Monthly).unwrap())
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query("INSERT INTO alerts (id, provider_id, rule_json) VALUES ('a1', 'p1', ?)")
+            .bind(
+                serde_json::to_string(&AlertRule::SpendThreshold {
+                    amount: 50.0,
+                    is_soft: false,
+                })
+                .unwrap(),
+            )
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let db = Database { pool };
+        db.run_migrations().await.unwrap();
+
+        assert!(db.get_providers().await.unwrap()[0]
+            .settings
+            .base_url
+            .is_none());
+
+        let mut metrics = db.get_recent_metrics("p1", 2).await.unwrap();
+        metrics.sort_by(|a, b| a.value.total_cmp(&b.value));
+        assert_eq!(
+            metrics.iter().map(|m| m.value).collect::<Vec<_>>(),
+            vec![2.0, 5.0]
+        );
+
+        let budget = db
+            .get_budget("p1", BudgetPeriod::Monthly)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(budget.hard_limit, Some(100.0));
+
+        let alert = db.get_alert("a1").await.unwrap().unwrap();
+        assert!(alert.notify_desktop);
+        assert!(alert.channel_ids.is_empty());
+
+        // Group budgets, alerts and events have no provider.
+        for table in ["budgets", "alerts", "events"] {
+            assert!(
+                !db.is_not_null(table, "provider_id").await.unwrap(),
+                "{}",
+                table
+            );
+        }
+        db.insert_group(&ProviderGroup {
+            id: "g1".to_string(),
+            name: "All".to_string(),
+            all_providers: true,
+            provider_ids: Vec::new(),
+            created_at: Utc::now(),
+        })
+        .await
+        .unwrap();
+        db.insert_event(None, Some("g1"), "alert_fired", &serde_json::json!({}))
+            .await
+            .unwrap();
+        let now = Utc::now();
+        let events = db
+            .get_events(
+                "alert_fired",
+                now - chrono::Duration::minutes(1),
+                now + chrono::Duration::minutes(1),
+            )
+            .await
+            .unwrap();
+        assert_eq!(events[0].group_id.as_deref(), Some("g1"));
+
+        // Upgrading again changes nothing.
+        db.run_migrations().await.unwrap();
+        assert_eq!(db.get_recent_metrics("p1", 2).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn deleting_keeps_the_delivery_log() {
+        let db = Database::in_memory().await.unwrap();
+        db.insert_provider(&crate::models::Provider {
+            id: "p1".to_string(),
+            name: "OpenAI".to_string(),
+            provider_type: crate::models::ProviderType::OpenAI,
+            api_key_ref: None,
+            enabled: true,
+            created_at: Utc::now(),
+            settings: Default::default(),
+        })
+        .await
+        .unwrap();
+        db.insert_group(&ProviderGroup {
+            id: "g1".to_string(),
+            name: "All".to_string(),
+            all_providers: true,
+            provider_ids: Vec::new(),
+            created_at: Utc::now(),
+        })
+        .await
+        .unwrap();
+        db.insert_event(Some("p1"), None, "alert_fired", &serde_json::json!({}))
+            .await
+            .unwrap();
+        db.insert_event(None, Some("g1"), "alert_fired", &serde_json::json!({}))
+            .await
+            .unwrap();
+
+        db.delete_provider("p1").await.unwrap();
+        assert!(db.delete_group("g1").await.unwrap());
+
+        let now = Utc::now();
+        let events = db
+            .get_events(
+                "alert_fired",
+                now - chrono::Duration::minutes(1),
+                now + chrono::Duration::minutes(1),
+            )
+            .await
+            .unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(events
+            .iter()
+            .all(|e| e.provider_id.is_none() && e.group_id.is_none()));
+    }
+}
@@ -0,0 +1,120 @@
+use chrono::Utc;
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use thiserror::Error;
+
+use crate::models::{ErrorCode, ProviderErrorState};
+use crate::providers::http::ProviderError;
+
+/// Error returned by Tauri commands. Serializes as
+/// `{ code, message, retryable }` so the frontend can tell an invalid key
+/// from a network failure without parsing strings.
+#[derive(Debug, Error)]
+pub enum AppError {
+    #[error("The provider rejected this API key")]
+    InvalidApiKey,
+    #[error("This key cannot read usage data; the provider may require an admin key")]
+    InsufficientPermissions,
+    #[error("The access token has expired; enter a new one, or credentials that can be refreshed")]
+    TokenExpired,
+    #[error("Rate limited by the provider; try again later")]
+    RateLimited,
+    #[error("Could not reach the provider. Check your network connection")]
+    NetworkUnavailable,
+    #[error("The provider is currently unavailable")]
+    ProviderUnavailable,
+    #[error("The provider returned data in an unexpected format")]
+    UnexpectedResponse,
+    #[error("{0}")]
+    InvalidRequest(String),
+    /// `kind` names the entity, e.g. `"Provider"` or `"Group"`.
+    #[error("{kind} {id} not found")]
+    NotFound { kind: &'static str, id: String },
+    #[error("Keychain access failed: {0}")]
+    Keychain(String),
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl AppError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::InvalidApiKey => ErrorCode::InvalidApiKey,
+            Self::InsufficientPermissions => ErrorCode::InsufficientPermissions,
+            Self::TokenExpired => ErrorCode::TokenExpired,
+            Self::RateLimited => ErrorCode::RateLimited,
+            Self::NetworkUnavailable => ErrorCode::NetworkUnavailable,
+            Self::ProviderUnavailable => ErrorCode::ProviderUnavailable,
+            Self::UnexpectedResponse => ErrorCode::UnexpectedResponse,
+            Self::InvalidRequest(_) => ErrorCode::InvalidRequest,
+            Self::NotFound { .. } => ErrorCode::NotFound,
+            Self::Keychain(_) => ErrorCode::KeychainUnavailable,
+            Self::Internal(_) => ErrorCode::Internal,
+        }
+    }
+
+    /// Whether trying the same thing again later could succeed.
+    pub fn retryable(&self) -> bool {
+        matches!(
+            self,
+            Self::RateLimited | Self::NetworkUnavailable | Self::ProviderUnavailable
+        )
+    }
+
+    pub fn to_state(&self) -> ProviderErrorState {
+        ProviderErrorState {
+            code: self.code(),
+            message: self.to_string(),
+            retryable: self.retryable(),
+            occurred_at: Utc::now(),
+        }
+    }
+}
+
+impl From<&ProviderError> for AppError {
+    fn from(error: &ProviderError) -> Self {
+        match error {
+            ProviderError::Unauthorized { status: 403, .. } => Self::InsufficientPermissions,
+            ProviderError::Unauthorized { .. } => Self::InvalidApiKey,
+            ProviderError::TokenExpired => Self::TokenExpired,
+            ProviderError::RateLimited { .. } => Self::RateLimited,
+            ProviderError::UpstreamDown { status: None, .. } => Self::NetworkUnavailable,
+            ProviderError::UpstreamDown { .. } => Self::ProviderUnavailable,
+            ProviderError::SchemaChanged(_) => Self::UnexpectedResponse,
+            ProviderError::Rejected { status, body } => Self::InvalidRequest(format!(
+                "Provider rejected the request ({}): {}",
+                status, body
+            )),
+            ProviderError::Misconfigured(reason) => {
+                Self::InvalidRequest(format!("Invalid provider settings: {}", reason))
+            }
+        }
+    }
+}
+
+impl From<&anyhow::Error> for AppError {
+    fn from(error: &anyhow::Error) -> Self {
+        if let Some(provider_error) = error.downcast_ref::<ProviderError>() {
+            return provider_error.into();
+        }
+        if let Some(keyring_error) = error.downcast_ref::<keyring::Error>() {
+            return Self::Keychain(keyring_error.to_string());
+        }
+        Self::Internal(error.to_string())
+    }
+}
+
+impl From<anyhow::Error> for AppError {
+    fn from(error: anyhow::Error) -> Self {
+        Self::from(&error)
+    }
+}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("AppError", 3)?;
+        state.serialize_field("code", &self.code())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.serialize_field("retryable", &self.retryable())?;
+        state.end()
+    }
+}
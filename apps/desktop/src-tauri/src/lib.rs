@@ -1,27 +1,97 @@
+mod db;
+mod error;
 mod models;
+mod providers;
+mod services;
 
 use serde::{Deserialize, Serialize};
-use std::sync::{Mutex, OnceLock};
-use std::collections::HashMap;
+use std::sync::Arc;
+use tauri::{Emitter, Manager};
+use tauri_plugin_notification::NotificationExt;
+use tracing::error;
+use tracing_subscriber::EnvFilter;
 
-// In-memory storage for providers (temporary solution)
-static PROVIDERS: OnceLock<Mutex<HashMap<String, models::ProviderUsage>>> = OnceLock::new();
+use error::AppError;
+use services::{
+    alerts::AlertEngine, backfill::BackfillService, digest::DigestService, monitor::MonitorService,
+    validation, AppState,
+};
 
 #[derive(Serialize, Deserialize)]
 struct AddProviderRequest {
     provider_type: models::ProviderType,
     api_key: String,
     name: String,
+    #[serde(default)]
+    settings: models::ProviderSettings,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ValidateProviderKeyRequest {
+    provider_type: models::ProviderType,
+    api_key: String,
+    #[serde(default)]
+    settings: models::ProviderSettings,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ProviderCapabilitiesRequest {
+    provider_type: models::ProviderType,
+    #[serde(default)]
+    settings: models::ProviderSettings,
+}
+
+/// Exactly one of `provider_id` and `group_id` is given.
+#[derive(Serialize, Deserialize)]
+struct CreateAlertRequest {
+    #[serde(default)]
+    provider_id: Option<String>,
+    #[serde(default)]
+    group_id: Option<String>,
+    rule: models::AlertRule,
+    #[serde(default = "default_notify_desktop")]
+    notify_desktop: bool,
+    #[serde(default)]
+    channel_ids: Vec<String>,
+}
+
+fn default_notify_desktop() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize)]
+struct GroupRequest {
+    name: String,
+    #[serde(default)]
+    all_providers: bool,
+    #[serde(default)]
+    provider_ids: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct AddChannelRequest {
+    name: String,
+    config: models::ChannelConfig,
+    /// Slack or Discord webhook URL, the signing key for a generic webhook,
+    /// or the SMTP password (empty if the server needs none); stored in the
+    /// keychain, never in the database.
+    secret: String,
 }
 
 #[derive(Serialize, Deserialize)]
 struct ProviderUsageResponse {
     providers: Vec<models::ProviderUsage>,
+    /// Set while no provider can be reached; usage is then last-known.
+    offline_since: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 #[tauri::command]
-async fn add_provider(request: AddProviderRequest) -> Result<(), String> {
-    println!("Adding provider: {} - {:?}", request.name, request.provider_type);
+async fn add_provider(
+    state: tauri::State<'_, Arc<AppState>>,
+    backfill: tauri::State<'_, Arc<BackfillService>>,
+    request: AddProviderRequest,
+) -> Result<(), AppError> {
+    validation::check_key_format(&request.provider_type, &request.api_key)?;
 
     let provider_id = format!("{:?}_{}", request.provider_type, uuid::Uuid::new_v4());
 
@@ -29,76 +99,416 @@ async fn add_provider(request: AddProviderRequest) -> Result<(), String> {
         id: provider_id.clone(),
         name: request.name,
         provider_type: request.provider_type,
-        api_key_ref: Some(provider_id.clone()),
+        api_key_ref: (!request.api_key.is_empty()).then(|| provider_id.clone()),
         enabled: true,
         created_at: chrono::Utc::now(),
+        settings: request.settings,
     };
 
-    let usage = models::ProviderUsage {
-        provider,
-        today_tokens: 0,
-        today_cost: 0.0,
-        mtd_tokens: 0,
-        mtd_cost: 0.0,
-        balance: None,
-        credits: None,
-        budget_used_percentage: None,
-    };
-
-    let providers_map = PROVIDERS.get_or_init(|| Mutex::new(HashMap::new()));
-    let mut providers = providers_map.lock().unwrap();
-    providers.insert(provider_id, usage);
+    state.add_provider(provider, &request.api_key).await?;
+    backfill.start(&provider_id).await?;
 
-    println!("Provider stored successfully. Total providers: {}", providers.len());
     Ok(())
 }
 
 #[tauri::command]
-async fn get_usage() -> Result<ProviderUsageResponse, String> {
-    let providers_map = PROVIDERS.get_or_init(|| Mutex::new(HashMap::new()));
-    let providers = providers_map.lock().unwrap();
-    let provider_list: Vec<models::ProviderUsage> = providers.values().cloned().collect();
+async fn validate_provider_key(
+    request: ValidateProviderKeyRequest,
+) -> Result<models::KeyValidation, AppError> {
+    validation::validate_provider_key(&request.provider_type, &request.api_key, &request.settings)
+        .await
+}
 
-    println!("get_usage called, returning {} providers", provider_list.len());
-    Ok(ProviderUsageResponse { providers: provider_list })
+#[tauri::command]
+async fn get_provider_capabilities(
+    request: ProviderCapabilitiesRequest,
+) -> Result<models::ProviderCapabilities, AppError> {
+    let adapter = AppState::adapter_for(&request.provider_type, &request.settings)?;
+    Ok(adapter.capabilities())
 }
 
 #[tauri::command]
-async fn remove_provider(provider_id: String) -> Result<(), String> {
-    let providers_map = PROVIDERS.get_or_init(|| Mutex::new(HashMap::new()));
-    let mut providers = providers_map.lock().unwrap();
+async fn get_usage(
+    state: tauri::State<'_, Arc<AppState>>,
+) -> Result<ProviderUsageResponse, AppError> {
+    let provider_list = state.usage_snapshot().await;
 
-    if providers.remove(&provider_id).is_some() {
-        println!("Removed provider: {}", provider_id);
-        Ok(())
-    } else {
-        Err(format!("Provider {} not found", provider_id))
-    }
+    println!(
+        "get_usage called, returning {} providers",
+        provider_list.len()
+    );
+    Ok(ProviderUsageResponse {
+        providers: provider_list,
+        offline_since: *state.offline_since.read().await,
+    })
 }
 
 #[tauri::command]
-async fn toggle_provider(provider_id: String, enabled: bool) -> Result<(), String> {
-    // Mock implementation for initial setup
-    println!("Toggling provider {} to {}", provider_id, enabled);
+async fn remove_provider(
+    state: tauri::State<'_, Arc<AppState>>,
+    backfill: tauri::State<'_, Arc<BackfillService>>,
+    provider_id: String,
+) -> Result<(), AppError> {
+    backfill.cancel(&provider_id).await?;
+    state.remove_provider(&provider_id).await?;
+    println!("Removed provider: {}", provider_id);
     Ok(())
 }
 
+#[tauri::command]
+async fn toggle_provider(
+    state: tauri::State<'_, Arc<AppState>>,
+    provider_id: String,
+    enabled: bool,
+) -> Result<(), AppError> {
+    println!("Toggling provider {} to {}", provider_id, enabled);
+    state.set_provider_enabled(&provider_id, enabled).await
+}
+
+/// Fetches the provider's history as far back as its API allows. Progress is
+/// reported through `backfill-progress` events.
+#[tauri::command]
+async fn start_backfill(
+    backfill: tauri::State<'_, Arc<BackfillService>>,
+    provider_id: String,
+) -> Result<bool, AppError> {
+    backfill.start(&provider_id).await
+}
+
+/// Stops a running history import. It resumes from where it stopped the
+/// next time `start_backfill` is called.
+#[tauri::command]
+async fn cancel_backfill(
+    backfill: tauri::State<'_, Arc<BackfillService>>,
+    provider_id: String,
+) -> Result<bool, AppError> {
+    Ok(backfill.cancel(&provider_id).await?)
+}
+
+/// Polls immediately instead of waiting for the schedule. Without a
+/// `provider_id`, every enabled provider is polled.
+#[tauri::command]
+async fn poll_now(
+    state: tauri::State<'_, Arc<AppState>>,
+    monitor: tauri::State<'_, Arc<MonitorService>>,
+    provider_id: Option<String>,
+) -> Result<ProviderUsageResponse, AppError> {
+    let providers = monitor.poll_now(provider_id.as_deref()).await?;
+    Ok(ProviderUsageResponse {
+        providers,
+        offline_since: *state.offline_since.read().await,
+    })
+}
+
+/// Saves a provider's or group's budget for one period, replacing any
+/// existing one.
+#[tauri::command]
+async fn set_budget(
+    state: tauri::State<'_, Arc<AppState>>,
+    budget: models::Budget,
+) -> Result<models::Budget, AppError> {
+    state.set_budget(budget).await
+}
+
+#[tauri::command]
+async fn list_budgets(
+    state: tauri::State<'_, Arc<AppState>>,
+    provider_id: Option<String>,
+    group_id: Option<String>,
+) -> Result<Vec<models::Budget>, AppError> {
+    Ok(state
+        .db
+        .get_budgets(provider_id.as_deref(), group_id.as_deref())
+        .await?)
+}
+
+#[tauri::command]
+async fn remove_budget(
+    state: tauri::State<'_, Arc<AppState>>,
+    provider_id: Option<String>,
+    group_id: Option<String>,
+    period: models::BudgetPeriod,
+) -> Result<(), AppError> {
+    state
+        .remove_budget(provider_id.as_deref(), group_id.as_deref(), period)
+        .await
+}
+
+#[tauri::command]
+async fn create_group(
+    state: tauri::State<'_, Arc<AppState>>,
+    request: GroupRequest,
+) -> Result<models::ProviderGroup, AppError> {
+    state
+        .create_group(&request.name, request.all_providers, request.provider_ids)
+        .await
+}
+
+/// Renames a group or replaces its members.
+#[tauri::command]
+async fn update_group(
+    state: tauri::State<'_, Arc<AppState>>,
+    group_id: String,
+    request: GroupRequest,
+) -> Result<models::ProviderGroup, AppError> {
+    state
+        .update_group(
+            &group_id,
+            &request.name,
+            request.all_providers,
+            request.provider_ids,
+        )
+        .await
+}
+
+#[tauri::command]
+async fn list_groups(
+    state: tauri::State<'_, Arc<AppState>>,
+) -> Result<Vec<models::ProviderGroup>, AppError> {
+    Ok(state.db.get_groups().await?)
+}
+
+/// Deletes a group along with its budgets and alerts.
+#[tauri::command]
+async fn remove_group(
+    state: tauri::State<'_, Arc<AppState>>,
+    group_id: String,
+) -> Result<(), AppError> {
+    state.remove_group(&group_id).await
+}
+
+#[tauri::command]
+async fn create_alert(
+    alerts: tauri::State<'_, Arc<AlertEngine>>,
+    request: CreateAlertRequest,
+) -> Result<models::Alert, AppError> {
+    alerts
+        .create_alert(
+            request.provider_id.as_deref(),
+            request.group_id.as_deref(),
+            request.rule,
+            request.notify_desktop,
+            request.channel_ids,
+        )
+        .await
+}
+
+/// Replaces the notification channels an alert is delivered to.
+#[tauri::command]
+async fn set_alert_channels(
+    alerts: tauri::State<'_, Arc<AlertEngine>>,
+    alert_id: String,
+    channel_ids: Vec<String>,
+) -> Result<(), AppError> {
+    alerts.set_channels(&alert_id, channel_ids).await
+}
+
+#[tauri::command]
+async fn add_channel(
+    alerts: tauri::State<'_, Arc<AlertEngine>>,
+    request: AddChannelRequest,
+) -> Result<models::NotificationChannel, AppError> {
+    alerts
+        .add_channel(&request.name, request.config, &request.secret)
+        .await
+}
+
+#[tauri::command]
+async fn list_channels(
+    alerts: tauri::State<'_, Arc<AlertEngine>>,
+) -> Result<Vec<models::NotificationChannel>, AppError> {
+    alerts.list_channels().await
+}
+
+/// Sends a sample alert through a channel without recording it in `events`.
+#[tauri::command]
+async fn test_channel(
+    alerts: tauri::State<'_, Arc<AlertEngine>>,
+    channel_id: String,
+) -> Result<services::channels::DeliveryRecord, AppError> {
+    alerts.test_channel(&channel_id).await
+}
+
+#[tauri::command]
+async fn remove_channel(
+    alerts: tauri::State<'_, Arc<AlertEngine>>,
+    channel_id: String,
+) -> Result<(), AppError> {
+    alerts.remove_channel(&channel_id).await
+}
+
+/// Turns the OS notification for one alert on or off.
+#[tauri::command]
+async fn set_alert_notify(
+    alerts: tauri::State<'_, Arc<AlertEngine>>,
+    alert_id: String,
+    notify_desktop: bool,
+) -> Result<(), AppError> {
+    alerts.set_notify_desktop(&alert_id, notify_desktop).await
+}
+
+#[tauri::command]
+async fn list_alerts(
+    alerts: tauri::State<'_, Arc<AlertEngine>>,
+    provider_id: Option<String>,
+    group_id: Option<String>,
+) -> Result<Vec<models::Alert>, AppError> {
+    alerts
+        .list_alerts(provider_id.as_deref(), group_id.as_deref())
+        .await
+}
+
+#[tauri::command]
+async fn snooze_alert(
+    alerts: tauri::State<'_, Arc<AlertEngine>>,
+    alert_id: String,
+    until: chrono::DateTime<chrono::Utc>,
+) -> Result<models::Alert, AppError> {
+    alerts.snooze(&alert_id, until).await
+}
+
+#[tauri::command]
+async fn acknowledge_alert(
+    alerts: tauri::State<'_, Arc<AlertEngine>>,
+    alert_id: String,
+) -> Result<models::Alert, AppError> {
+    alerts.acknowledge(&alert_id).await
+}
+
+#[tauri::command]
+async fn disable_alert(
+    alerts: tauri::State<'_, Arc<AlertEngine>>,
+    alert_id: String,
+) -> Result<models::Alert, AppError> {
+    alerts.disable(&alert_id).await
+}
+
+#[tauri::command]
+async fn enable_alert(
+    alerts: tauri::State<'_, Arc<AlertEngine>>,
+    alert_id: String,
+) -> Result<models::Alert, AppError> {
+    alerts.enable(&alert_id).await
+}
+
+/// Shows an alert as an OS notification. The Snooze and Open Dashboard
+/// buttons come from the `alert` action type the frontend registers; on
+/// platforms without notification actions the in-app banner offers both.
+fn notify_alert(handle: &tauri::AppHandle, event: &models::AlertEvent) {
+    let result = handle
+        .notification()
+        .builder()
+        .title(&event.title)
+        .body(&event.message)
+        .action_type_id("alert")
+        .extra("alert_id", &event.alert_id)
+        .show();
+
+    if let Err(e) = result {
+        error!(
+            "Failed to show notification for alert {}: {}",
+            event.alert_id, e
+        );
+    }
+}
+
 // Tray functionality temporarily disabled for initial setup
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        )
+        .init();
+
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
-        .setup(|_app| {
-            // Initialize app state here
+        .plugin(tauri_plugin_notification::init())
+        .setup(|app| {
+            let data_dir = app.path().app_data_dir()?;
+            std::fs::create_dir_all(&data_dir)?;
+
+            let state = tauri::async_runtime::block_on(async {
+                let db = db::Database::new(&data_dir.join("aimonitor.db")).await?;
+                AppState::new(db).await
+            })?;
+            let state = Arc::new(state);
+            app.manage(Arc::clone(&state));
+
+            let handle = app.handle().clone();
+            let backfill = Arc::new(BackfillService::new(
+                Arc::clone(&state),
+                Arc::new(move |progress| {
+                    let _ = handle.emit("backfill-progress", progress);
+                }),
+            ));
+            app.manage(Arc::clone(&backfill));
+            tauri::async_runtime::spawn(async move {
+                if let Err(e) = backfill.resume_all().await {
+                    error!("Failed to resume backfills: {}", e);
+                }
+            });
+
+            let handle = app.handle().clone();
+            let alerts = Arc::new(AlertEngine::new(
+                Arc::clone(&state),
+                Arc::new(move |event| {
+                    let _ = handle.emit("alert-triggered", event);
+                    if event.notify_desktop {
+                        notify_alert(&handle, event);
+                    }
+                }),
+            )?);
+            app.manage(Arc::clone(&alerts));
+            let snoozes = Arc::clone(&alerts);
+            tauri::async_runtime::spawn(async move {
+                snoozes.start().await;
+            });
+
+            let digest = Arc::new(DigestService::new(Arc::clone(&state)));
+            tauri::async_runtime::spawn(async move {
+                digest.start().await;
+            });
+
+            let monitor = Arc::new(MonitorService::new(state, alerts));
+            app.manage(Arc::clone(&monitor));
+            tauri::async_runtime::spawn(async move {
+                monitor.start_polling().await;
+            });
+
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             add_provider,
+            validate_provider_key,
+            get_provider_capabilities,
             get_usage,
             remove_provider,
-            toggle_provider
+            toggle_provider,
+            start_backfill,
+            cancel_backfill,
+            poll_now,
+            set_budget,
+            list_budgets,
+            remove_budget,
+            create_group,
+            update_group,
+            list_groups,
+            remove_group,
+            create_alert,
+            set_alert_notify,
+            list_alerts,
+            snooze_alert,
+            acknowledge_alert,
+            disable_alert,
+            enable_alert,
+            set_alert_channels,
+            add_channel,
+            list_channels,
+            test_channel,
+            remove_channel
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
-}
\ No newline at end of file
+}
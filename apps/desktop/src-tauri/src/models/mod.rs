@@ -10,6 +10,8 @@ pub struct Provider {
     pub api_key_ref: Option<String>,
     pub enabled: bool,
     pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub settings: ProviderSettings,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -20,6 +22,156 @@ pub enum ProviderType {
     Anthropic,
     #[serde(rename = "openrouter")]
     OpenRouter,
+    Gemini,
+    #[serde(rename = "azure_openai")]
+    AzureOpenAI,
+    Custom,
+    Local,
+    Bedrock,
+}
+
+/// Provider-specific configuration that does not belong in the keychain.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProviderSettings {
+    /// Google Cloud project to read Vertex AI metrics from. Without it a
+    /// Gemini provider is tracked against its budget only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gcp_project_id: Option<String>,
+    /// OAuth token endpoint override (Gemini only). Takes precedence over the
+    /// `token_uri` in the credentials JSON.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gcp_token_uri: Option<String>,
+    /// API endpoint override for corporate gateways, regional endpoints or
+    /// mock servers. Required for Azure OpenAI (the resource endpoint, e.g.
+    /// `https://my-resource.openai.azure.com`) and custom providers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<String>,
+    /// REST API version sent as the `api-version` query parameter (Azure OpenAI only).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_version: Option<String>,
+    /// Where an Azure OpenAI provider reads token usage and cost. Without it
+    /// only budgets are tracked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub azure_monitor: Option<AzureMonitorSettings>,
+    /// Send a one-token completion to each Azure OpenAI deployment, at most
+    /// every 15 minutes, to read its remaining tokens per minute. Every probe
+    /// is a billed request, so this is off by default.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub azure_tpm_probe: bool,
+    /// Endpoint and response mappings for `ProviderType::Custom`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub custom: Option<CustomProviderConfig>,
+    /// Which server a `ProviderType::Local` provider points at.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub local_runtime: Option<LocalRuntime>,
+    /// Notional USD per million tokens for local runtimes, so their usage can
+    /// be compared with hosted spend.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notional_cost_per_million_tokens: Option<f64>,
+    /// AWS region used for request signing (Bedrock only). Defaults to `us-east-1`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aws_region: Option<String>,
+    /// Usual poll interval, in seconds. Defaults to 60. Polling adapts
+    /// around it between the min and max below.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub poll_interval_secs: Option<u64>,
+    /// Never shorter than the adapter's `min_poll_interval_secs`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_poll_interval_secs: Option<u64>,
+    /// Defaults to 15 minutes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_poll_interval_secs: Option<u64>,
+    #[serde(default)]
+    pub http: HttpSettings,
+    #[serde(default)]
+    pub forecast: ForecastSettings,
+}
+
+/// How a provider's spend forecast is computed from its stored metrics.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ForecastSettings {
+    /// Days of history the burn rate is computed from. Defaults to 14.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lookback_days: Option<u32>,
+    /// Age in days at which a day's spend counts half as much as
+    /// yesterday's. Defaults to 3; larger values smooth out spikes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub half_life_days: Option<f64>,
+}
+
+/// Client options applied to every request made for a provider.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HttpSettings {
+    /// HTTP or HTTPS proxy, e.g. `http://proxy.corp:3128`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy_url: Option<String>,
+    /// PEM bundle trusted in addition to the system roots.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca_bundle_path: Option<String>,
+    /// Defaults to 30 seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+    /// Defaults to `AIMonitor-Desktop/<version> (<os>)`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LocalRuntime {
+    Ollama,
+    LlamaCpp,
+}
+
+/// A Microsoft Entra service principal that can read an Azure OpenAI
+/// resource's metrics (Monitoring Reader) and cost (Cost Management Reader).
+/// Its client secret is stored in the keychain with the resource key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AzureMonitorSettings {
+    pub tenant_id: String,
+    pub client_id: String,
+    /// ARM id of the resource, e.g. `/subscriptions/<id>/resourceGroups/<group>/providers/Microsoft.CognitiveServices/accounts/<name>`.
+    pub resource_id: String,
+    /// Azure Resource Manager endpoint override for sovereign clouds or mock
+    /// servers. Defaults to `https://management.azure.com`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub management_url: Option<String>,
+    /// Microsoft Entra ID endpoint override. Defaults to
+    /// `https://login.microsoftonline.com`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub login_url: Option<String>,
+}
+
+/// Describes an OpenAI-style vendor API so it can be monitored without a
+/// dedicated adapter. Paths are relative to `ProviderSettings::base_url`;
+/// pointers are RFC 6901 JSON pointers into the response body.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CustomProviderConfig {
+    #[serde(default)]
+    pub auth_style: AuthStyle,
+    /// e.g. `/user/balance`
+    pub balance_path: Option<String>,
+    /// e.g. `/balance_infos/0/total_balance`
+    pub balance_pointer: Option<String>,
+    /// May contain `{from}` and `{to}` placeholders, filled in as `YYYY-MM-DD`.
+    /// Usage is requested one day at a time, so both are the same day; a path
+    /// without them is read as the total for the month so far.
+    pub usage_path: Option<String>,
+    pub cost_pointer: Option<String>,
+    pub tokens_in_pointer: Option<String>,
+    pub tokens_out_pointer: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum AuthStyle {
+    /// `Authorization: Bearer <key>`
+    #[default]
+    Bearer,
+    /// The key in a named header, e.g. `x-api-key`.
+    Header { name: String },
+    /// The key in a named query parameter, e.g. `key`.
+    Query { name: String },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -42,18 +194,31 @@ pub enum MetricType {
     CostUsd,
     CreditsRemaining,
     Balance,
+    TpmRemaining,
+    Requests,
+    ModelLoaded,
+    /// How much a poll grew the stored USD spend, per model. Anomaly rules
+    /// compare these by hour, whatever granularity the provider reports.
+    SpendDelta,
+    /// How much a poll grew the stored token count, per model.
+    TokensDelta,
 }
 
+/// A spending limit for one provider, or for the combined spend of a
+/// `ProviderGroup`. Exactly one of `provider_id` and `group_id` is set.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Budget {
-    pub provider_id: String,
+    #[serde(default)]
+    pub provider_id: Option<String>,
+    #[serde(default)]
+    pub group_id: Option<String>,
     pub period: BudgetPeriod,
     pub soft_limit: Option<f64>,
     pub hard_limit: Option<f64>,
     pub notes: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum BudgetPeriod {
     Daily,
@@ -61,32 +226,221 @@ pub enum BudgetPeriod {
     Monthly,
 }
 
+/// Watches one provider, or the combined usage of a `ProviderGroup`.
+/// Exactly one of `provider_id` and `group_id` is set.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Alert {
     pub id: String,
-    pub provider_id: String,
+    #[serde(default)]
+    pub provider_id: Option<String>,
+    #[serde(default)]
+    pub group_id: Option<String>,
     pub rule: AlertRule,
     pub last_fired_at: Option<DateTime<Utc>>,
     pub status: AlertStatus,
+    /// Raise an OS notification when the alert fires. The in-app banner is
+    /// shown either way.
+    #[serde(default = "default_true")]
+    pub notify_desktop: bool,
+    /// The alert is not evaluated until then.
+    #[serde(default)]
+    pub snoozed_until: Option<DateTime<Utc>>,
+    /// `NotificationChannel`s the alert is delivered to when it fires.
+    #[serde(default)]
+    pub channel_ids: Vec<String>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Providers whose usage is added up for shared budgets and alerts, such as
+/// all AI spend across vendors.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderGroup {
+    pub id: String,
+    pub name: String,
+    /// Every provider, including ones added later; `provider_ids` is ignored.
+    #[serde(default)]
+    pub all_providers: bool,
+    #[serde(default)]
+    pub provider_ids: Vec<String>,
+    pub created_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum AlertRule {
-    SpendThreshold { amount: f64, is_soft: bool },
-    CreditThreshold { amount: f64, is_soft: bool },
-    ProjectedRunOut { days_before: u32 },
+    SpendThreshold {
+        amount: f64,
+        is_soft: bool,
+    },
+    CreditThreshold {
+        amount: f64,
+        is_soft: bool,
+    },
+    ProjectedRunOut {
+        days_before: u32,
+    },
+    /// Spend this period at or above `percent` of the provider's `Budget`
+    /// for `period` (its hard limit, or the soft one if that is all it has).
+    BudgetPercentage {
+        percent: f64,
+        period: BudgetPeriod,
+    },
+    /// Hourly spend or tokens, for the provider or any one model, far above
+    /// their usual level.
+    Anomaly {
+        metric: AnomalyMetric,
+        #[serde(default)]
+        sensitivity: AnomalySensitivity,
+    },
+    /// A condition over the provider's stored metrics, such as
+    /// `sum(cost, today) > 2 * sum(cost, yesterday)`; see `services::expression`.
+    Expression {
+        expression: String,
+        is_soft: bool,
+    },
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnomalyMetric {
+    Spend,
+    Tokens,
+}
+
+/// How unusual an hour must be to fire, in median absolute deviations
+/// above the median hour.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnomalySensitivity {
+    Low,
+    #[default]
+    Medium,
+    High,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum AlertStatus {
     Active,
     Triggered,
+    /// Triggered and seen; re-arms like `Triggered` once the condition clears.
+    Acknowledged,
     Snoozed,
     Disabled,
 }
 
+/// Somewhere alerts are delivered besides the desktop. Its secret, such as
+/// a webhook URL, is kept in the keychain under the channel's id.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationChannel {
+    pub id: String,
+    pub name: String,
+    pub config: ChannelConfig,
+    pub enabled: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChannelConfig {
+    /// Slack incoming webhook; the secret is the webhook URL.
+    Slack,
+    /// Discord channel webhook; the secret is the webhook URL.
+    Discord,
+    /// POSTs a versioned JSON payload signed with HMAC-SHA256; the secret is
+    /// the signing key.
+    Webhook {
+        url: String,
+        /// Sent with every request, e.g. a routing key for the receiver.
+        #[serde(default)]
+        headers: HashMap<String, String>,
+        /// Per attempt. Defaults to 10 seconds.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        timeout_secs: Option<u64>,
+        /// Including the first. Defaults to 4.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_attempts: Option<u32>,
+    },
+    /// Sends alerts, and optionally a spend digest, by SMTP; the secret is
+    /// the SMTP password, if the server needs one.
+    Email {
+        host: String,
+        port: u16,
+        #[serde(default)]
+        security: SmtpSecurity,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        username: Option<String>,
+        from: String,
+        to: Vec<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        digest: Option<DigestFrequency>,
+        /// Local hour the digest goes out at. Defaults to 8.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        digest_hour: Option<u32>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SmtpSecurity {
+    /// Upgrade a plain connection, usually on port 587.
+    #[default]
+    StartTls,
+    /// TLS from the start, usually on port 465.
+    Tls,
+    /// No encryption, for a relay on the local machine or network.
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DigestFrequency {
+    Daily,
+    /// Sent on Mondays.
+    Weekly,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertSeverity {
+    /// A soft limit was crossed.
+    Warning,
+    /// A hard limit was crossed.
+    Critical,
+}
+
+/// An alert that just fired, emitted as the `alert-triggered` event and
+/// handed to every notification channel.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertEvent {
+    pub alert_id: String,
+    /// The alert's provider, or `None` for a group alert.
+    #[serde(default)]
+    pub provider_id: Option<String>,
+    #[serde(default)]
+    pub group_id: Option<String>,
+    /// The provider's name, or the group's.
+    pub provider_name: String,
+    pub rule: AlertRule,
+    pub severity: AlertSeverity,
+    /// The value that crossed the threshold, in `unit`.
+    pub current_value: f64,
+    pub threshold: f64,
+    /// `usd`, `credits`, `tokens`, `days` until a projected run-out, or
+    /// `value` for expression rules.
+    pub unit: String,
+    /// The budget period `current_value` covers; `None` for balances and run-outs.
+    #[serde(default)]
+    pub period: Option<BudgetPeriod>,
+    pub title: String,
+    pub message: String,
+    pub notify_desktop: bool,
+    pub triggered_at: DateTime<Utc>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProviderUsage {
     pub provider: Provider,
@@ -96,5 +450,110 @@ pub struct ProviderUsage {
     pub mtd_cost: f64,
     pub balance: Option<f64>,
     pub credits: Option<f64>,
+    /// The most used of the provider's budgets, or of a spending limit the
+    /// provider itself reports (OpenRouter key limits).
     pub budget_used_percentage: Option<f64>,
-}
\ No newline at end of file
+    /// Why the last poll failed, if it did; cleared on the next success.
+    #[serde(default)]
+    pub last_error: Option<ProviderErrorState>,
+    #[serde(default)]
+    pub capabilities: ProviderCapabilities,
+    /// When these values were last fetched successfully. `None` if never.
+    #[serde(default)]
+    pub last_synced_at: Option<DateTime<Utc>>,
+    /// The values are last-known rather than current: the latest poll failed,
+    /// or the last successful one is too old to trust.
+    #[serde(default)]
+    pub stale: bool,
+    /// When credits or the balance run out at the recent burn rate, or this
+    /// month's budget does if that happens before the month ends, whichever
+    /// is first. `None` if neither is expected or the history is too short.
+    #[serde(default)]
+    pub projected_run_out_at: Option<DateTime<Utc>>,
+    /// Month-to-date spend plus the recent burn rate for the rest of the month.
+    #[serde(default)]
+    pub projected_period_spend: Option<f64>,
+    /// Month-to-date tokens of a local runtime priced at the user's notional
+    /// rate, for comparison with hosted spend. Never counted as spend.
+    #[serde(default)]
+    pub notional_mtd_cost: Option<f64>,
+}
+
+/// What an adapter can report, so the UI, budgets and alerts can check for a
+/// capability instead of matching on `ProviderType`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProviderCapabilities {
+    pub balance: bool,
+    pub credits: bool,
+    pub token_usage: bool,
+    pub cost: bool,
+    /// Cost is derived from a price table rather than billed amounts.
+    pub estimated_cost: bool,
+    pub per_model_breakdown: bool,
+    /// How far back usage can be fetched; 0 if only current totals are available.
+    pub backfill_days: u32,
+    /// Polling more often than this wastes quota or returns the same data.
+    pub min_poll_interval_secs: u64,
+    pub requires_api_key: bool,
+    /// Usage is stored as per-poll deltas of running counters, so today and
+    /// month-to-date are summed from stored metrics instead of the adapter.
+    #[serde(default)]
+    pub usage_from_history: bool,
+    /// Usage is only reported as a month-to-date total, so today's tokens
+    /// and cost are unknown and left at zero.
+    #[serde(default)]
+    pub month_to_date_only: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderErrorState {
+    pub code: ErrorCode,
+    pub message: String,
+    pub retryable: bool,
+    pub occurred_at: DateTime<Utc>,
+}
+
+/// Machine-readable error category shared by command errors and
+/// `ProviderErrorState`, so the frontend can branch without parsing messages.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    InvalidApiKey,
+    InsufficientPermissions,
+    TokenExpired,
+    RateLimited,
+    NetworkUnavailable,
+    ProviderUnavailable,
+    UnexpectedResponse,
+    InvalidRequest,
+    NotFound,
+    KeychainUnavailable,
+    Internal,
+}
+
+/// What an API key was able to read when tested by `validate_provider_key`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KeyValidation {
+    pub usage_read: bool,
+    pub cost_read: bool,
+    pub balance_read: bool,
+    /// Anthropic only: usage and cost reports require an Admin API key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub admin_key: Option<bool>,
+}
+
+/// Emitted as the `backfill-progress` event while history is being fetched.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackfillProgress {
+    pub provider_id: String,
+    /// Usage from this point up to the start of the current month is stored.
+    pub fetched_back_to: DateTime<Utc>,
+    pub target: DateTime<Utc>,
+    pub percent: f64,
+    pub done: bool,
+    /// Stopped by the user; starting again resumes from `fetched_back_to`.
+    #[serde(default)]
+    pub cancelled: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ProviderErrorState>,
+}
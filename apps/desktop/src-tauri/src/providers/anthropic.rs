@@ -1,12 +1,15 @@
-use async_trait::async_trait;
 use anyhow::Result;
-use chrono::{DateTime, Utc};
+use async_trait::async_trait;
+use chrono::{DateTime, Datelike, Utc};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-use crate::models::{Metric, MetricType, Provider, ProviderType, ProviderUsage};
-use super::ProviderAdapter;
+use super::{http, ProviderAdapter};
+use crate::models::{
+    KeyValidation, Metric, MetricType, Provider, ProviderCapabilities, ProviderSettings,
+    ProviderType, ProviderUsage,
+};
 
 pub struct AnthropicAdapter {
     client: Client,
@@ -40,34 +43,53 @@ struct CostItem {
 }
 
 impl AnthropicAdapter {
-    pub fn new() -> Self {
-        Self {
-            client: Client::new(),
-            base_url: "https://api.anthropic.com/v1".to_string(),
-        }
+    pub fn new(settings: &ProviderSettings) -> Result<Self> {
+        Ok(Self {
+            client: http::build_client(&settings.http)?,
+            base_url: http::base_url(settings, "https://api.anthropic.com/v1"),
+        })
+    }
+
+    fn get(&self, path: &str, api_key: &str) -> reqwest::RequestBuilder {
+        self.client
+            .get(format!("{}{}", self.base_url, path))
+            .header("x-api-key", api_key)
+            .header("anthropic-version", "2023-06-01")
     }
 }
 
 #[async_trait]
 impl ProviderAdapter for AnthropicAdapter {
-    async fn fetch_usage(&self, api_key: &str, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<Metric>> {
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities {
+            token_usage: true,
+            cost: true,
+            per_model_breakdown: true,
+            backfill_days: 365,
+            min_poll_interval_secs: 60,
+            requires_api_key: true,
+            ..Default::default()
+        }
+    }
+
+    async fn fetch_usage(
+        &self,
+        api_key: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Metric>> {
         let mut metrics = Vec::new();
 
-        let usage_url = format!("{}/organizations/usage_report/messages", self.base_url);
         let params = HashMap::from([
             ("start_date", from.format("%Y-%m-%d").to_string()),
             ("end_date", to.format("%Y-%m-%d").to_string()),
         ]);
 
-        let usage_response = self.client
-            .get(&usage_url)
-            .header("x-api-key", api_key)
-            .header("anthropic-version", "2023-06-01")
-            .query(&params)
-            .send()
-            .await?
-            .json::<UsageReport>()
-            .await?;
+        let usage_response = http::send_json::<UsageReport>(
+            self.get("/organizations/usage_report/messages", api_key)
+                .query(&params),
+        )
+        .await?;
 
         for item in usage_response.usage {
             let timestamp = DateTime::parse_from_rfc3339(&item.timestamp)
@@ -81,9 +103,7 @@ impl ProviderAdapter for AnthropicAdapter {
                 value: item.input_tokens as f64,
                 unit: "tokens".to_string(),
                 timestamp,
-                dimensions: HashMap::from([
-                    ("model".to_string(), item.model.clone()),
-                ]),
+                dimensions: HashMap::from([("model".to_string(), item.model.clone())]),
             });
 
             metrics.push(Metric {
@@ -93,9 +113,7 @@ impl ProviderAdapter for AnthropicAdapter {
                 value: item.output_tokens as f64,
                 unit: "tokens".to_string(),
                 timestamp,
-                dimensions: HashMap::from([
-                    ("model".to_string(), item.model.clone()),
-                ]),
+                dimensions: HashMap::from([("model".to_string(), item.model.clone())]),
             });
 
             if let Some(cached) = item.input_cached_tokens {
@@ -106,23 +124,16 @@ impl ProviderAdapter for AnthropicAdapter {
                     value: cached as f64,
                     unit: "tokens".to_string(),
                     timestamp,
-                    dimensions: HashMap::from([
-                        ("model".to_string(), item.model.clone()),
-                    ]),
+                    dimensions: HashMap::from([("model".to_string(), item.model.clone())]),
                 });
             }
         }
 
-        let cost_url = format!("{}/organizations/cost_report", self.base_url);
-        let cost_response = self.client
-            .get(&cost_url)
-            .header("x-api-key", api_key)
-            .header("anthropic-version", "2023-06-01")
-            .query(&params)
-            .send()
-            .await?
-            .json::<CostReport>()
-            .await?;
+        let cost_response = http::send_json::<CostReport>(
+            self.get("/organizations/cost_report", api_key)
+                .query(&params),
+        )
+        .await?;
 
         for item in cost_response.costs {
             let timestamp = DateTime::parse_from_rfc3339(&item.timestamp)
@@ -143,45 +154,61 @@ impl ProviderAdapter for AnthropicAdapter {
         Ok(metrics)
     }
 
+    /// Usage and cost reports are Admin API endpoints. Standard keys are
+    /// checked against the models list so a typo is still caught.
+    async fn validate_key(&self, api_key: &str) -> Result<KeyValidation> {
+        if !api_key.starts_with("sk-ant-admin") {
+            http::send(self.get("/models", api_key)).await?;
+
+            return Ok(KeyValidation {
+                admin_key: Some(false),
+                ..Default::default()
+            });
+        }
+
+        let today = Utc::now().format("%Y-%m-%d").to_string();
+        let params = [("start_date", today.as_str()), ("end_date", today.as_str())];
+
+        let usage = http::permitted(
+            http::send(
+                self.get("/organizations/usage_report/messages", api_key)
+                    .query(&params),
+            )
+            .await,
+        )?;
+        let cost = http::permitted(
+            http::send(
+                self.get("/organizations/cost_report", api_key)
+                    .query(&params),
+            )
+            .await,
+        )?;
+
+        Ok(KeyValidation {
+            usage_read: usage.is_some(),
+            cost_read: cost.is_some(),
+            balance_read: false,
+            admin_key: Some(true),
+        })
+    }
+
     async fn fetch_balance(&self, _api_key: &str) -> Result<Option<f64>> {
         Ok(None)
     }
 
-    async fn get_current_usage(&self, api_key: &str) -> Result<ProviderUsage> {
+    async fn get_current_usage(&self, _api_key: &str, metrics: &[Metric]) -> Result<ProviderUsage> {
         let now = Utc::now();
-        let start_of_day = now.date_naive().and_hms_opt(0, 0, 0)
-            .unwrap()
-            .and_utc();
-        let start_of_month = now.date_naive()
+        let start_of_day = now.date_naive().and_hms_opt(0, 0, 0).unwrap().and_utc();
+        let start_of_month = now
+            .date_naive()
             .with_day(1)
             .unwrap()
             .and_hms_opt(0, 0, 0)
             .unwrap()
             .and_utc();
 
-        let metrics = self.fetch_usage(api_key, start_of_month, now).await?;
-
-        let today_tokens: u64 = metrics.iter()
-            .filter(|m| m.timestamp >= start_of_day)
-            .filter(|m| matches!(m.metric_type, MetricType::TokensIn | MetricType::TokensOut))
-            .map(|m| m.value as u64)
-            .sum();
-
-        let today_cost: f64 = metrics.iter()
-            .filter(|m| m.timestamp >= start_of_day)
-            .filter(|m| matches!(m.metric_type, MetricType::CostUsd))
-            .map(|m| m.value)
-            .sum();
-
-        let mtd_tokens: u64 = metrics.iter()
-            .filter(|m| matches!(m.metric_type, MetricType::TokensIn | MetricType::TokensOut))
-            .map(|m| m.value as u64)
-            .sum();
-
-        let mtd_cost: f64 = metrics.iter()
-            .filter(|m| matches!(m.metric_type, MetricType::CostUsd))
-            .map(|m| m.value)
-            .sum();
+        let (today_tokens, today_cost) = super::totals_since(metrics, start_of_day);
+        let (mtd_tokens, mtd_cost) = super::totals_since(metrics, start_of_month);
 
         Ok(ProviderUsage {
             provider: Provider {
@@ -191,6 +218,7 @@ impl ProviderAdapter for AnthropicAdapter {
                 api_key_ref: Some("anthropic_key".to_string()),
                 enabled: true,
                 created_at: Utc::now(),
+                settings: ProviderSettings::default(),
             },
             today_tokens,
             today_cost,
@@ -199,6 +227,13 @@ impl ProviderAdapter for AnthropicAdapter {
             balance: None,
             credits: None,
             budget_used_percentage: None,
+            last_error: None,
+            capabilities: self.capabilities(),
+            last_synced_at: None,
+            stale: false,
+            projected_run_out_at: None,
+            projected_period_spend: None,
+            notional_mtd_cost: None,
         })
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,549 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+use super::http::{self, ProviderError};
+use super::ProviderAdapter;
+use crate::models::{
+    AzureMonitorSettings, KeyValidation, Metric, MetricType, Provider, ProviderCapabilities,
+    ProviderSettings, ProviderType, ProviderUsage,
+};
+
+const DEFAULT_API_VERSION: &str = "2024-10-21";
+// Listing deployments was dropped from the data plane after this version.
+const DEPLOYMENTS_API_VERSION: &str = "2022-12-01";
+const MANAGEMENT_URL: &str = "https://management.azure.com";
+const LOGIN_URL: &str = "https://login.microsoftonline.com";
+const METRICS_API_VERSION: &str = "2018-01-01";
+const COST_API_VERSION: &str = "2023-03-01";
+/// Probes are billed completions, so they are spaced out whatever the poll interval.
+const PROBE_INTERVAL_SECS: i64 = 15 * 60;
+
+// When each endpoint was last probed. Adapters are created per poll, so this
+// has to outlive them.
+static LAST_PROBES: OnceLock<Mutex<HashMap<String, DateTime<Utc>>>> = OnceLock::new();
+
+/// Azure OpenAI resource. Token usage comes from Azure Monitor and cost from
+/// Cost Management, both read with a service principal configured in
+/// `ProviderSettings::azure_monitor`; without one the provider is tracked
+/// against its budget only. The key is the resource's `api-key`, followed by
+/// `:<client secret>` when a service principal is configured.
+pub struct AzureOpenAIAdapter {
+    client: Client,
+    base_url: Option<String>,
+    api_version: String,
+    monitor: Option<AzureMonitorSettings>,
+    tpm_probe: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeploymentList {
+    data: Vec<Deployment>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Deployment {
+    id: String,
+    model: String,
+    status: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MetricsResponse {
+    value: Vec<MonitorMetric>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MonitorMetric {
+    name: LocalizedName,
+    #[serde(default)]
+    timeseries: Vec<MonitorTimeSeries>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LocalizedName {
+    value: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct MonitorTimeSeries {
+    #[serde(default)]
+    metadatavalues: Vec<MetadataValue>,
+    #[serde(default)]
+    data: Vec<MonitorPoint>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MetadataValue {
+    name: LocalizedName,
+    value: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MonitorPoint {
+    time_stamp: DateTime<Utc>,
+    total: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CostResponse {
+    properties: CostTable,
+}
+
+#[derive(Debug, Deserialize)]
+struct CostTable {
+    columns: Vec<CostColumn>,
+    rows: Vec<Vec<serde_json::Value>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CostColumn {
+    name: String,
+}
+
+/// Azure Resource Manager root for `monitor`, without a trailing slash.
+fn management_url(monitor: &AzureMonitorSettings) -> String {
+    http::url_or(monitor.management_url.as_deref(), MANAGEMENT_URL)
+}
+
+impl AzureOpenAIAdapter {
+    pub fn new(settings: &ProviderSettings) -> Result<Self> {
+        Ok(Self {
+            client: http::build_client(&settings.http)?,
+            base_url: settings
+                .base_url
+                .as_ref()
+                .map(|url| url.trim_end_matches('/').to_string()),
+            api_version: settings
+                .api_version
+                .clone()
+                .unwrap_or_else(|| DEFAULT_API_VERSION.to_string()),
+            monitor: settings.azure_monitor.clone(),
+            tpm_probe: settings.azure_tpm_probe,
+        })
+    }
+
+    fn endpoint(&self) -> Result<&str> {
+        self.base_url
+            .as_deref()
+            .ok_or_else(|| anyhow!("Azure OpenAI provider has no resource endpoint configured"))
+    }
+
+    /// The resource key and, if present, the service principal's secret.
+    fn split_key(api_key: &str) -> (&str, Option<&str>) {
+        match api_key.split_once(':') {
+            Some((resource_key, client_secret)) => (resource_key, Some(client_secret)),
+            None => (api_key, None),
+        }
+    }
+
+    async fn list_deployments(&self, resource_key: &str) -> Result<Vec<Deployment>> {
+        let url = format!("{}/openai/deployments", self.endpoint()?);
+
+        let response = http::send_json::<DeploymentList>(
+            self.client
+                .get(&url)
+                .header("api-key", resource_key)
+                .query(&[("api-version", DEPLOYMENTS_API_VERSION)]),
+        )
+        .await?;
+
+        Ok(response
+            .data
+            .into_iter()
+            .filter(|d| matches!(d.status.as_deref(), None | Some("succeeded")))
+            .collect())
+    }
+
+    /// An Azure Resource Manager token for the service principal.
+    async fn management_token(
+        &self,
+        monitor: &AzureMonitorSettings,
+        client_secret: &str,
+    ) -> Result<String, ProviderError> {
+        let login_url = http::url_or(monitor.login_url.as_deref(), LOGIN_URL);
+        let scope = format!("{}/.default", management_url(monitor));
+        let request = self
+            .client
+            .post(format!(
+                "{}/{}/oauth2/v2.0/token",
+                login_url, monitor.tenant_id
+            ))
+            .form(&[
+                ("grant_type", "client_credentials"),
+                ("client_id", monitor.client_id.as_str()),
+                ("client_secret", client_secret),
+                ("scope", scope.as_str()),
+            ]);
+
+        http::oauth_token(
+            &format!("{}:{}", monitor.tenant_id, monitor.client_id),
+            request,
+        )
+        .await
+    }
+
+    /// Hourly prompt and generated tokens per deployment from Azure Monitor,
+    /// stamped at the start of each hour.
+    async fn fetch_tokens(
+        &self,
+        monitor: &AzureMonitorSettings,
+        token: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Metric>> {
+        let url = format!(
+            "{}{}/providers/Microsoft.Insights/metrics",
+            management_url(monitor),
+            monitor.resource_id
+        );
+        let timespan = format!("{}/{}", from.to_rfc3339(), to.to_rfc3339());
+
+        let response =
+            http::send_json::<MetricsResponse>(self.client.get(&url).bearer_auth(token).query(&[
+                ("api-version", METRICS_API_VERSION),
+                ("metricnames", "ProcessedPromptTokens,GeneratedTokens"),
+                ("timespan", timespan.as_str()),
+                ("interval", "PT1H"),
+                ("aggregation", "Total"),
+                ("$filter", "ModelDeploymentName eq '*'"),
+            ]))
+            .await?;
+
+        let mut metrics = Vec::new();
+        for metric in response.value {
+            let metric_type = match metric.name.value.as_str() {
+                "ProcessedPromptTokens" => MetricType::TokensIn,
+                "GeneratedTokens" => MetricType::TokensOut,
+                _ => continue,
+            };
+
+            for series in metric.timeseries {
+                let deployment = series
+                    .metadatavalues
+                    .iter()
+                    .find(|m| m.name.value.eq_ignore_ascii_case("ModelDeploymentName"))
+                    .map(|m| m.value.clone())
+                    .unwrap_or_else(|| "unknown".to_string());
+
+                for point in series.data {
+                    let Some(total) = point.total.filter(|total| *total > 0.0) else {
+                        continue;
+                    };
+                    metrics.push(Metric {
+                        id: uuid::Uuid::new_v4().to_string(),
+                        provider_id: "azure_openai".to_string(),
+                        metric_type: metric_type.clone(),
+                        value: total,
+                        unit: "tokens".to_string(),
+                        timestamp: point.time_stamp,
+                        dimensions: HashMap::from([("deployment".to_string(), deployment.clone())]),
+                    });
+                }
+            }
+        }
+
+        Ok(metrics)
+    }
+
+    /// Daily actual cost of the resource from Cost Management, stamped at the
+    /// start of each day. Costs billed in another currency keep its code as
+    /// their unit, so they stay out of USD totals.
+    async fn fetch_cost(
+        &self,
+        monitor: &AzureMonitorSettings,
+        token: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Metric>> {
+        let subscription = monitor
+            .resource_id
+            .split('/')
+            .skip_while(|part| !part.eq_ignore_ascii_case("subscriptions"))
+            .nth(1)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Azure resource id has no subscription: {}",
+                    monitor.resource_id
+                )
+            })?;
+        let url = format!(
+            "{}/subscriptions/{}/providers/Microsoft.CostManagement/query",
+            management_url(monitor),
+            subscription
+        );
+
+        let response = http::send_json::<CostResponse>(
+            self.client
+                .post(&url)
+                .bearer_auth(token)
+                .query(&[("api-version", COST_API_VERSION)])
+                .json(&json!({
+                    "type": "ActualCost",
+                    "timeframe": "Custom",
+                    "timePeriod": { "from": from.to_rfc3339(), "to": to.to_rfc3339() },
+                    "dataset": {
+                        "granularity": "Daily",
+                        "aggregation": { "totalCost": { "name": "Cost", "function": "Sum" } },
+                        "filter": {
+                            "dimensions": { "name": "ResourceId", "operator": "In", "values": [monitor.resource_id] }
+                        }
+                    }
+                }))
+        )
+        .await?;
+
+        let column = |name: &str| {
+            response
+                .properties
+                .columns
+                .iter()
+                .position(|c| c.name == name)
+        };
+        let (Some(cost), Some(date), Some(currency)) =
+            (column("Cost"), column("UsageDate"), column("Currency"))
+        else {
+            return Err(ProviderError::SchemaChanged(
+                "Cost Management response is missing columns".to_string(),
+            )
+            .into());
+        };
+
+        let mut metrics = Vec::new();
+        for row in &response.properties.rows {
+            let value = row.get(cost).and_then(serde_json::Value::as_f64);
+            // Dates come back as numbers such as 20261018.
+            let day = row
+                .get(date)
+                .and_then(serde_json::Value::as_u64)
+                .and_then(|d| NaiveDate::parse_from_str(&d.to_string(), "%Y%m%d").ok());
+            let (Some(value), Some(day)) = (value, day) else {
+                continue;
+            };
+            let currency = row
+                .get(currency)
+                .and_then(serde_json::Value::as_str)
+                .unwrap_or("usd")
+                .to_lowercase();
+
+            metrics.push(Metric {
+                id: uuid::Uuid::new_v4().to_string(),
+                provider_id: "azure_openai".to_string(),
+                metric_type: MetricType::CostUsd,
+                value,
+                unit: currency,
+                timestamp: day.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc(),
+                dimensions: HashMap::new(),
+            });
+        }
+
+        Ok(metrics)
+    }
+
+    /// Remaining tokens per minute for each chat deployment, if probing is
+    /// enabled and the endpoint has not been probed recently.
+    async fn probe_deployments(&self, resource_key: &str) -> Result<Vec<Metric>> {
+        if !self.tpm_probe {
+            return Ok(Vec::new());
+        }
+        let endpoint = self.endpoint()?.to_string();
+        let now = Utc::now();
+        {
+            let mut last_probes = LAST_PROBES
+                .get_or_init(|| Mutex::new(HashMap::new()))
+                .lock()
+                .unwrap();
+            if last_probes
+                .get(&endpoint)
+                .is_some_and(|at| (now - *at).num_seconds() < PROBE_INTERVAL_SECS)
+            {
+                return Ok(Vec::new());
+            }
+            last_probes.insert(endpoint, now);
+        }
+
+        let mut metrics = Vec::new();
+        for deployment in self.list_deployments(resource_key).await? {
+            // Embedding and other non-chat deployments reject the probe.
+            let Ok(Some(remaining)) = self.remaining_tokens(resource_key, &deployment.id).await
+            else {
+                continue;
+            };
+
+            metrics.push(Metric {
+                id: uuid::Uuid::new_v4().to_string(),
+                provider_id: "azure_openai".to_string(),
+                metric_type: MetricType::TpmRemaining,
+                value: remaining,
+                unit: "tokens_per_minute".to_string(),
+                timestamp: now,
+                dimensions: HashMap::from([
+                    ("deployment".to_string(), deployment.id.clone()),
+                    ("model".to_string(), deployment.model.clone()),
+                ]),
+            });
+        }
+
+        Ok(metrics)
+    }
+
+    /// Azure only reports the remaining token budget for the current minute on
+    /// inference responses, so this sends a one-token completion.
+    async fn remaining_tokens(&self, resource_key: &str, deployment: &str) -> Result<Option<f64>> {
+        let url = format!(
+            "{}/openai/deployments/{}/chat/completions",
+            self.endpoint()?,
+            deployment
+        );
+
+        let request = self
+            .client
+            .post(&url)
+            .header("api-key", resource_key)
+            .query(&[("api-version", self.api_version.as_str())])
+            .json(&json!({
+                "messages": [{ "role": "user", "content": "." }],
+                "max_tokens": 1,
+            }));
+
+        let response = match http::send(request).await {
+            Ok(response) => response,
+            // The deployment is out of tokens for this minute.
+            Err(ProviderError::RateLimited { .. }) => return Ok(Some(0.0)),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(response
+            .headers()
+            .get("x-ratelimit-remaining-tokens")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<f64>().ok()))
+    }
+}
+
+#[async_trait]
+impl ProviderAdapter for AzureOpenAIAdapter {
+    fn capabilities(&self) -> ProviderCapabilities {
+        let monitored = self.monitor.is_some();
+
+        ProviderCapabilities {
+            token_usage: monitored,
+            cost: monitored,
+            per_model_breakdown: monitored,
+            // Azure Monitor keeps platform metrics for 93 days.
+            backfill_days: if monitored { 90 } else { 0 },
+            min_poll_interval_secs: 60,
+            requires_api_key: true,
+            ..Default::default()
+        }
+    }
+
+    async fn fetch_usage(
+        &self,
+        api_key: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Metric>> {
+        let (resource_key, client_secret) = Self::split_key(api_key);
+        let mut metrics = self.probe_deployments(resource_key).await?;
+
+        let Some(monitor) = &self.monitor else {
+            return Ok(metrics);
+        };
+        let client_secret = client_secret.ok_or_else(|| {
+            anyhow!("Azure Monitor needs the service principal's secret after the resource key")
+        })?;
+
+        let token = self.management_token(monitor, client_secret).await?;
+        metrics.extend(self.fetch_tokens(monitor, &token, from, to).await?);
+        metrics.extend(self.fetch_cost(monitor, &token, from, to).await?);
+
+        Ok(metrics)
+    }
+
+    /// Listing deployments proves the resource key without spending tokens;
+    /// the service principal is checked with a one-hour metrics query.
+    async fn validate_key(&self, api_key: &str) -> Result<KeyValidation> {
+        let (resource_key, client_secret) = Self::split_key(api_key);
+        self.list_deployments(resource_key).await?;
+
+        let (Some(monitor), Some(client_secret)) = (&self.monitor, client_secret) else {
+            return Ok(KeyValidation::default());
+        };
+        let token = self.management_token(monitor, client_secret).await?;
+        let now = Utc::now();
+        let usage = http::permitted(
+            self.fetch_tokens(monitor, &token, now - chrono::Duration::hours(1), now)
+                .await,
+        )?;
+        let cost = http::permitted(
+            self.fetch_cost(monitor, &token, now - chrono::Duration::days(1), now)
+                .await,
+        )?;
+
+        Ok(KeyValidation {
+            usage_read: usage.is_some(),
+            cost_read: cost.is_some(),
+            ..Default::default()
+        })
+    }
+
+    async fn fetch_balance(&self, _api_key: &str) -> Result<Option<f64>> {
+        Ok(None)
+    }
+
+    async fn get_current_usage(&self, api_key: &str, metrics: &[Metric]) -> Result<ProviderUsage> {
+        let now = Utc::now();
+        let start_of_day = now.date_naive().and_hms_opt(0, 0, 0).unwrap().and_utc();
+        let start_of_month = now
+            .date_naive()
+            .with_day(1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+            .and_utc();
+
+        // Fails early on a missing endpoint or a rejected key.
+        self.list_deployments(Self::split_key(api_key).0).await?;
+        let (today_tokens, today_cost) = super::totals_since(metrics, start_of_day);
+        let (mtd_tokens, mtd_cost) = super::totals_since(metrics, start_of_month);
+
+        Ok(ProviderUsage {
+            provider: Provider {
+                id: "azure_openai".to_string(),
+                name: "Azure OpenAI".to_string(),
+                provider_type: ProviderType::AzureOpenAI,
+                api_key_ref: Some("azure_openai_key".to_string()),
+                enabled: true,
+                created_at: Utc::now(),
+                settings: ProviderSettings {
+                    base_url: self.base_url.clone(),
+                    api_version: Some(self.api_version.clone()),
+                    azure_monitor: self.monitor.clone(),
+                    azure_tpm_probe: self.tpm_probe,
+                    ..Default::default()
+                },
+            },
+            today_tokens,
+            today_cost,
+            mtd_tokens,
+            mtd_cost,
+            balance: None,
+            credits: None,
+            budget_used_percentage: None,
+            last_error: None,
+            capabilities: self.capabilities(),
+            last_synced_at: None,
+            stale: false,
+            projected_run_out_at: None,
+            projected_period_spend: None,
+            notional_mtd_cost: None,
+        })
+    }
+}
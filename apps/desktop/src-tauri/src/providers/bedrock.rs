@@ -0,0 +1,355 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Datelike, Utc};
+use reqwest::{Client, Url};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+use super::http;
+use super::sigv4::{self, AwsCredentials};
+use super::ProviderAdapter;
+use crate::models::{
+    Metric, MetricType, Provider, ProviderCapabilities, ProviderSettings, ProviderType,
+    ProviderUsage,
+};
+
+const DEFAULT_REGION: &str = "us-east-1";
+const NAMESPACE: &str = "AWS/Bedrock";
+
+/// Bedrock invocations and token counts per model, read from CloudWatch with
+/// SigV4-signed JSON protocol requests. The stored key is an
+/// `AwsCredentials` string; the endpoint can be pointed at a local stand-in.
+pub struct BedrockAdapter {
+    client: Client,
+    base_url: String,
+    region: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ListMetricsResponse {
+    #[serde(default)]
+    metrics: Vec<CloudWatchMetric>,
+    next_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct CloudWatchMetric {
+    #[serde(default)]
+    dimensions: Vec<Dimension>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Dimension {
+    name: String,
+    value: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct GetMetricDataResponse {
+    #[serde(default)]
+    metric_data_results: Vec<MetricDataResult>,
+    next_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct MetricDataResult {
+    id: String,
+    #[serde(default)]
+    timestamps: Vec<f64>,
+    #[serde(default)]
+    values: Vec<f64>,
+}
+
+impl BedrockAdapter {
+    pub fn new(settings: &ProviderSettings) -> Result<Self> {
+        let region = settings
+            .aws_region
+            .clone()
+            .unwrap_or_else(|| DEFAULT_REGION.to_string());
+
+        Ok(Self {
+            client: http::build_client(&settings.http)?,
+            base_url: http::base_url(
+                settings,
+                &format!("https://monitoring.{}.amazonaws.com", region),
+            ),
+            region,
+        })
+    }
+
+    async fn call(
+        &self,
+        credentials: &AwsCredentials,
+        operation: &str,
+        payload: &Value,
+    ) -> Result<Value> {
+        let url = Url::parse(&self.base_url)?;
+        let body = serde_json::to_vec(payload)?;
+        let target = format!("GraniteServiceVersion20100801.{}", operation);
+
+        let headers = sigv4::sign_post(
+            credentials,
+            &url,
+            &self.region,
+            "monitoring",
+            &[
+                ("content-type", "application/x-amz-json-1.0"),
+                ("x-amz-target", &target),
+            ],
+            &body,
+            Utc::now(),
+        )?;
+
+        let mut request = self.client.post(url).body(body);
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+
+        Ok(http::send_json::<Value>(request).await?)
+    }
+
+    /// Model IDs that have reported Bedrock invocations to CloudWatch.
+    async fn list_models(&self, credentials: &AwsCredentials) -> Result<Vec<String>> {
+        let mut models = Vec::new();
+        let mut next_token: Option<String> = None;
+
+        loop {
+            let mut payload = json!({
+                "Namespace": NAMESPACE,
+                "MetricName": "Invocations",
+            });
+            if let Some(token) = &next_token {
+                payload["NextToken"] = json!(token);
+            }
+
+            let response: ListMetricsResponse =
+                serde_json::from_value(self.call(credentials, "ListMetrics", &payload).await?)?;
+
+            for metric in response.metrics {
+                for dimension in metric.dimensions {
+                    if dimension.name == "ModelId" && !models.contains(&dimension.value) {
+                        models.push(dimension.value);
+                    }
+                }
+            }
+
+            match response.next_token {
+                Some(token) => next_token = Some(token),
+                None => break,
+            }
+        }
+
+        Ok(models)
+    }
+
+    /// USD per million input and output tokens for on-demand Claude models,
+    /// used to estimate cost since CloudWatch carries no billing data.
+    fn price_per_million(model_id: &str) -> (f64, f64) {
+        if model_id.contains("opus") {
+            (15.0, 75.0)
+        } else if model_id.contains("sonnet") {
+            (3.0, 15.0)
+        } else if model_id.contains("claude-3-5-haiku") || model_id.contains("claude-haiku") {
+            (0.80, 4.0)
+        } else if model_id.contains("claude-3-haiku") {
+            (0.25, 1.25)
+        } else {
+            (0.0, 0.0)
+        }
+    }
+}
+
+#[async_trait]
+impl ProviderAdapter for BedrockAdapter {
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities {
+            token_usage: true,
+            cost: true,
+            estimated_cost: true,
+            per_model_breakdown: true,
+            // CloudWatch keeps datapoints for 455 days, but models are found
+            // through ListMetrics, which only returns metrics with data in the
+            // last two weeks; older days would come back empty.
+            backfill_days: 14,
+            // GetMetricData is billed per metric requested.
+            min_poll_interval_secs: 300,
+            requires_api_key: true,
+            ..Default::default()
+        }
+    }
+
+    async fn fetch_usage(
+        &self,
+        api_key: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Metric>> {
+        let credentials = AwsCredentials::parse(api_key)?;
+        let mut metrics = Vec::new();
+
+        let models = self.list_models(&credentials).await?;
+        if models.is_empty() {
+            return Ok(metrics);
+        }
+
+        let series = [
+            ("inv", "Invocations", MetricType::Requests, "requests"),
+            ("in", "InputTokenCount", MetricType::TokensIn, "tokens"),
+            ("out", "OutputTokenCount", MetricType::TokensOut, "tokens"),
+        ];
+
+        let mut queries = Vec::new();
+        for (index, model) in models.iter().enumerate() {
+            for (suffix, metric_name, _, _) in &series {
+                queries.push(json!({
+                    "Id": format!("m{}_{}", index, suffix),
+                    "MetricStat": {
+                        "Metric": {
+                            "Namespace": NAMESPACE,
+                            "MetricName": metric_name,
+                            "Dimensions": [{ "Name": "ModelId", "Value": model }],
+                        },
+                        "Period": 86400,
+                        "Stat": "Sum",
+                    },
+                    "ReturnData": true,
+                }));
+            }
+        }
+
+        // GetMetricData takes at most 500 queries per request.
+        for chunk in queries.chunks(500) {
+            let mut next_token: Option<String> = None;
+
+            loop {
+                let mut payload = json!({
+                    "MetricDataQueries": chunk,
+                    "StartTime": from.timestamp(),
+                    "EndTime": to.timestamp(),
+                });
+                if let Some(token) = &next_token {
+                    payload["NextToken"] = json!(token);
+                }
+
+                let response: GetMetricDataResponse = serde_json::from_value(
+                    self.call(&credentials, "GetMetricData", &payload).await?,
+                )?;
+
+                for result in response.metric_data_results {
+                    let Some((index, suffix)) = result
+                        .id
+                        .strip_prefix('m')
+                        .and_then(|id| id.split_once('_'))
+                    else {
+                        continue;
+                    };
+                    let Some(model) = index.parse::<usize>().ok().and_then(|i| models.get(i))
+                    else {
+                        continue;
+                    };
+                    let Some((_, _, metric_type, unit)) =
+                        series.iter().find(|(s, _, _, _)| *s == suffix)
+                    else {
+                        continue;
+                    };
+                    let (input_price, output_price) = Self::price_per_million(model);
+
+                    for (timestamp, value) in result.timestamps.iter().zip(result.values.iter()) {
+                        let timestamp =
+                            DateTime::from_timestamp(*timestamp as i64, 0).unwrap_or_else(Utc::now);
+
+                        metrics.push(Metric {
+                            id: uuid::Uuid::new_v4().to_string(),
+                            provider_id: "bedrock".to_string(),
+                            metric_type: metric_type.clone(),
+                            value: *value,
+                            unit: unit.to_string(),
+                            timestamp,
+                            dimensions: HashMap::from([("model".to_string(), model.clone())]),
+                        });
+
+                        let price = match metric_type {
+                            MetricType::TokensIn => input_price,
+                            MetricType::TokensOut => output_price,
+                            _ => continue,
+                        };
+                        metrics.push(Metric {
+                            id: uuid::Uuid::new_v4().to_string(),
+                            provider_id: "bedrock".to_string(),
+                            metric_type: MetricType::CostUsd,
+                            value: value * price / 1_000_000.0,
+                            unit: "usd".to_string(),
+                            timestamp,
+                            dimensions: HashMap::from([
+                                ("model".to_string(), model.clone()),
+                                ("estimated".to_string(), "true".to_string()),
+                            ]),
+                        });
+                    }
+                }
+
+                match response.next_token {
+                    Some(token) => next_token = Some(token),
+                    None => break,
+                }
+            }
+        }
+
+        Ok(metrics)
+    }
+
+    async fn fetch_balance(&self, _api_key: &str) -> Result<Option<f64>> {
+        Ok(None)
+    }
+
+    async fn get_current_usage(&self, _api_key: &str, metrics: &[Metric]) -> Result<ProviderUsage> {
+        let now = Utc::now();
+        let start_of_day = now.date_naive().and_hms_opt(0, 0, 0).unwrap().and_utc();
+        let start_of_month = now
+            .date_naive()
+            .with_day(1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+            .and_utc();
+
+        let (today_tokens, today_cost) = super::totals_since(metrics, start_of_day);
+        let (mtd_tokens, mtd_cost) = super::totals_since(metrics, start_of_month);
+
+        Ok(ProviderUsage {
+            provider: Provider {
+                id: "bedrock".to_string(),
+                name: "AWS Bedrock".to_string(),
+                provider_type: ProviderType::Bedrock,
+                api_key_ref: Some("bedrock_key".to_string()),
+                enabled: true,
+                created_at: Utc::now(),
+                settings: ProviderSettings {
+                    base_url: Some(self.base_url.clone()),
+                    aws_region: Some(self.region.clone()),
+                    ..Default::default()
+                },
+            },
+            today_tokens,
+            today_cost,
+            mtd_tokens,
+            mtd_cost,
+            balance: None,
+            credits: None,
+            budget_used_percentage: None,
+            last_error: None,
+            capabilities: self.capabilities(),
+            last_synced_at: None,
+            stale: false,
+            projected_run_out_at: None,
+            projected_period_spend: None,
+            notional_mtd_cost: None,
+        })
+    }
+}
@@ -0,0 +1,389 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use reqwest::{Client, RequestBuilder};
+use serde_json::Value;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::{Mutex, OnceLock};
+
+use super::http::{self, ProviderError};
+use super::ProviderAdapter;
+use crate::models::{
+    AuthStyle, CustomProviderConfig, Metric, MetricType, Provider, ProviderCapabilities,
+    ProviderSettings, ProviderType, ProviderUsage,
+};
+
+/// Totals for each day that has ended, with when they were read.
+type DayTotals = HashMap<NaiveDate, (DateTime<Utc>, Vec<Metric>)>;
+
+/// Only days a month-to-date poll asks for are kept; backfilled days are
+/// read once and stored.
+const CACHED_DAYS: i64 = 31;
+/// Vendors can revise closed days, e.g. when late requests are billed, so
+/// they are read again after this.
+const CACHED_DAY_HOURS: i64 = 6;
+
+// Day totals keyed by a hash of the endpoint and key, so most polls only ask
+// for today again. Adapters are created per poll, so this has to outlive them.
+static CLOSED_DAYS: OnceLock<Mutex<HashMap<u64, DayTotals>>> = OnceLock::new();
+
+/// User-configured adapter for vendors with OpenAI-style APIs (Groq, Together,
+/// Fireworks, DeepSeek, Mistral, xAI, ...). Endpoints and response fields are
+/// taken from `CustomProviderConfig`; anything left unset is not reported.
+pub struct CustomAdapter {
+    client: Client,
+    base_url: Option<String>,
+    config: CustomProviderConfig,
+}
+
+impl CustomAdapter {
+    pub fn new(settings: &ProviderSettings) -> Result<Self> {
+        Ok(Self {
+            client: http::build_client(&settings.http)?,
+            base_url: settings
+                .base_url
+                .as_ref()
+                .map(|url| url.trim_end_matches('/').to_string()),
+            config: settings.custom.clone().unwrap_or_default(),
+        })
+    }
+
+    fn get(&self, path: &str, api_key: &str) -> Result<RequestBuilder> {
+        let base_url = self
+            .base_url
+            .as_deref()
+            .ok_or_else(|| ProviderError::Misconfigured("no base URL configured".to_string()))?;
+        let request = self.client.get(format!("{}{}", base_url, path));
+
+        Ok(match &self.config.auth_style {
+            AuthStyle::Bearer => request.bearer_auth(api_key),
+            AuthStyle::Header { name } => request.header(name.as_str(), api_key),
+            AuthStyle::Query { name } => request.query(&[(name.as_str(), api_key)]),
+        })
+    }
+
+    /// Reads a number at `pointer`, accepting vendors that encode amounts as
+    /// strings (DeepSeek reports `"total_balance": "110.00"`). `None` if the
+    /// field is absent or null; anything else that is not a number means the
+    /// response is not shaped as configured.
+    fn read_number(body: &Value, pointer: &str) -> Result<Option<f64>, ProviderError> {
+        let value = match body.pointer(pointer) {
+            None | Some(Value::Null) => return Ok(None),
+            Some(Value::Number(n)) => n.as_f64(),
+            Some(Value::String(s)) => s.trim().parse().ok(),
+            Some(_) => None,
+        };
+
+        value.map(Some).ok_or_else(|| {
+            ProviderError::SchemaChanged(format!("the value at {} is not a number", pointer))
+        })
+    }
+
+    /// Totals for one day, stamped at its start.
+    async fn fetch_day(
+        &self,
+        api_key: &str,
+        usage_path: &str,
+        day: NaiveDate,
+    ) -> Result<Vec<Metric>> {
+        let date = day.format("%Y-%m-%d").to_string();
+        let path = usage_path.replace("{from}", &date).replace("{to}", &date);
+
+        let body = http::send_json::<Value>(self.get(&path, api_key)?).await?;
+        let timestamp = day.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc();
+
+        let fields = [
+            (
+                &self.config.tokens_in_pointer,
+                MetricType::TokensIn,
+                "tokens",
+            ),
+            (
+                &self.config.tokens_out_pointer,
+                MetricType::TokensOut,
+                "tokens",
+            ),
+            (&self.config.cost_pointer, MetricType::CostUsd, "usd"),
+        ];
+
+        let mut metrics = Vec::new();
+        for (pointer, metric_type, unit) in fields {
+            let Some(pointer) = pointer else {
+                continue;
+            };
+            // Vendors leave out the fields of a day without usage.
+            let value = Self::read_number(&body, pointer)?.unwrap_or(0.0);
+            metrics.push(Self::metric(metric_type, value, unit, timestamp));
+        }
+
+        Ok(metrics)
+    }
+
+    fn metric(metric_type: MetricType, value: f64, unit: &str, timestamp: DateTime<Utc>) -> Metric {
+        Metric {
+            id: uuid::Uuid::new_v4().to_string(),
+            provider_id: "custom".to_string(),
+            metric_type,
+            value,
+            unit: unit.to_string(),
+            timestamp,
+            dimensions: HashMap::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl ProviderAdapter for CustomAdapter {
+    fn capabilities(&self) -> ProviderCapabilities {
+        let config = &self.config;
+        let has_usage = config.usage_path.is_some();
+        let daily = config
+            .usage_path
+            .as_deref()
+            .is_some_and(|p| p.contains("{from}"));
+
+        ProviderCapabilities {
+            balance: config.balance_path.is_some() && config.balance_pointer.is_some(),
+            token_usage: has_usage
+                && (config.tokens_in_pointer.is_some() || config.tokens_out_pointer.is_some()),
+            cost: has_usage && config.cost_pointer.is_some(),
+            // A usage path with a date range can be walked back one day at a time.
+            backfill_days: if daily { 90 } else { 0 },
+            month_to_date_only: has_usage && !daily,
+            min_poll_interval_secs: 60,
+            requires_api_key: true,
+            ..Default::default()
+        }
+    }
+
+    /// Usage endpoints return a total for the requested range, so usage is
+    /// requested one day at a time and each total is stamped at the start of
+    /// its day; polling again replaces the day's row instead of adding one.
+    /// A path without `{from}` is taken to report the month so far, stamped
+    /// at the start of the month.
+    async fn fetch_usage(
+        &self,
+        api_key: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Metric>> {
+        let Some(usage_path) = &self.config.usage_path else {
+            return Ok(Vec::new());
+        };
+
+        if !usage_path.contains("{from}") {
+            let start_of_month = to.date_naive().with_day(1).unwrap_or(to.date_naive());
+            return self.fetch_day(api_key, usage_path, start_of_month).await;
+        }
+
+        let endpoint = {
+            let mut hasher = DefaultHasher::new();
+            (&self.base_url, usage_path, api_key).hash(&mut hasher);
+            hasher.finish()
+        };
+        let now = Utc::now();
+        let today = now.date_naive();
+        let oldest_cached = today - chrono::Duration::days(CACHED_DAYS);
+        // `to` is exclusive, so a range ending at midnight stops the day before.
+        let last_day = (to - chrono::Duration::nanoseconds(1)).date_naive();
+
+        let mut metrics = Vec::new();
+        let mut day = from.date_naive();
+        while day <= last_day {
+            let cached = CLOSED_DAYS
+                .get_or_init(|| Mutex::new(HashMap::new()))
+                .lock()
+                .unwrap()
+                .get(&endpoint)
+                .and_then(|days| days.get(&day).cloned())
+                .filter(|(read_at, _)| now - *read_at < chrono::Duration::hours(CACHED_DAY_HOURS))
+                .map(|(_, day_metrics)| day_metrics);
+
+            let day_metrics = match cached {
+                Some(day_metrics) => day_metrics,
+                None => {
+                    let day_metrics = self.fetch_day(api_key, usage_path, day).await?;
+                    if day < today && day >= oldest_cached {
+                        let mut cache = CLOSED_DAYS
+                            .get_or_init(|| Mutex::new(HashMap::new()))
+                            .lock()
+                            .unwrap();
+                        let days = cache.entry(endpoint).or_default();
+                        days.retain(|cached_day, _| *cached_day >= oldest_cached);
+                        days.insert(day, (now, day_metrics.clone()));
+                    }
+                    day_metrics
+                }
+            };
+            metrics.extend(day_metrics);
+
+            let Some(next) = day.succ_opt() else {
+                break;
+            };
+            day = next;
+        }
+
+        Ok(metrics)
+    }
+
+    async fn fetch_balance(&self, api_key: &str) -> Result<Option<f64>> {
+        let (Some(path), Some(pointer)) = (&self.config.balance_path, &self.config.balance_pointer)
+        else {
+            return Ok(None);
+        };
+
+        let body = http::send_json::<Value>(self.get(path, api_key)?).await?;
+
+        let balance = Self::read_number(&body, pointer)?.ok_or_else(|| {
+            ProviderError::SchemaChanged(format!("no balance at {} in the response", pointer))
+        })?;
+
+        Ok(Some(balance))
+    }
+
+    async fn get_current_usage(&self, api_key: &str, metrics: &[Metric]) -> Result<ProviderUsage> {
+        let now = Utc::now();
+        let start_of_day = now.date_naive().and_hms_opt(0, 0, 0).unwrap().and_utc();
+        let start_of_month = now
+            .date_naive()
+            .with_day(1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+            .and_utc();
+
+        let (mtd_tokens, mtd_cost) = super::totals_since(metrics, start_of_month);
+        let balance = self.fetch_balance(api_key).await?;
+        let capabilities = self.capabilities();
+        // A month-to-date total says nothing about today, even on the 1st.
+        let (today_tokens, today_cost) = if capabilities.month_to_date_only {
+            (0, 0.0)
+        } else {
+            super::totals_since(metrics, start_of_day)
+        };
+
+        Ok(ProviderUsage {
+            provider: Provider {
+                id: "custom".to_string(),
+                name: "Custom".to_string(),
+                provider_type: ProviderType::Custom,
+                api_key_ref: Some("custom_key".to_string()),
+                enabled: true,
+                created_at: Utc::now(),
+                settings: ProviderSettings {
+                    base_url: self.base_url.clone(),
+                    custom: Some(self.config.clone()),
+                    ..Default::default()
+                },
+            },
+            today_tokens,
+            today_cost,
+            mtd_tokens,
+            mtd_cost,
+            balance,
+            credits: None,
+            budget_used_percentage: None,
+            last_error: None,
+            capabilities,
+            last_synced_at: None,
+            stale: false,
+            projected_run_out_at: None,
+            projected_period_spend: None,
+            notional_mtd_cost: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn adapter(config: CustomProviderConfig, base_url: Option<&str>) -> CustomAdapter {
+        CustomAdapter::new(&ProviderSettings {
+            base_url: base_url.map(str::to_string),
+            custom: Some(config),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn reads_numbers_and_numeric_strings() {
+        let body = json!({
+            "balance_infos": [{ "total_balance": "110.00" }],
+            "usage": { "cost": 1.25, "tokens": 300, "empty": null },
+        });
+
+        assert_eq!(
+            CustomAdapter::read_number(&body, "/balance_infos/0/total_balance").unwrap(),
+            Some(110.0)
+        );
+        assert_eq!(
+            CustomAdapter::read_number(&body, "/usage/cost").unwrap(),
+            Some(1.25)
+        );
+        assert_eq!(
+            CustomAdapter::read_number(&body, "/usage/tokens").unwrap(),
+            Some(300.0)
+        );
+    }
+
+    #[test]
+    fn absent_values_are_none_and_others_are_schema_errors() {
+        let body = json!({ "usage": { "empty": null, "cost": "n/a", "tokens": { "in": 1 } } });
+
+        assert_eq!(
+            CustomAdapter::read_number(&body, "/usage/empty").unwrap(),
+            None
+        );
+        assert_eq!(
+            CustomAdapter::read_number(&body, "/usage/missing").unwrap(),
+            None
+        );
+        assert!(matches!(
+            CustomAdapter::read_number(&body, "/usage/cost"),
+            Err(ProviderError::SchemaChanged(_))
+        ));
+        assert!(matches!(
+            CustomAdapter::read_number(&body, "/usage/tokens"),
+            Err(ProviderError::SchemaChanged(_))
+        ));
+    }
+
+    #[test]
+    fn missing_base_url_is_a_settings_error() {
+        let error = adapter(CustomProviderConfig::default(), None)
+            .get("/usage", "key")
+            .unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<ProviderError>(),
+            Some(ProviderError::Misconfigured(_))
+        ));
+    }
+
+    #[test]
+    fn capabilities_follow_the_usage_path() {
+        let config = |usage_path: &str| CustomProviderConfig {
+            usage_path: Some(usage_path.to_string()),
+            cost_pointer: Some("/cost".to_string()),
+            ..Default::default()
+        };
+
+        let daily = adapter(
+            config("/usage?from={from}&to={to}"),
+            Some("https://api.example.com"),
+        )
+        .capabilities();
+        assert!(daily.cost && !daily.token_usage && !daily.month_to_date_only);
+        assert_eq!(daily.backfill_days, 90);
+
+        let monthly =
+            adapter(config("/usage/month"), Some("https://api.example.com")).capabilities();
+        assert!(monthly.cost && monthly.month_to_date_only);
+        assert_eq!(monthly.backfill_days, 0);
+    }
+}
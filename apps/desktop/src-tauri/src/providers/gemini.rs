@@ -0,0 +1,329 @@
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Datelike, Utc};
+use reqwest::Client;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use super::http::{self, ProviderError};
+use super::ProviderAdapter;
+use crate::models::{
+    Metric, MetricType, Provider, ProviderCapabilities, ProviderSettings, ProviderType,
+    ProviderUsage,
+};
+
+const TOKEN_COUNT_METRIC: &str = "aiplatform.googleapis.com/publisher/online_serving/token_count";
+const TOKEN_URI: &str = "https://oauth2.googleapis.com/token";
+
+/// Gemini through Vertex AI. Token counts come from Cloud Monitoring, which
+/// needs OAuth credentials for a project with Vertex AI enabled. Google AI
+/// Studio keys have no usage endpoint, so without a project the adapter
+/// reports nothing and the provider is tracked against its budget only.
+pub struct GeminiAdapter {
+    client: Client,
+    base_url: String,
+    token_uri: Option<String>,
+    project_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TimeSeriesResponse {
+    #[serde(default)]
+    time_series: Vec<TimeSeries>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TimeSeries {
+    metric: MetricDescriptor,
+    resource: MonitoredResource,
+    points: Vec<Point>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MetricDescriptor {
+    #[serde(default)]
+    labels: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MonitoredResource {
+    #[serde(default)]
+    labels: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Point {
+    interval: PointInterval,
+    value: PointValue,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PointInterval {
+    end_time: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PointValue {
+    // Cloud Monitoring encodes int64 values as JSON strings.
+    int64_value: Option<String>,
+}
+
+/// What the user stored as the Gemini key.
+enum Credentials {
+    /// An OAuth access token, e.g. from `gcloud auth print-access-token`.
+    /// It expires after about an hour and cannot be refreshed.
+    AccessToken(String),
+    /// The JSON written by `gcloud auth application-default login`, whose
+    /// refresh token is exchanged for access tokens as they expire.
+    AuthorizedUser(AuthorizedUser),
+}
+
+#[derive(Debug, Deserialize)]
+struct AuthorizedUser {
+    client_id: String,
+    client_secret: String,
+    refresh_token: String,
+    #[serde(default)]
+    token_uri: Option<String>,
+}
+
+impl Credentials {
+    fn parse(api_key: &str) -> Result<Self> {
+        if !api_key.trim_start().starts_with('{') {
+            return Ok(Self::AccessToken(api_key.to_string()));
+        }
+
+        let value: serde_json::Value =
+            serde_json::from_str(api_key).context("Invalid Google credentials JSON")?;
+        match value.get("type").and_then(serde_json::Value::as_str) {
+            Some("authorized_user") => Ok(Self::AuthorizedUser(serde_json::from_value(value)?)),
+            other => bail!(
+                "Unsupported Google credentials type {:?}; use application default credentials or an access token",
+                other.unwrap_or_default()
+            ),
+        }
+    }
+}
+
+impl GeminiAdapter {
+    pub fn new(settings: &ProviderSettings) -> Result<Self> {
+        Ok(Self {
+            client: http::build_client(&settings.http)?,
+            base_url: http::base_url(settings, "https://monitoring.googleapis.com/v3"),
+            token_uri: settings.gcp_token_uri.clone(),
+            project_id: settings.gcp_project_id.clone(),
+        })
+    }
+
+    /// USD per million input and output tokens, used to estimate cost since
+    /// Vertex AI billing is only available through a BigQuery export.
+    fn price_per_million(model: &str) -> (f64, f64) {
+        if model.starts_with("gemini-2.5-pro") {
+            (1.25, 10.0)
+        } else if model.starts_with("gemini-2.5-flash") {
+            (0.30, 2.50)
+        } else if model.starts_with("gemini-2.0-flash") {
+            (0.10, 0.40)
+        } else if model.starts_with("gemini-1.5-pro") {
+            (1.25, 5.0)
+        } else if model.starts_with("gemini-1.5-flash") {
+            (0.075, 0.30)
+        } else {
+            (0.0, 0.0)
+        }
+    }
+
+    /// An access token for `credentials`, refreshing it if needed.
+    async fn access_token(&self, credentials: &Credentials) -> Result<String, ProviderError> {
+        let user = match credentials {
+            Credentials::AccessToken(token) => return Ok(token.clone()),
+            Credentials::AuthorizedUser(user) => user,
+        };
+
+        let token_uri = self.token_uri.as_ref().or(user.token_uri.as_ref());
+        let request = self
+            .client
+            .post(http::url_or(token_uri.map(String::as_str), TOKEN_URI))
+            .form(&[
+                ("grant_type", "refresh_token"),
+                ("client_id", user.client_id.as_str()),
+                ("client_secret", user.client_secret.as_str()),
+                ("refresh_token", user.refresh_token.as_str()),
+            ]);
+        http::oauth_token(&user.refresh_token, request).await
+    }
+}
+
+#[async_trait]
+impl ProviderAdapter for GeminiAdapter {
+    fn capabilities(&self) -> ProviderCapabilities {
+        // Without a project only budgets can be tracked.
+        let monitored = self.project_id.is_some();
+
+        ProviderCapabilities {
+            token_usage: monitored,
+            cost: monitored,
+            estimated_cost: monitored,
+            per_model_breakdown: monitored,
+            // Cloud Monitoring keeps metric data for six weeks.
+            backfill_days: if monitored { 42 } else { 0 },
+            min_poll_interval_secs: 60,
+            requires_api_key: true,
+            ..Default::default()
+        }
+    }
+
+    async fn fetch_usage(
+        &self,
+        api_key: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Metric>> {
+        let mut metrics = Vec::new();
+
+        let Some(project_id) = &self.project_id else {
+            return Ok(metrics);
+        };
+
+        let credentials = Credentials::parse(api_key)?;
+        let access_token = self.access_token(&credentials).await?;
+
+        let url = format!("{}/projects/{}/timeSeries", self.base_url, project_id);
+        let params = [
+            (
+                "filter",
+                format!("metric.type = \"{}\"", TOKEN_COUNT_METRIC),
+            ),
+            ("interval.startTime", from.to_rfc3339()),
+            ("interval.endTime", to.to_rfc3339()),
+            ("aggregation.alignmentPeriod", "86400s".to_string()),
+            ("aggregation.perSeriesAligner", "ALIGN_SUM".to_string()),
+        ];
+
+        let response = match http::send_json::<TimeSeriesResponse>(
+            self.client
+                .get(&url)
+                .bearer_auth(&access_token)
+                .query(&params),
+        )
+        .await
+        {
+            Ok(response) => response,
+            Err(ProviderError::Unauthorized { status: 401, body }) => {
+                return Err(match credentials {
+                    Credentials::AccessToken(_) => ProviderError::TokenExpired,
+                    // Revoked early; get a new token on the next poll.
+                    Credentials::AuthorizedUser(user) => {
+                        http::forget_oauth_token(&user.refresh_token);
+                        ProviderError::Unauthorized { status: 401, body }
+                    }
+                }
+                .into());
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        for series in response.time_series {
+            let model = series
+                .resource
+                .labels
+                .get("model_user_id")
+                .cloned()
+                .unwrap_or_else(|| "unknown".to_string());
+            let is_output = series.metric.labels.get("type").map(String::as_str) == Some("output");
+            let (input_price, output_price) = Self::price_per_million(&model);
+
+            for point in series.points {
+                let timestamp = DateTime::parse_from_rfc3339(&point.interval.end_time)
+                    .map(|dt| dt.with_timezone(&Utc))
+                    .unwrap_or_else(|_| Utc::now());
+                let tokens = point
+                    .value
+                    .int64_value
+                    .and_then(|v| v.parse::<u64>().ok())
+                    .unwrap_or(0);
+
+                metrics.push(Metric {
+                    id: uuid::Uuid::new_v4().to_string(),
+                    provider_id: "gemini".to_string(),
+                    metric_type: if is_output {
+                        MetricType::TokensOut
+                    } else {
+                        MetricType::TokensIn
+                    },
+                    value: tokens as f64,
+                    unit: "tokens".to_string(),
+                    timestamp,
+                    dimensions: HashMap::from([("model".to_string(), model.clone())]),
+                });
+
+                let price = if is_output { output_price } else { input_price };
+                metrics.push(Metric {
+                    id: uuid::Uuid::new_v4().to_string(),
+                    provider_id: "gemini".to_string(),
+                    metric_type: MetricType::CostUsd,
+                    value: tokens as f64 * price / 1_000_000.0,
+                    unit: "usd".to_string(),
+                    timestamp,
+                    dimensions: HashMap::from([
+                        ("model".to_string(), model.clone()),
+                        ("estimated".to_string(), "true".to_string()),
+                    ]),
+                });
+            }
+        }
+
+        Ok(metrics)
+    }
+
+    async fn fetch_balance(&self, _api_key: &str) -> Result<Option<f64>> {
+        Ok(None)
+    }
+
+    async fn get_current_usage(&self, _api_key: &str, metrics: &[Metric]) -> Result<ProviderUsage> {
+        let now = Utc::now();
+        let start_of_day = now.date_naive().and_hms_opt(0, 0, 0).unwrap().and_utc();
+        let start_of_month = now
+            .date_naive()
+            .with_day(1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+            .and_utc();
+
+        let (today_tokens, today_cost) = super::totals_since(metrics, start_of_day);
+        let (mtd_tokens, mtd_cost) = super::totals_since(metrics, start_of_month);
+
+        Ok(ProviderUsage {
+            provider: Provider {
+                id: "gemini".to_string(),
+                name: "Gemini".to_string(),
+                provider_type: ProviderType::Gemini,
+                api_key_ref: Some("gemini_key".to_string()),
+                enabled: true,
+                created_at: Utc::now(),
+                settings: ProviderSettings {
+                    gcp_project_id: self.project_id.clone(),
+                    ..Default::default()
+                },
+            },
+            today_tokens,
+            today_cost,
+            mtd_tokens,
+            mtd_cost,
+            balance: None,
+            credits: None,
+            budget_used_percentage: None,
+            last_error: None,
+            capabilities: self.capabilities(),
+            last_synced_at: None,
+            stale: false,
+            projected_run_out_at: None,
+            projected_period_spend: None,
+            notional_mtd_cost: None,
+        })
+    }
+}
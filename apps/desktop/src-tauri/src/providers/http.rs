@@ -0,0 +1,403 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use reqwest::{Certificate, Client, Proxy, RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use thiserror::Error;
+use tracing::warn;
+
+use crate::models::{HttpSettings, ProviderSettings};
+
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+const MAX_RETRIES: u32 = 3;
+const BASE_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(8);
+// A longer Retry-After is handed back to MonitorService instead of blocking the poll.
+const MAX_INLINE_RETRY_AFTER: Duration = Duration::from_secs(30);
+/// OAuth access tokens are refreshed this long before they expire.
+const TOKEN_EXPIRY_MARGIN_SECS: i64 = 300;
+
+/// An access token and when it expires.
+type CachedToken = (String, DateTime<Utc>);
+
+// OAuth access tokens keyed by the credential they were issued for.
+// Adapters are created per poll, so the tokens have to outlive them.
+static ACCESS_TOKENS: OnceLock<Mutex<HashMap<String, CachedToken>>> = OnceLock::new();
+
+/// Why a provider request failed, so callers can tell a bad key from an
+/// outage. Adapters return these inside `anyhow::Error`; use `downcast_ref`.
+#[derive(Debug, Error)]
+pub enum ProviderError {
+    #[error("Provider rejected the API key ({status})")]
+    Unauthorized { status: u16, body: String },
+    /// A short-lived access token that can no longer be used and cannot be
+    /// refreshed; the user has to enter new credentials.
+    #[error("The access token has expired")]
+    TokenExpired,
+    #[error("Rate limited by provider")]
+    RateLimited { retry_after: Option<Duration> },
+    #[error("Provider unavailable: {reason}")]
+    UpstreamDown { status: Option<u16>, reason: String },
+    #[error("Unexpected response from provider: {0}")]
+    SchemaChanged(String),
+    #[error("Provider rejected the request ({status}): {body}")]
+    Rejected { status: u16, body: String },
+    /// The request could not be built, e.g. an invalid base URL or header
+    /// value in the provider's settings. Nothing was sent.
+    #[error("Invalid provider settings: {0}")]
+    Misconfigured(String),
+}
+
+impl ProviderError {
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Self::RateLimited { .. } | Self::UpstreamDown { .. })
+    }
+
+    async fn from_response(response: Response) -> Self {
+        let status = response.status();
+        let retry_after = parse_retry_after(&response);
+        let body = response.text().await.unwrap_or_default();
+
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Self::Unauthorized {
+                status: status.as_u16(),
+                body,
+            },
+            StatusCode::TOO_MANY_REQUESTS => Self::RateLimited { retry_after },
+            s if s.is_server_error() => Self::UpstreamDown {
+                status: Some(s.as_u16()),
+                reason: body,
+            },
+            s => Self::Rejected {
+                status: s.as_u16(),
+                body,
+            },
+        }
+    }
+}
+
+impl From<reqwest::Error> for ProviderError {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_builder() {
+            Self::Misconfigured(error.to_string())
+        } else if error.is_decode() {
+            Self::SchemaChanged(error.to_string())
+        } else {
+            Self::UpstreamDown {
+                status: error.status().map(|s| s.as_u16()),
+                reason: error.to_string(),
+            }
+        }
+    }
+}
+
+/// Runs a capability probe: `Ok(Some)` if the call worked, `Ok(None)` if the
+/// key is valid but not allowed to make it (403). A rejected key and any other
+/// failure are returned as errors.
+pub fn permitted<T, E: Into<anyhow::Error>>(result: Result<T, E>) -> Result<Option<T>> {
+    match result.map_err(Into::into) {
+        Ok(value) => Ok(Some(value)),
+        Err(e)
+            if matches!(
+                e.downcast_ref::<ProviderError>(),
+                Some(ProviderError::Unauthorized { status: 403, .. })
+            ) =>
+        {
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Sends `request`, retrying rate limits, 5xx responses and network failures
+/// with jittered exponential backoff. Non-success responses that survive the
+/// retries come back as a `ProviderError`.
+pub async fn send(request: RequestBuilder) -> Result<Response, ProviderError> {
+    let mut attempt = 0;
+
+    loop {
+        // Adapter requests have in-memory bodies, so they can always be cloned.
+        let attempt_request = request
+            .try_clone()
+            .expect("provider requests must not stream their body");
+
+        let error = match attempt_request.send().await {
+            Ok(response) if response.status().is_success() => return Ok(response),
+            Ok(response) => ProviderError::from_response(response).await,
+            Err(e) => ProviderError::from(e),
+        };
+
+        if !error.is_retryable() || attempt >= MAX_RETRIES {
+            return Err(error);
+        }
+
+        let delay = match &error {
+            ProviderError::RateLimited {
+                retry_after: Some(retry_after),
+            } => {
+                if *retry_after > MAX_INLINE_RETRY_AFTER {
+                    return Err(error);
+                }
+                *retry_after
+            }
+            _ => backoff(attempt),
+        };
+
+        warn!("Retrying provider request in {:?} after: {}", delay, error);
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// `send`, then decode the body as `T`. A body that does not match `T` is
+/// reported as `SchemaChanged`.
+pub async fn send_json<T: DeserializeOwned>(request: RequestBuilder) -> Result<T, ProviderError> {
+    let body = send(request).await?.bytes().await?;
+    serde_json::from_slice(&body).map_err(|e| ProviderError::SchemaChanged(e.to_string()))
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: i64,
+}
+
+/// An OAuth access token for `credential`, reusing the last one until it is
+/// about to expire. `request` is the token endpoint call, e.g. a refresh
+/// token or client credentials grant. A rejected grant comes back as
+/// `Unauthorized`.
+pub async fn oauth_token(
+    credential: &str,
+    request: RequestBuilder,
+) -> Result<String, ProviderError> {
+    let cache = ACCESS_TOKENS.get_or_init(|| Mutex::new(HashMap::new()));
+    let now = Utc::now();
+    if let Some((token, _)) = cache
+        .lock()
+        .unwrap()
+        .get(credential)
+        .filter(|(_, expires_at)| *expires_at > now)
+    {
+        return Ok(token.clone());
+    }
+
+    let response = match send_json::<TokenResponse>(request).await {
+        Ok(response) => response,
+        // OAuth servers answer 400 for revoked grants and bad client secrets.
+        Err(ProviderError::Rejected { status: 400, body })
+            if ["invalid_grant", "invalid_client", "unauthorized_client"]
+                .iter()
+                .any(|e| body.contains(e)) =>
+        {
+            return Err(ProviderError::Unauthorized { status: 401, body });
+        }
+        Err(e) => return Err(e),
+    };
+
+    let expires_at =
+        now + chrono::Duration::seconds(response.expires_in - TOKEN_EXPIRY_MARGIN_SECS);
+    cache.lock().unwrap().insert(
+        credential.to_string(),
+        (response.access_token.clone(), expires_at),
+    );
+    Ok(response.access_token)
+}
+
+/// Drops the cached token for `credential`, e.g. after the API rejected it.
+pub fn forget_oauth_token(credential: &str) {
+    if let Some(cache) = ACCESS_TOKENS.get() {
+        cache.lock().unwrap().remove(credential);
+    }
+}
+
+/// Full jitter: a random delay up to the exponential ceiling for this attempt.
+fn backoff(attempt: u32) -> Duration {
+    let ceiling = BASE_BACKOFF
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(MAX_BACKOFF);
+    let millis = rand::thread_rng().gen_range(0..=ceiling.as_millis() as u64);
+    Duration::from_millis(millis)
+}
+
+/// `Retry-After` as either delay-seconds or an HTTP date.
+fn parse_retry_after(response: &Response) -> Option<Duration> {
+    let value = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?;
+
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let at = DateTime::parse_from_rfc2822(value.trim())
+        .ok()?
+        .with_timezone(&Utc);
+    (at - Utc::now()).to_std().ok()
+}
+
+/// Builds the client an adapter uses for every request, applying the
+/// provider's proxy, extra CA certificates, timeout and User-Agent.
+pub fn build_client(settings: &HttpSettings) -> Result<Client> {
+    let user_agent = settings.user_agent.clone().unwrap_or_else(|| {
+        format!(
+            "AIMonitor-Desktop/{} ({})",
+            env!("CARGO_PKG_VERSION"),
+            std::env::consts::OS
+        )
+    });
+
+    let mut builder = Client::builder()
+        .user_agent(user_agent)
+        .timeout(Duration::from_secs(
+            settings.timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS),
+        ));
+
+    if let Some(proxy_url) = &settings.proxy_url {
+        let proxy =
+            Proxy::all(proxy_url).with_context(|| format!("Invalid proxy URL {}", proxy_url))?;
+        builder = builder.proxy(proxy);
+    }
+
+    if let Some(path) = &settings.ca_bundle_path {
+        let pem =
+            std::fs::read(path).with_context(|| format!("Failed to read CA bundle {}", path))?;
+        let certificates = Certificate::from_pem_bundle(&pem)
+            .with_context(|| format!("Invalid CA bundle {}", path))?;
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+
+    Ok(builder.build()?)
+}
+
+/// The configured base URL override, or `default`, without a trailing slash.
+pub fn base_url(settings: &ProviderSettings, default: &str) -> String {
+    url_or(settings.base_url.as_deref(), default)
+}
+
+/// `url`, or `default` when no override is set, without a trailing slash.
+pub fn url_or(url: Option<&str>, default: &str) -> String {
+    url.unwrap_or(default).trim_end_matches('/').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serves `responses` in order, one per connection, repeating the last
+    /// one. Returns the server's URL and how many requests it has answered.
+    async fn serve(responses: &[&'static str]) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let responses = responses.to_vec();
+        let served = Arc::new(AtomicUsize::new(0));
+        let count = served.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    let n = socket.read(&mut buf).await.unwrap();
+                    if n == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buf[..n]);
+                }
+
+                let n = count.fetch_add(1, Ordering::SeqCst);
+                let status = responses[n.min(responses.len() - 1)];
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{{}}",
+                    status
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+                socket.shutdown().await.ok();
+            }
+        });
+
+        (url, served)
+    }
+
+    fn client() -> Client {
+        Client::builder().no_proxy().build().unwrap()
+    }
+
+    #[tokio::test]
+    async fn retries_until_success() {
+        let (url, served) = serve(&[
+            "429 Too Many Requests\r\nRetry-After: 0",
+            "503 Service Unavailable",
+            "200 OK",
+        ])
+        .await;
+
+        let response = send(client().get(&url)).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(served.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_the_last_retry() {
+        let (url, served) = serve(&["429 Too Many Requests\r\nRetry-After: 0"]).await;
+
+        let error = send(client().get(&url)).await.unwrap_err();
+
+        assert!(matches!(
+            error,
+            ProviderError::RateLimited {
+                retry_after: Some(d)
+            } if d.is_zero()
+        ));
+        assert_eq!(served.load(Ordering::SeqCst), MAX_RETRIES as usize + 1);
+    }
+
+    #[tokio::test]
+    async fn hands_back_a_long_retry_after() {
+        let (url, served) = serve(&["429 Too Many Requests\r\nRetry-After: 120"]).await;
+
+        let error = send(client().get(&url)).await.unwrap_err();
+
+        assert!(matches!(
+            error,
+            ProviderError::RateLimited {
+                retry_after: Some(d)
+            } if d == Duration::from_secs(120)
+        ));
+        assert_eq!(served.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_client_errors() {
+        let (url, served) = serve(&["401 Unauthorized", "200 OK"]).await;
+
+        let error = send(client().get(&url)).await.unwrap_err();
+
+        assert!(matches!(
+            error,
+            ProviderError::Unauthorized { status: 401, .. }
+        ));
+        assert_eq!(served.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn backoff_stays_under_the_ceiling() {
+        for attempt in 0..10 {
+            let ceiling = BASE_BACKOFF * 2u32.pow(attempt.min(4));
+            assert!(backoff(attempt) <= ceiling.min(MAX_BACKOFF));
+        }
+    }
+}
@@ -0,0 +1,368 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use reqwest::{Client, RequestBuilder};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+use super::http::{self, ProviderError};
+use super::ProviderAdapter;
+use crate::models::{
+    KeyValidation, LocalRuntime, Metric, MetricType, Provider, ProviderCapabilities,
+    ProviderSettings, ProviderType, ProviderUsage,
+};
+
+/// Unit of the notional cost metric. It is not `usd`, so it is never counted
+/// as spend; `ProviderUsage::notional_mtd_cost` reports it separately.
+pub const NOTIONAL_USD: &str = "notional_usd";
+
+// Counter values from the previous scrape, keyed by base URL. Adapters are
+// created per poll, so the baseline has to outlive them.
+static LAST_COUNTERS: OnceLock<Mutex<HashMap<String, Counters>>> = OnceLock::new();
+
+/// Local OpenAI-compatible runtime (Ollama, llama.cpp server, vLLM). There is
+/// no bill, so tokens are read from the Prometheus `/metrics` endpoint and
+/// priced at the user's notional rate, if any. Ollama has no `/metrics`, so
+/// only its loaded models are reported.
+pub struct LocalAdapter {
+    client: Client,
+    base_url: String,
+    runtime: Option<LocalRuntime>,
+    cost_per_million_tokens: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Counters {
+    prompt_tokens: f64,
+    generated_tokens: f64,
+    requests: f64,
+}
+
+/// Reads llama.cpp (`llamacpp:`) and vLLM (`vllm:`) counters by their exact
+/// names, summing across label sets. Anything else on the page is ignored.
+fn parse_counters(body: &str) -> Counters {
+    let mut counters = Counters::default();
+
+    for line in body.lines().filter(|l| !l.starts_with('#')) {
+        let name = line.split(['{', ' ']).next().unwrap_or_default();
+        let Some(value) = line
+            .split_whitespace()
+            .last()
+            .and_then(|v| v.parse::<f64>().ok())
+        else {
+            continue;
+        };
+
+        match name {
+            "llamacpp:prompt_tokens_total" | "vllm:prompt_tokens_total" => {
+                counters.prompt_tokens += value
+            }
+            "llamacpp:tokens_predicted_total" | "vllm:generation_tokens_total" => {
+                counters.generated_tokens += value
+            }
+            "vllm:request_success_total" => counters.requests += value,
+            _ => {}
+        }
+    }
+
+    counters
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelList {
+    data: Vec<ModelEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelEntry {
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaProcessList {
+    models: Vec<OllamaProcess>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaProcess {
+    name: String,
+}
+
+impl LocalAdapter {
+    pub fn new(settings: &ProviderSettings) -> Result<Self> {
+        let default_url = match settings.local_runtime {
+            Some(LocalRuntime::Ollama) => "http://localhost:11434",
+            _ => "http://localhost:8080",
+        };
+
+        Ok(Self {
+            client: http::build_client(&settings.http)?,
+            base_url: http::base_url(settings, default_url),
+            runtime: settings.local_runtime,
+            cost_per_million_tokens: settings.notional_cost_per_million_tokens,
+        })
+    }
+
+    fn has_metrics(&self) -> bool {
+        self.runtime != Some(LocalRuntime::Ollama)
+    }
+
+    /// Sums the token and request counters exposed in Prometheus text format;
+    /// runtimes without `/metrics` read as zero.
+    async fn scrape_counters(&self, api_key: &str) -> Result<Counters> {
+        let url = format!("{}/metrics", self.base_url);
+        let response = match http::send(self.get(&url, api_key)).await {
+            Ok(response) => response,
+            Err(ProviderError::Rejected { .. }) => return Ok(Counters::default()),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(parse_counters(&response.text().await?))
+    }
+
+    async fn loaded_models(&self, api_key: &str) -> Result<Vec<String>> {
+        // Ollama's `/v1/models` lists everything pulled; `/api/ps` lists what is in memory.
+        if self.runtime == Some(LocalRuntime::Ollama) {
+            let url = format!("{}/api/ps", self.base_url);
+            let response = http::send_json::<OllamaProcessList>(self.get(&url, api_key)).await?;
+
+            return Ok(response.models.into_iter().map(|m| m.name).collect());
+        }
+
+        let url = format!("{}/v1/models", self.base_url);
+        let response = http::send_json::<ModelList>(self.get(&url, api_key)).await?;
+
+        Ok(response.data.into_iter().map(|m| m.id).collect())
+    }
+
+    /// llama.cpp and vLLM can be started with `--api-key`; most local servers are open.
+    fn get(&self, url: &str, api_key: &str) -> RequestBuilder {
+        let request = self.client.get(url);
+        if api_key.is_empty() {
+            request
+        } else {
+            request.bearer_auth(api_key)
+        }
+    }
+
+    fn notional_cost(&self, tokens: f64) -> f64 {
+        self.cost_per_million_tokens.unwrap_or(0.0) * tokens / 1_000_000.0
+    }
+}
+
+#[async_trait]
+impl ProviderAdapter for LocalAdapter {
+    fn capabilities(&self) -> ProviderCapabilities {
+        // Notional cost is not spend, so `cost` stays off.
+        ProviderCapabilities {
+            token_usage: self.has_metrics(),
+            min_poll_interval_secs: 15,
+            usage_from_history: self.has_metrics(),
+            ..Default::default()
+        }
+    }
+
+    async fn fetch_usage(
+        &self,
+        api_key: &str,
+        _from: DateTime<Utc>,
+        _to: DateTime<Utc>,
+    ) -> Result<Vec<Metric>> {
+        let mut metrics = Vec::new();
+        let now = Utc::now();
+
+        let previous = if self.has_metrics() {
+            let current = self.scrape_counters(api_key).await?;
+            let map = LAST_COUNTERS.get_or_init(|| Mutex::new(HashMap::new()));
+            map.lock()
+                .unwrap()
+                .insert(self.base_url.clone(), current)
+                .map(|previous| (current, previous))
+        } else {
+            None
+        };
+
+        // The first scrape only establishes a baseline. A counter going
+        // backwards means the server restarted, so everything since is new.
+        if let Some((current, previous)) = previous {
+            let delta = |current: f64, previous: f64| {
+                if current >= previous {
+                    current - previous
+                } else {
+                    current
+                }
+            };
+            let tokens_in = delta(current.prompt_tokens, previous.prompt_tokens);
+            let tokens_out = delta(current.generated_tokens, previous.generated_tokens);
+            let requests = delta(current.requests, previous.requests);
+
+            metrics.push(Metric {
+                id: uuid::Uuid::new_v4().to_string(),
+                provider_id: "local".to_string(),
+                metric_type: MetricType::TokensIn,
+                value: tokens_in,
+                unit: "tokens".to_string(),
+                timestamp: now,
+                dimensions: HashMap::new(),
+            });
+
+            metrics.push(Metric {
+                id: uuid::Uuid::new_v4().to_string(),
+                provider_id: "local".to_string(),
+                metric_type: MetricType::TokensOut,
+                value: tokens_out,
+                unit: "tokens".to_string(),
+                timestamp: now,
+                dimensions: HashMap::new(),
+            });
+
+            metrics.push(Metric {
+                id: uuid::Uuid::new_v4().to_string(),
+                provider_id: "local".to_string(),
+                metric_type: MetricType::Requests,
+                value: requests,
+                unit: "requests".to_string(),
+                timestamp: now,
+                dimensions: HashMap::new(),
+            });
+
+            if self.cost_per_million_tokens.is_some() {
+                metrics.push(Metric {
+                    id: uuid::Uuid::new_v4().to_string(),
+                    provider_id: "local".to_string(),
+                    metric_type: MetricType::CostUsd,
+                    value: self.notional_cost(tokens_in + tokens_out),
+                    unit: NOTIONAL_USD.to_string(),
+                    timestamp: now,
+                    dimensions: HashMap::new(),
+                });
+            }
+        }
+
+        for model in self.loaded_models(api_key).await? {
+            metrics.push(Metric {
+                id: uuid::Uuid::new_v4().to_string(),
+                provider_id: "local".to_string(),
+                metric_type: MetricType::ModelLoaded,
+                value: 1.0,
+                unit: "models".to_string(),
+                timestamp: now,
+                dimensions: HashMap::from([("model".to_string(), model)]),
+            });
+        }
+
+        Ok(metrics)
+    }
+
+    /// Reads the server without going through `fetch_usage`, which would
+    /// move the counter baseline and drop the usage since the last poll.
+    async fn validate_key(&self, api_key: &str) -> Result<KeyValidation> {
+        self.loaded_models(api_key).await?;
+        let usage = if self.has_metrics() {
+            http::permitted(self.scrape_counters(api_key).await)?
+        } else {
+            None
+        };
+
+        Ok(KeyValidation {
+            usage_read: usage.is_some(),
+            ..Default::default()
+        })
+    }
+
+    async fn fetch_balance(&self, _api_key: &str) -> Result<Option<f64>> {
+        Ok(None)
+    }
+
+    /// Runtimes only expose totals since they started, so usage is left at
+    /// zero here; `MonitorService` sums today and month-to-date from the
+    /// stored per-poll deltas (see `usage_from_history`).
+    async fn get_current_usage(&self, api_key: &str, _metrics: &[Metric]) -> Result<ProviderUsage> {
+        // Fails early if the server is not running.
+        self.loaded_models(api_key).await?;
+
+        Ok(ProviderUsage {
+            provider: Provider {
+                id: "local".to_string(),
+                name: "Local".to_string(),
+                provider_type: ProviderType::Local,
+                api_key_ref: None,
+                enabled: true,
+                created_at: Utc::now(),
+                settings: ProviderSettings {
+                    base_url: Some(self.base_url.clone()),
+                    local_runtime: self.runtime,
+                    notional_cost_per_million_tokens: self.cost_per_million_tokens,
+                    ..Default::default()
+                },
+            },
+            today_tokens: 0,
+            today_cost: 0.0,
+            mtd_tokens: 0,
+            mtd_cost: 0.0,
+            balance: None,
+            credits: None,
+            budget_used_percentage: None,
+            last_error: None,
+            capabilities: self.capabilities(),
+            last_synced_at: None,
+            stale: false,
+            projected_run_out_at: None,
+            projected_period_spend: None,
+            notional_mtd_cost: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_llama_cpp_counters() {
+        let body = "\
+# HELP llamacpp:prompt_tokens_total Number of prompt tokens processed.
+# TYPE llamacpp:prompt_tokens_total counter
+llamacpp:prompt_tokens_total 1200
+llamacpp:tokens_predicted_total 340
+llamacpp:requests_processing 1
+";
+        let counters = parse_counters(body);
+
+        assert_eq!(counters.prompt_tokens, 1200.0);
+        assert_eq!(counters.generated_tokens, 340.0);
+        assert_eq!(counters.requests, 0.0);
+    }
+
+    #[test]
+    fn sums_vllm_counters_across_labels() {
+        let body = "\
+vllm:prompt_tokens_total{model_name=\"a\"} 100.0
+vllm:prompt_tokens_total{model_name=\"b\"} 50.0
+vllm:generation_tokens_total{model_name=\"a\"} 25.0
+vllm:request_success_total{finished_reason=\"stop\",model_name=\"a\"} 3.0
+vllm:request_success_total{finished_reason=\"length\",model_name=\"a\"} 1.0
+";
+        let counters = parse_counters(body);
+
+        assert_eq!(counters.prompt_tokens, 150.0);
+        assert_eq!(counters.generated_tokens, 25.0);
+        assert_eq!(counters.requests, 4.0);
+    }
+
+    #[test]
+    fn ignores_unrelated_metrics_with_similar_names() {
+        let body = "\
+http_requests_total{method=\"GET\"} 900
+vllm:request_prompt_tokens_sum 80
+my_prompt_tokens_total 7
+vllm:prompt_tokens_total not-a-number
+";
+        let counters = parse_counters(body);
+
+        assert_eq!(counters.prompt_tokens, 0.0);
+        assert_eq!(counters.generated_tokens, 0.0);
+        assert_eq!(counters.requests, 0.0);
+    }
+}
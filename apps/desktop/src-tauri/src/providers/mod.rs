@@ -1,15 +1,102 @@
-pub mod openai;
 pub mod anthropic;
+pub mod azure_openai;
+pub mod bedrock;
+pub mod custom;
+pub mod gemini;
+pub mod http;
+pub mod local;
+pub mod openai;
 pub mod openrouter;
+mod sigv4;
 
-use async_trait::async_trait;
+use crate::models::{KeyValidation, Metric, MetricType, ProviderCapabilities, ProviderUsage};
 use anyhow::Result;
-use crate::models::{Metric, ProviderUsage};
-use chrono::{DateTime, Utc};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+
+/// Tokens and USD cost in `metrics` stamped at or after `since`. Estimated
+/// cost counts; notional cost does not.
+pub fn totals_since(metrics: &[Metric], since: DateTime<Utc>) -> (u64, f64) {
+    metrics
+        .iter()
+        .filter(|m| m.timestamp >= since)
+        .fold((0, 0.0), |(tokens, cost), m| match m.metric_type {
+            MetricType::TokensIn | MetricType::TokensOut => (tokens + m.value as u64, cost),
+            MetricType::CostUsd if m.unit == "usd" => (tokens, cost + m.value),
+            _ => (tokens, cost),
+        })
+}
 
 #[async_trait]
 pub trait ProviderAdapter: Send + Sync {
-    async fn fetch_usage(&self, api_key: &str, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<Metric>>;
+    fn capabilities(&self) -> ProviderCapabilities;
+
+    async fn fetch_usage(
+        &self,
+        api_key: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Metric>>;
     async fn fetch_balance(&self, api_key: &str) -> Result<Option<f64>>;
-    async fn get_current_usage(&self, api_key: &str) -> Result<ProviderUsage>;
-}
\ No newline at end of file
+    /// Today and month-to-date from `metrics`, this poll's `fetch_usage`
+    /// result (at least the month so far), plus whatever else the provider
+    /// reports, such as a balance. Usage is not fetched again.
+    async fn get_current_usage(&self, api_key: &str, metrics: &[Metric]) -> Result<ProviderUsage>;
+
+    /// Checks the key with the cheapest authenticated calls the provider
+    /// offers. A rejected key is an error; calls the key is not permitted to
+    /// make are reported as missing capabilities. By default this reads the
+    /// last day of usage and the balance.
+    async fn validate_key(&self, api_key: &str) -> Result<KeyValidation> {
+        let now = Utc::now();
+        let usage = http::permitted(
+            self.fetch_usage(api_key, now - Duration::days(1), now)
+                .await,
+        )?;
+        let balance = http::permitted(self.fetch_balance(api_key).await)?;
+
+        Ok(KeyValidation {
+            usage_read: usage.is_some(),
+            // Adapters report cost (or an estimate) from the same calls as usage.
+            cost_read: usage.is_some(),
+            balance_read: balance.flatten().is_some(),
+            admin_key: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn metric(metric_type: MetricType, value: f64, unit: &str, day: u32) -> Metric {
+        Metric {
+            id: uuid::Uuid::new_v4().to_string(),
+            provider_id: "p".to_string(),
+            metric_type,
+            value,
+            unit: unit.to_string(),
+            timestamp: Utc.with_ymd_and_hms(2026, 3, day, 12, 0, 0).unwrap(),
+            dimensions: Default::default(),
+        }
+    }
+
+    #[test]
+    fn totals_count_tokens_and_usd_cost_since_the_cutoff() {
+        let metrics = [
+            metric(MetricType::TokensIn, 100.0, "tokens", 1),
+            metric(MetricType::TokensOut, 50.0, "tokens", 2),
+            metric(MetricType::CostUsd, 1.5, "usd", 1),
+            metric(MetricType::CostUsd, 2.0, "usd", 2),
+            metric(MetricType::CostUsd, 9.0, "notional_usd", 2),
+            metric(MetricType::Requests, 7.0, "requests", 2),
+        ];
+
+        let month = Utc.with_ymd_and_hms(2026, 3, 1, 0, 0, 0).unwrap();
+        let today = Utc.with_ymd_and_hms(2026, 3, 2, 0, 0, 0).unwrap();
+
+        assert_eq!(totals_since(&metrics, month), (150, 3.5));
+        assert_eq!(totals_since(&metrics, today), (50, 2.0));
+    }
+}
@@ -1,12 +1,15 @@
-use async_trait::async_trait;
 use anyhow::Result;
-use chrono::{DateTime, Utc};
+use async_trait::async_trait;
+use chrono::{DateTime, Datelike, Utc};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-use crate::models::{Metric, MetricType, Provider, ProviderType, ProviderUsage};
-use super::ProviderAdapter;
+use super::{http, ProviderAdapter};
+use crate::models::{
+    Metric, MetricType, Provider, ProviderCapabilities, ProviderSettings, ProviderType,
+    ProviderUsage,
+};
 
 pub struct OpenAIAdapter {
     client: Client,
@@ -43,31 +46,42 @@ struct LineItem {
 }
 
 impl OpenAIAdapter {
-    pub fn new() -> Self {
-        Self {
-            client: Client::new(),
-            base_url: "https://api.openai.com/v1".to_string(),
-        }
+    pub fn new(settings: &ProviderSettings) -> Result<Self> {
+        Ok(Self {
+            client: http::build_client(&settings.http)?,
+            base_url: http::base_url(settings, "https://api.openai.com/v1"),
+        })
     }
 }
 
 #[async_trait]
 impl ProviderAdapter for OpenAIAdapter {
-    async fn fetch_usage(&self, api_key: &str, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<Metric>> {
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities {
+            token_usage: true,
+            cost: true,
+            backfill_days: 90,
+            // The legacy usage endpoint is aggressively rate limited.
+            min_poll_interval_secs: 300,
+            requires_api_key: true,
+            ..Default::default()
+        }
+    }
+
+    async fn fetch_usage(
+        &self,
+        api_key: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Metric>> {
         let url = format!("{}/usage", self.base_url);
 
-        let params = HashMap::from([
-            ("date", from.format("%Y-%m-%d").to_string()),
-        ]);
+        let params = HashMap::from([("date", from.format("%Y-%m-%d").to_string())]);
 
-        let response = self.client
-            .get(&url)
-            .bearer_auth(api_key)
-            .query(&params)
-            .send()
-            .await?
-            .json::<UsageResponse>()
-            .await?;
+        let response = http::send_json::<UsageResponse>(
+            self.client.get(&url).bearer_auth(api_key).query(&params),
+        )
+        .await?;
 
         let mut metrics = Vec::new();
 
@@ -82,9 +96,7 @@ impl ProviderAdapter for OpenAIAdapter {
                 value: usage.n_context_tokens_total as f64,
                 unit: "tokens".to_string(),
                 timestamp,
-                dimensions: HashMap::from([
-                    ("operation".to_string(), usage.operation.clone()),
-                ]),
+                dimensions: HashMap::from([("operation".to_string(), usage.operation.clone())]),
             });
 
             metrics.push(Metric {
@@ -94,9 +106,7 @@ impl ProviderAdapter for OpenAIAdapter {
                 value: usage.n_generated_tokens_total as f64,
                 unit: "tokens".to_string(),
                 timestamp,
-                dimensions: HashMap::from([
-                    ("operation".to_string(), usage.operation.clone()),
-                ]),
+                dimensions: HashMap::from([("operation".to_string(), usage.operation.clone())]),
             });
 
             if let Some(cached) = usage.n_cached_context_tokens_total {
@@ -107,17 +117,15 @@ impl ProviderAdapter for OpenAIAdapter {
                     value: cached as f64,
                     unit: "tokens".to_string(),
                     timestamp,
-                    dimensions: HashMap::from([
-                        ("operation".to_string(), usage.operation.clone()),
-                    ]),
+                    dimensions: HashMap::from([("operation".to_string(), usage.operation.clone())]),
                 });
             }
         }
 
         if let Some(daily_costs) = response.daily_costs {
             for cost in daily_costs {
-                let timestamp = DateTime::from_timestamp(cost.timestamp, 0)
-                    .unwrap_or_else(|| Utc::now());
+                let timestamp =
+                    DateTime::from_timestamp(cost.timestamp, 0).unwrap_or_else(|| Utc::now());
 
                 let total_cost: f64 = cost.line_items.iter().map(|item| item.cost).sum();
 
@@ -140,41 +148,19 @@ impl ProviderAdapter for OpenAIAdapter {
         Ok(None)
     }
 
-    async fn get_current_usage(&self, api_key: &str) -> Result<ProviderUsage> {
+    async fn get_current_usage(&self, _api_key: &str, metrics: &[Metric]) -> Result<ProviderUsage> {
         let now = Utc::now();
-        let start_of_day = now.date_naive().and_hms_opt(0, 0, 0)
-            .unwrap()
-            .and_utc();
-        let start_of_month = now.date_naive()
+        let start_of_day = now.date_naive().and_hms_opt(0, 0, 0).unwrap().and_utc();
+        let start_of_month = now
+            .date_naive()
             .with_day(1)
             .unwrap()
             .and_hms_opt(0, 0, 0)
             .unwrap()
             .and_utc();
 
-        let metrics = self.fetch_usage(api_key, start_of_month, now).await?;
-
-        let today_tokens: u64 = metrics.iter()
-            .filter(|m| m.timestamp >= start_of_day)
-            .filter(|m| matches!(m.metric_type, MetricType::TokensIn | MetricType::TokensOut))
-            .map(|m| m.value as u64)
-            .sum();
-
-        let today_cost: f64 = metrics.iter()
-            .filter(|m| m.timestamp >= start_of_day)
-            .filter(|m| matches!(m.metric_type, MetricType::CostUsd))
-            .map(|m| m.value)
-            .sum();
-
-        let mtd_tokens: u64 = metrics.iter()
-            .filter(|m| matches!(m.metric_type, MetricType::TokensIn | MetricType::TokensOut))
-            .map(|m| m.value as u64)
-            .sum();
-
-        let mtd_cost: f64 = metrics.iter()
-            .filter(|m| matches!(m.metric_type, MetricType::CostUsd))
-            .map(|m| m.value)
-            .sum();
+        let (today_tokens, today_cost) = super::totals_since(metrics, start_of_day);
+        let (mtd_tokens, mtd_cost) = super::totals_since(metrics, start_of_month);
 
         Ok(ProviderUsage {
             provider: Provider {
@@ -184,6 +170,7 @@ impl ProviderAdapter for OpenAIAdapter {
                 api_key_ref: Some("openai_key".to_string()),
                 enabled: true,
                 created_at: Utc::now(),
+                settings: ProviderSettings::default(),
             },
             today_tokens,
             today_cost,
@@ -192,6 +179,13 @@ impl ProviderAdapter for OpenAIAdapter {
             balance: None,
             credits: None,
             budget_used_percentage: None,
+            last_error: None,
+            capabilities: self.capabilities(),
+            last_synced_at: None,
+            stale: false,
+            projected_run_out_at: None,
+            projected_period_spend: None,
+            notional_mtd_cost: None,
         })
     }
-}
\ No newline at end of file
+}
@@ -1,12 +1,15 @@
-use async_trait::async_trait;
 use anyhow::Result;
+use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-use crate::models::{Metric, MetricType, Provider, ProviderType, ProviderUsage};
-use super::ProviderAdapter;
+use super::{http, ProviderAdapter};
+use crate::models::{
+    Metric, MetricType, Provider, ProviderCapabilities, ProviderSettings, ProviderType,
+    ProviderUsage,
+};
 
 pub struct OpenRouterAdapter {
     client: Client,
@@ -34,27 +37,39 @@ struct CreditsInfo {
 }
 
 impl OpenRouterAdapter {
-    pub fn new() -> Self {
-        Self {
-            client: Client::new(),
-            base_url: "https://openrouter.ai".to_string(),
-        }
+    pub fn new(settings: &ProviderSettings) -> Result<Self> {
+        Ok(Self {
+            client: http::build_client(&settings.http)?,
+            base_url: http::base_url(settings, "https://openrouter.ai"),
+        })
     }
 }
 
 #[async_trait]
 impl ProviderAdapter for OpenRouterAdapter {
-    async fn fetch_usage(&self, api_key: &str, _from: DateTime<Utc>, _to: DateTime<Utc>) -> Result<Vec<Metric>> {
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities {
+            balance: true,
+            credits: true,
+            cost: true,
+            min_poll_interval_secs: 60,
+            requires_api_key: true,
+            ..Default::default()
+        }
+    }
+
+    async fn fetch_usage(
+        &self,
+        api_key: &str,
+        _from: DateTime<Utc>,
+        _to: DateTime<Utc>,
+    ) -> Result<Vec<Metric>> {
         let mut metrics = Vec::new();
 
         let credits_url = format!("{}/api/v1/credits", self.base_url);
-        let credits_response = self.client
-            .get(&credits_url)
-            .bearer_auth(api_key)
-            .send()
-            .await?
-            .json::<CreditsInfo>()
-            .await?;
+        let credits_response =
+            http::send_json::<CreditsInfo>(self.client.get(&credits_url).bearer_auth(api_key))
+                .await?;
 
         metrics.push(Metric {
             id: uuid::Uuid::new_v4().to_string(),
@@ -81,35 +96,21 @@ impl ProviderAdapter for OpenRouterAdapter {
 
     async fn fetch_balance(&self, api_key: &str) -> Result<Option<f64>> {
         let key_url = format!("{}/api/v1/key", self.base_url);
-        let key_response = self.client
-            .get(&key_url)
-            .bearer_auth(api_key)
-            .send()
-            .await?
-            .json::<KeyInfo>()
-            .await?;
+        let key_response =
+            http::send_json::<KeyInfo>(self.client.get(&key_url).bearer_auth(api_key)).await?;
 
         Ok(key_response.data.limit_remaining)
     }
 
-    async fn get_current_usage(&self, api_key: &str) -> Result<ProviderUsage> {
+    async fn get_current_usage(&self, api_key: &str, _metrics: &[Metric]) -> Result<ProviderUsage> {
         let key_url = format!("{}/api/v1/key", self.base_url);
-        let key_response = self.client
-            .get(&key_url)
-            .bearer_auth(api_key)
-            .send()
-            .await?
-            .json::<KeyInfo>()
-            .await?;
+        let key_response =
+            http::send_json::<KeyInfo>(self.client.get(&key_url).bearer_auth(api_key)).await?;
 
         let credits_url = format!("{}/api/v1/credits", self.base_url);
-        let credits_response = self.client
-            .get(&credits_url)
-            .bearer_auth(api_key)
-            .send()
-            .await?
-            .json::<CreditsInfo>()
-            .await?;
+        let credits_response =
+            http::send_json::<CreditsInfo>(self.client.get(&credits_url).bearer_auth(api_key))
+                .await?;
 
         let budget_used_percentage = if let Some(limit) = key_response.data.limit {
             Some((key_response.data.usage / limit) * 100.0)
@@ -125,6 +126,7 @@ impl ProviderAdapter for OpenRouterAdapter {
                 api_key_ref: Some("openrouter_key".to_string()),
                 enabled: true,
                 created_at: Utc::now(),
+                settings: ProviderSettings::default(),
             },
             today_tokens: 0,
             today_cost: 0.0,
@@ -133,6 +135,13 @@ impl ProviderAdapter for OpenRouterAdapter {
             balance: key_response.data.limit_remaining,
             credits: Some(credits_response.remaining_credits),
             budget_used_percentage,
+            last_error: None,
+            capabilities: self.capabilities(),
+            last_synced_at: None,
+            stale: false,
+            projected_run_out_at: None,
+            projected_period_spend: None,
+            notional_mtd_cost: None,
         })
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,219 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use reqwest::Url;
+use sha2::{Digest, Sha256};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// AWS credentials as stored in the keychain:
+/// `ACCESS_KEY_ID:SECRET_ACCESS_KEY[:SESSION_TOKEN]`.
+pub struct AwsCredentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+}
+
+impl AwsCredentials {
+    pub fn parse(secret: &str) -> Result<Self> {
+        let mut parts = secret.trim().splitn(3, ':');
+
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(id), Some(key), token) if !id.is_empty() && !key.is_empty() => Ok(Self {
+                access_key_id: id.to_string(),
+                secret_access_key: key.to_string(),
+                session_token: token.filter(|t| !t.is_empty()).map(str::to_string),
+            }),
+            _ => Err(anyhow!(
+                "AWS credentials must be ACCESS_KEY_ID:SECRET_ACCESS_KEY[:SESSION_TOKEN]"
+            )),
+        }
+    }
+}
+
+/// Signs a POST with an empty query string and returns the headers to send,
+/// `Authorization` included. `headers` must not contain `host` or `x-amz-date`.
+pub fn sign_post(
+    credentials: &AwsCredentials,
+    url: &Url,
+    region: &str,
+    service: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+    now: DateTime<Utc>,
+) -> Result<Vec<(String, String)>> {
+    let host = match url.port() {
+        Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+        None => url.host_str().unwrap_or_default().to_string(),
+    };
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = now.format("%Y%m%d").to_string();
+
+    let mut signed: Vec<(String, String)> = headers
+        .iter()
+        .map(|(name, value)| (name.to_lowercase(), value.trim().to_string()))
+        .collect();
+    signed.push(("host".to_string(), host));
+    signed.push(("x-amz-date".to_string(), amz_date.clone()));
+    if let Some(token) = &credentials.session_token {
+        signed.push(("x-amz-security-token".to_string(), token.clone()));
+    }
+    signed.sort();
+
+    let canonical_headers: String = signed
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value))
+        .collect();
+    let signed_headers = signed
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>()
+        .join(";");
+
+    let canonical_request = format!(
+        "POST\n{}\n\n{}\n{}\n{}",
+        url.path(),
+        canonical_headers,
+        signed_headers,
+        hex::encode(Sha256::digest(body)),
+    );
+
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        hex::encode(Sha256::digest(canonical_request.as_bytes())),
+    );
+
+    let k_date = hmac(
+        format!("AWS4{}", credentials.secret_access_key).as_bytes(),
+        date.as_bytes(),
+    );
+    let k_region = hmac(&k_date, region.as_bytes());
+    let k_service = hmac(&k_region, service.as_bytes());
+    let k_signing = hmac(&k_service, b"aws4_request");
+    let signature = hex::encode(hmac(&k_signing, string_to_sign.as_bytes()));
+
+    signed.push((
+        "authorization".to_string(),
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            credentials.access_key_id, scope, signed_headers, signature
+        ),
+    ));
+
+    // reqwest derives Host from the URL itself.
+    signed.retain(|(name, _)| name != "host");
+    Ok(signed)
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn credentials(session_token: Option<&str>) -> AwsCredentials {
+        AwsCredentials {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+            session_token: session_token.map(str::to_string),
+        }
+    }
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2015, 8, 30, 12, 36, 0).unwrap()
+    }
+
+    fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+        headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    #[test]
+    fn parses_credentials() {
+        let parsed = AwsCredentials::parse(" AKID:secret:token ").unwrap();
+        assert_eq!(parsed.access_key_id, "AKID");
+        assert_eq!(parsed.secret_access_key, "secret");
+        assert_eq!(parsed.session_token.as_deref(), Some("token"));
+
+        assert_eq!(
+            AwsCredentials::parse("AKID:secret:").unwrap().session_token,
+            None
+        );
+        // Session tokens may contain colons of their own.
+        assert_eq!(
+            AwsCredentials::parse("AKID:secret:a:b")
+                .unwrap()
+                .session_token
+                .as_deref(),
+            Some("a:b")
+        );
+
+        assert!(AwsCredentials::parse("AKID").is_err());
+        assert!(AwsCredentials::parse(":secret").is_err());
+    }
+
+    /// The `post-vanilla` case from the AWS Signature Version 4 test suite.
+    #[test]
+    fn matches_aws_test_suite() {
+        let url = Url::parse("https://example.amazonaws.com/").unwrap();
+        let headers = sign_post(
+            &credentials(None),
+            &url,
+            "us-east-1",
+            "service",
+            &[],
+            b"",
+            now(),
+        )
+        .unwrap();
+
+        assert_eq!(header(&headers, "x-amz-date"), Some("20150830T123600Z"));
+        assert_eq!(
+            header(&headers, "authorization"),
+            Some(
+                "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+                 SignedHeaders=host;x-amz-date, \
+                 Signature=5da7c1a2acd57cee7505fc6676e4e544621c30862966e37dddb68e92efbe5d6b"
+            ),
+        );
+        // reqwest sets Host from the URL.
+        assert_eq!(header(&headers, "host"), None);
+    }
+
+    #[test]
+    fn signs_extra_headers_and_session_token() {
+        let url = Url::parse("https://example.amazonaws.com/").unwrap();
+        let headers = sign_post(
+            &credentials(Some("TOKEN")),
+            &url,
+            "us-east-1",
+            "service",
+            &[("Content-Type", " application/x-www-form-urlencoded ")],
+            b"Param1=value1",
+            now(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            header(&headers, "content-type"),
+            Some("application/x-www-form-urlencoded")
+        );
+        assert_eq!(header(&headers, "x-amz-security-token"), Some("TOKEN"));
+        let authorization = header(&headers, "authorization").unwrap();
+        assert!(authorization
+            .contains("SignedHeaders=content-type;host;x-amz-date;x-amz-security-token,"));
+        assert!(authorization.ends_with(
+            "Signature=9536bd5cb7bdcccba70c85e2632185f5cce90fea22906ffd507fc9328b85ad0d"
+        ));
+    }
+}
@@ -0,0 +1,290 @@
+use chrono::{DateTime, Duration, Utc};
+
+use crate::models::{Provider, ProviderCapabilities, ProviderUsage};
+use crate::services::power::PowerState;
+
+const DEFAULT_POLL_INTERVAL_SECS: u64 = 60;
+const DEFAULT_MAX_POLL_INTERVAL_SECS: u64 = 900;
+// Each quiet poll doubles the interval, up to 16x.
+const MAX_QUIET_DOUBLINGS: u32 = 4;
+// Spend per hour must grow by half again to count as accelerating.
+const ACCELERATION_FACTOR: f64 = 1.5;
+const NEAR_BUDGET_PERCENT: f64 = 80.0;
+const IDLE_AFTER: std::time::Duration = std::time::Duration::from_secs(10 * 60);
+
+/// The range a provider's poll interval moves within.
+#[derive(Debug, Clone, Copy)]
+pub struct IntervalBounds {
+    pub base: Duration,
+    pub min: Duration,
+    pub max: Duration,
+}
+
+impl IntervalBounds {
+    /// From the provider's settings, never going below what its API can
+    /// usefully serve.
+    pub fn for_provider(provider: &Provider, capabilities: &ProviderCapabilities) -> Self {
+        let settings = &provider.settings;
+        let min = settings
+            .min_poll_interval_secs
+            .unwrap_or(0)
+            .max(capabilities.min_poll_interval_secs);
+        let max = settings
+            .max_poll_interval_secs
+            .unwrap_or(DEFAULT_MAX_POLL_INTERVAL_SECS)
+            .max(min);
+        let base = settings
+            .poll_interval_secs
+            .unwrap_or(DEFAULT_POLL_INTERVAL_SECS)
+            .clamp(min, max);
+
+        Self {
+            base: Duration::seconds(base as i64),
+            min: Duration::seconds(min as i64),
+            max: Duration::seconds(max as i64),
+        }
+    }
+}
+
+/// How usage moved between the last two polls.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Trend {
+    /// Nothing changed for this many polls in a row.
+    Quiet(u32),
+    Steady,
+    Accelerating,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    at: DateTime<Utc>,
+    cost: f64,
+    tokens: u64,
+}
+
+/// Month-to-date usage seen on recent polls of one provider.
+#[derive(Debug, Default)]
+pub struct Activity {
+    last: Option<Sample>,
+    /// USD per hour between the previous two polls.
+    last_rate: Option<f64>,
+    quiet_polls: u32,
+}
+
+impl Activity {
+    pub fn record(&mut self, usage: &ProviderUsage) -> Trend {
+        let sample = Sample {
+            at: Utc::now(),
+            cost: usage.mtd_cost,
+            tokens: usage.mtd_tokens,
+        };
+
+        let Some(previous) = self.last.replace(sample) else {
+            return Trend::Steady;
+        };
+
+        if sample.cost == previous.cost && sample.tokens == previous.tokens {
+            self.quiet_polls += 1;
+            self.last_rate = Some(0.0);
+            return Trend::Quiet(self.quiet_polls);
+        }
+        self.quiet_polls = 0;
+
+        // Month-to-date totals reset at the start of a month.
+        if sample.cost < previous.cost {
+            self.last_rate = None;
+            return Trend::Steady;
+        }
+
+        let hours = ((sample.at - previous.at).num_seconds().max(1)) as f64 / 3600.0;
+        let rate = (sample.cost - previous.cost) / hours;
+        let accelerating = rate > 0.0
+            && self
+                .last_rate
+                .is_some_and(|last| rate > last * ACCELERATION_FACTOR);
+        self.last_rate = Some(rate);
+
+        if accelerating {
+            Trend::Accelerating
+        } else {
+            Trend::Steady
+        }
+    }
+}
+
+/// Slows down while nothing is happening and speeds up while spend is
+/// accelerating or close to budget. On battery or when the user is away the
+/// interval doubles, unless spend needs watching.
+pub fn next_interval(
+    bounds: &IntervalBounds,
+    trend: Trend,
+    budget_used_percentage: Option<f64>,
+    power: &PowerState,
+) -> Duration {
+    let near_budget = budget_used_percentage.is_some_and(|p| p >= NEAR_BUDGET_PERCENT);
+
+    let mut interval = match trend {
+        Trend::Quiet(polls) => bounds.base * 2i32.pow(polls.min(MAX_QUIET_DOUBLINGS)),
+        Trend::Steady => bounds.base,
+        Trend::Accelerating => bounds.base / 2,
+    };
+
+    if near_budget {
+        interval = interval.min(bounds.base / 2);
+    } else if trend != Trend::Accelerating {
+        if power.on_battery {
+            interval = interval * 2;
+        }
+        if power.idle_for.is_some_and(|idle| idle >= IDLE_AFTER) {
+            interval = interval * 2;
+        }
+    }
+
+    interval.clamp(bounds.min, bounds.max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ProviderType;
+
+    fn usage(mtd_cost: f64, mtd_tokens: u64) -> ProviderUsage {
+        ProviderUsage {
+            provider: Provider {
+                id: "p".to_string(),
+                name: "P".to_string(),
+                provider_type: ProviderType::OpenAI,
+                api_key_ref: None,
+                enabled: true,
+                created_at: Utc::now(),
+                settings: Default::default(),
+            },
+            today_tokens: 0,
+            today_cost: 0.0,
+            mtd_tokens,
+            mtd_cost,
+            balance: None,
+            credits: None,
+            budget_used_percentage: None,
+            last_error: None,
+            capabilities: Default::default(),
+            last_synced_at: None,
+            stale: false,
+            projected_run_out_at: None,
+            projected_period_spend: None,
+            notional_mtd_cost: None,
+        }
+    }
+
+    fn bounds() -> IntervalBounds {
+        IntervalBounds {
+            base: Duration::seconds(60),
+            min: Duration::seconds(30),
+            max: Duration::seconds(900),
+        }
+    }
+
+    /// Moves the last sample back an hour, as if that much time had passed.
+    fn an_hour_later(activity: &mut Activity) {
+        if let Some(last) = activity.last.as_mut() {
+            last.at -= Duration::hours(1);
+        }
+    }
+
+    #[test]
+    fn counts_quiet_polls_until_usage_moves() {
+        let mut activity = Activity::default();
+
+        assert_eq!(activity.record(&usage(1.0, 100)), Trend::Steady);
+        assert_eq!(activity.record(&usage(1.0, 100)), Trend::Quiet(1));
+        assert_eq!(activity.record(&usage(1.0, 100)), Trend::Quiet(2));
+        // Any spend after a quiet spell is faster than none.
+        an_hour_later(&mut activity);
+        assert_eq!(activity.record(&usage(2.0, 200)), Trend::Accelerating);
+        assert_eq!(activity.quiet_polls, 0);
+    }
+
+    #[test]
+    fn accelerates_only_when_the_hourly_rate_grows_by_half() {
+        let mut activity = Activity::default();
+        activity.record(&usage(0.0, 0));
+
+        an_hour_later(&mut activity);
+        assert_eq!(activity.record(&usage(1.0, 10)), Trend::Steady);
+        an_hour_later(&mut activity);
+        assert_eq!(activity.record(&usage(2.2, 20)), Trend::Steady);
+        an_hour_later(&mut activity);
+        assert_eq!(activity.record(&usage(4.2, 30)), Trend::Accelerating);
+    }
+
+    #[test]
+    fn a_new_month_is_not_a_trend() {
+        let mut activity = Activity::default();
+        activity.record(&usage(50.0, 1000));
+
+        an_hour_later(&mut activity);
+        assert_eq!(activity.record(&usage(1.0, 10)), Trend::Steady);
+        assert_eq!(activity.last_rate, None);
+    }
+
+    #[test]
+    fn quiet_polls_back_off_up_to_the_cap() {
+        let power = PowerState::default();
+
+        assert_eq!(
+            next_interval(&bounds(), Trend::Steady, None, &power),
+            Duration::seconds(60)
+        );
+        assert_eq!(
+            next_interval(&bounds(), Trend::Quiet(2), None, &power),
+            Duration::seconds(240)
+        );
+        // 60s * 16 would be 960s, past the maximum.
+        assert_eq!(
+            next_interval(&bounds(), Trend::Quiet(9), None, &power),
+            Duration::seconds(900)
+        );
+        assert_eq!(
+            next_interval(&bounds(), Trend::Accelerating, None, &power),
+            Duration::seconds(30)
+        );
+    }
+
+    #[test]
+    fn battery_and_idle_slow_polling_unless_spend_needs_watching() {
+        let away = PowerState {
+            on_battery: true,
+            idle_for: Some(std::time::Duration::from_secs(15 * 60)),
+        };
+
+        assert_eq!(
+            next_interval(&bounds(), Trend::Steady, None, &away),
+            Duration::seconds(240)
+        );
+        assert_eq!(
+            next_interval(&bounds(), Trend::Steady, Some(85.0), &away),
+            Duration::seconds(30)
+        );
+        assert_eq!(
+            next_interval(&bounds(), Trend::Accelerating, None, &away),
+            Duration::seconds(30)
+        );
+    }
+
+    #[test]
+    fn bounds_respect_the_adapter_minimum() {
+        let mut provider = usage(0.0, 0).provider;
+        provider.settings.poll_interval_secs = Some(10);
+        provider.settings.max_poll_interval_secs = Some(5);
+        let capabilities = ProviderCapabilities {
+            min_poll_interval_secs: 300,
+            ..Default::default()
+        };
+
+        let bounds = IntervalBounds::for_provider(&provider, &capabilities);
+
+        assert_eq!(bounds.min, Duration::seconds(300));
+        assert_eq!(bounds.base, Duration::seconds(300));
+        assert_eq!(bounds.max, Duration::seconds(300));
+    }
+}
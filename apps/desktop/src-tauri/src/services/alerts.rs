@@ -0,0 +1,1030 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, Duration, Utc};
+use reqwest::Client;
+use serde::Serialize;
+use std::sync::Arc;
+use tracing::{error, info};
+
+use crate::error::AppError;
+use crate::models::{
+    Alert, AlertEvent, AlertRule, AlertSeverity, AlertStatus, AnomalyMetric, AnomalySensitivity,
+    BudgetPeriod, ChannelConfig, HttpSettings, NotificationChannel, ProviderGroup, ProviderUsage,
+};
+use crate::providers::http;
+use crate::services::expression::Expression;
+use crate::services::keychain::KeychainService;
+use crate::services::AppState;
+use crate::services::{anomaly, channels};
+
+/// Called with every alert that fires; the app forwards these as
+/// `alert-triggered` events and OS notifications.
+pub type AlertSink = Arc<dyn Fn(&AlertEvent) + Send + Sync>;
+
+const SNOOZE_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Recorded in `events` as `alert_status_changed` whenever an alert moves
+/// between states.
+#[derive(Debug, Serialize)]
+pub struct StatusChange {
+    pub alert_id: String,
+    pub from: AlertStatus,
+    pub to: AlertStatus,
+    /// `fired`, `cleared`, `acknowledged`, `snoozed`, `snooze_expired`,
+    /// `disabled` or `enabled`.
+    pub reason: &'static str,
+    pub snoozed_until: Option<DateTime<Utc>>,
+    pub at: DateTime<Utc>,
+}
+
+/// A rule's condition holding for the latest usage.
+struct Breach {
+    current_value: f64,
+    threshold: f64,
+    unit: &'static str,
+    period: Option<BudgetPeriod>,
+    severity: AlertSeverity,
+    /// What was unusual, for rules whose values alone do not say.
+    detail: Option<String>,
+}
+
+/// What alerts are evaluated against: one provider's latest usage, or the
+/// latest usage of a group's members added up.
+struct Subject {
+    /// The provider or group id, which also keys its budgets.
+    id: String,
+    name: String,
+    provider_ids: Vec<String>,
+    /// The providers whose cost counts as spend.
+    cost_provider_ids: Vec<String>,
+    /// Whether any of the providers reports cost or token usage.
+    cost: bool,
+    token_usage: bool,
+    mtd_cost: f64,
+    /// Credits, or the balance of providers without credits.
+    remaining: Option<f64>,
+    /// The earliest projected run-out of any of the providers.
+    projected_run_out_at: Option<DateTime<Utc>>,
+}
+
+impl Subject {
+    fn provider(usage: &ProviderUsage) -> Self {
+        Self {
+            id: usage.provider.id.clone(),
+            name: usage.provider.name.clone(),
+            provider_ids: vec![usage.provider.id.clone()],
+            cost_provider_ids: vec![usage.provider.id.clone()],
+            cost: usage.capabilities.cost,
+            token_usage: usage.capabilities.token_usage,
+            mtd_cost: usage.mtd_cost,
+            remaining: usage.credits.or(usage.balance),
+            projected_run_out_at: usage.projected_run_out_at,
+        }
+    }
+
+    /// Estimated and notional costs (price tables, local runtimes) are left
+    /// out of the group's spend, so its budgets add up only billed amounts.
+    fn group(group: &ProviderGroup, members: &[&ProviderUsage]) -> Self {
+        let remaining: Vec<f64> = members
+            .iter()
+            .filter_map(|usage| usage.credits.or(usage.balance))
+            .collect();
+        let billed: Vec<&ProviderUsage> = members
+            .iter()
+            .copied()
+            .filter(|usage| usage.capabilities.cost && !usage.capabilities.estimated_cost)
+            .collect();
+
+        Self {
+            id: group.id.clone(),
+            name: group.name.clone(),
+            provider_ids: members
+                .iter()
+                .map(|usage| usage.provider.id.clone())
+                .collect(),
+            cost_provider_ids: billed
+                .iter()
+                .map(|usage| usage.provider.id.clone())
+                .collect(),
+            cost: !billed.is_empty(),
+            token_usage: members.iter().any(|usage| usage.capabilities.token_usage),
+            mtd_cost: billed.iter().map(|usage| usage.mtd_cost).sum(),
+            remaining: (!remaining.is_empty()).then(|| remaining.iter().sum()),
+            projected_run_out_at: members
+                .iter()
+                .filter_map(|usage| usage.projected_run_out_at)
+                .min(),
+        }
+    }
+}
+
+/// Evaluates a provider's alerts, and those of the groups it is in, after
+/// each successful poll. An alert fires
+/// once when its condition starts to hold, then stays `Triggered` (or
+/// `Acknowledged`) until the condition clears (e.g. a new month starts) and
+/// it re-arms. Snoozed alerts return to `Active` when the snooze ends.
+pub struct AlertEngine {
+    state: Arc<AppState>,
+    on_alert: AlertSink,
+    client: Client,
+}
+
+impl AlertEngine {
+    pub fn new(state: Arc<AppState>, on_alert: AlertSink) -> Result<Self> {
+        Ok(Self {
+            state,
+            on_alert,
+            client: http::build_client(&HttpSettings::default())?,
+        })
+    }
+
+    /// Ends expired snoozes every minute, so alerts for providers that are
+    /// not being polled do not stay snoozed.
+    pub async fn start(self: &Arc<Self>) {
+        let engine = Arc::clone(self);
+
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(SNOOZE_CHECK_INTERVAL);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = engine.expire_snoozes(None, None).await {
+                    error!("Failed to end expired snoozes: {}", e);
+                }
+            }
+        });
+    }
+
+    /// Creates an alert on a provider or, with `group_id`, on the combined
+    /// usage of a group's members.
+    pub async fn create_alert(
+        &self,
+        provider_id: Option<&str>,
+        group_id: Option<&str>,
+        rule: AlertRule,
+        notify_desktop: bool,
+        channel_ids: Vec<String>,
+    ) -> Result<Alert, AppError> {
+        self.state.check_owner(provider_id, group_id).await?;
+        match &rule {
+            AlertRule::BudgetPercentage { percent, .. }
+                if !percent.is_finite() || *percent <= 0.0 =>
+            {
+                return Err(AppError::InvalidRequest(
+                    "The budget percentage must be positive".to_string(),
+                ));
+            }
+            AlertRule::Expression { expression, .. } => {
+                Expression::parse(expression).map_err(|e| {
+                    AppError::InvalidRequest(format!("Invalid expression at {}", e))
+                })?;
+            }
+            _ => {}
+        }
+        self.check_channels(&channel_ids).await?;
+
+        let alert = Alert {
+            id: uuid::Uuid::new_v4().to_string(),
+            provider_id: provider_id.map(str::to_string),
+            group_id: group_id.map(str::to_string),
+            rule,
+            last_fired_at: None,
+            status: AlertStatus::Active,
+            notify_desktop,
+            snoozed_until: None,
+            channel_ids,
+        };
+        self.state.db.insert_alert(&alert).await?;
+
+        Ok(alert)
+    }
+
+    /// Replaces the channels an alert is delivered to.
+    pub async fn set_channels(
+        &self,
+        alert_id: &str,
+        channel_ids: Vec<String>,
+    ) -> Result<(), AppError> {
+        self.check_channels(&channel_ids).await?;
+
+        let mut alert = self.get_alert(alert_id).await?;
+        alert.channel_ids = channel_ids;
+        self.state.db.update_alert(&alert).await?;
+        Ok(())
+    }
+
+    async fn check_channels(&self, channel_ids: &[String]) -> Result<(), AppError> {
+        for channel_id in channel_ids {
+            if self.state.db.get_channel(channel_id).await?.is_none() {
+                return Err(AppError::NotFound {
+                    kind: "Channel",
+                    id: channel_id.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Saves a channel, with its webhook URL or other secret in the keychain.
+    pub async fn add_channel(
+        &self,
+        name: &str,
+        config: ChannelConfig,
+        secret: &str,
+    ) -> Result<NotificationChannel, AppError> {
+        channels::check_channel(&config, secret)?;
+
+        let channel = NotificationChannel {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            config,
+            enabled: true,
+            created_at: Utc::now(),
+        };
+        // Email servers without authentication have no secret.
+        if !secret.is_empty() {
+            KeychainService::store_channel_secret(&channel.id, secret)?;
+        }
+        self.state.db.insert_channel(&channel).await?;
+
+        Ok(channel)
+    }
+
+    pub async fn list_channels(&self) -> Result<Vec<NotificationChannel>, AppError> {
+        Ok(self.state.db.get_channels().await?)
+    }
+
+    /// Sends a sample alert through the channel and reports how it went.
+    pub async fn test_channel(
+        &self,
+        channel_id: &str,
+    ) -> Result<channels::DeliveryRecord, AppError> {
+        let channel = self
+            .state
+            .db
+            .get_channel(channel_id)
+            .await?
+            .ok_or_else(|| AppError::NotFound {
+                kind: "Channel",
+                id: channel_id.to_string(),
+            })?;
+
+        Ok(channels::test(&self.client, &channel).await)
+    }
+
+    /// Deletes a channel and stops delivering alerts to it.
+    pub async fn remove_channel(&self, channel_id: &str) -> Result<(), AppError> {
+        if self.state.db.get_channel(channel_id).await?.is_none() {
+            return Err(AppError::NotFound {
+                kind: "Channel",
+                id: channel_id.to_string(),
+            });
+        }
+
+        for mut alert in self.state.db.get_alerts(None, None).await? {
+            if alert.channel_ids.iter().any(|id| id == channel_id) {
+                alert.channel_ids.retain(|id| id != channel_id);
+                self.state.db.update_alert(&alert).await?;
+            }
+        }
+
+        self.state.db.delete_channel(channel_id).await?;
+        if KeychainService::has_channel_secret(channel_id) {
+            KeychainService::delete_channel_secret(channel_id)?;
+        }
+        Ok(())
+    }
+
+    pub async fn set_notify_desktop(
+        &self,
+        alert_id: &str,
+        notify_desktop: bool,
+    ) -> Result<(), AppError> {
+        let mut alert = self.get_alert(alert_id).await?;
+        alert.notify_desktop = notify_desktop;
+        self.state.db.update_alert(&alert).await?;
+        Ok(())
+    }
+
+    pub async fn list_alerts(
+        &self,
+        provider_id: Option<&str>,
+        group_id: Option<&str>,
+    ) -> Result<Vec<Alert>, AppError> {
+        self.expire_snoozes(provider_id, group_id).await?;
+        Ok(self.state.db.get_alerts(provider_id, group_id).await?)
+    }
+
+    /// Stops evaluating the alert until `until`, then returns it to `Active`.
+    pub async fn snooze(&self, alert_id: &str, until: DateTime<Utc>) -> Result<Alert, AppError> {
+        if until <= Utc::now() {
+            return Err(AppError::InvalidRequest(
+                "A snooze must end in the future".to_string(),
+            ));
+        }
+        let mut alert = self.get_alert(alert_id).await?;
+        if alert.status == AlertStatus::Disabled {
+            return Err(AppError::InvalidRequest(
+                "Disabled alerts cannot be snoozed".to_string(),
+            ));
+        }
+
+        alert.snoozed_until = Some(until);
+        self.set_status(&mut alert, AlertStatus::Snoozed, "snoozed")
+            .await?;
+        Ok(alert)
+    }
+
+    /// Marks a triggered alert as seen. It stays quiet until its condition
+    /// clears and it re-arms.
+    pub async fn acknowledge(&self, alert_id: &str) -> Result<Alert, AppError> {
+        let mut alert = self.get_alert(alert_id).await?;
+        if alert.status != AlertStatus::Triggered {
+            return Err(AppError::InvalidRequest(
+                "Only triggered alerts can be acknowledged".to_string(),
+            ));
+        }
+
+        self.set_status(&mut alert, AlertStatus::Acknowledged, "acknowledged")
+            .await?;
+        Ok(alert)
+    }
+
+    pub async fn disable(&self, alert_id: &str) -> Result<Alert, AppError> {
+        let mut alert = self.get_alert(alert_id).await?;
+        if alert.status != AlertStatus::Disabled {
+            self.set_status(&mut alert, AlertStatus::Disabled, "disabled")
+                .await?;
+        }
+        Ok(alert)
+    }
+
+    /// Re-arms a disabled alert; it fires on the next poll if its condition holds.
+    pub async fn enable(&self, alert_id: &str) -> Result<Alert, AppError> {
+        let mut alert = self.get_alert(alert_id).await?;
+        if alert.status == AlertStatus::Disabled {
+            self.set_status(&mut alert, AlertStatus::Active, "enabled")
+                .await?;
+        }
+        Ok(alert)
+    }
+
+    /// Returns snoozed alerts whose snooze has ended to `Active`.
+    async fn expire_snoozes(
+        &self,
+        provider_id: Option<&str>,
+        group_id: Option<&str>,
+    ) -> Result<()> {
+        let now = Utc::now();
+
+        for mut alert in self.state.db.get_alerts(provider_id, group_id).await? {
+            if alert.status == AlertStatus::Snoozed
+                && alert.snoozed_until.is_none_or(|until| until <= now)
+            {
+                self.set_status(&mut alert, AlertStatus::Active, "snooze_expired")
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Saves the alert in its new state and records the change in `events`.
+    async fn set_status(
+        &self,
+        alert: &mut Alert,
+        to: AlertStatus,
+        reason: &'static str,
+    ) -> Result<()> {
+        let from = alert.status;
+        alert.status = to;
+        if to != AlertStatus::Snoozed {
+            alert.snoozed_until = None;
+        }
+        self.state.db.update_alert(alert).await?;
+
+        let change = StatusChange {
+            alert_id: alert.id.clone(),
+            from,
+            to,
+            reason,
+            snoozed_until: alert.snoozed_until,
+            at: Utc::now(),
+        };
+        let (provider_id, group_id) = (alert.provider_id.as_deref(), alert.group_id.as_deref());
+        if let Err(e) = self
+            .state
+            .db
+            .insert_event(provider_id, group_id, "alert_status_changed", &change)
+            .await
+        {
+            error!(
+                "Failed to record status change of alert {}: {}",
+                alert.id, e
+            );
+        }
+        Ok(())
+    }
+
+    async fn get_alert(&self, alert_id: &str) -> Result<Alert, AppError> {
+        self.state
+            .db
+            .get_alert(alert_id)
+            .await?
+            .ok_or_else(|| AppError::NotFound {
+                kind: "Alert",
+                id: alert_id.to_string(),
+            })
+    }
+
+    pub async fn evaluate(&self, usage: &ProviderUsage) -> Result<()> {
+        let provider_id = &usage.provider.id;
+        self.expire_snoozes(Some(provider_id), None).await?;
+        let alerts = self.state.db.get_alerts(Some(provider_id), None).await?;
+        self.evaluate_alerts(alerts, &Subject::provider(usage))
+            .await?;
+
+        self.evaluate_groups(provider_id).await
+    }
+
+    /// Evaluates the alerts of every group the provider is in, over the
+    /// latest usage of all the group's members.
+    async fn evaluate_groups(&self, provider_id: &str) -> Result<()> {
+        let usage = self.state.usage_snapshot().await;
+
+        for group in self.state.db.get_groups().await? {
+            let members = self.state.group_members(&group).await;
+            if !members.iter().any(|id| id == provider_id) {
+                continue;
+            }
+            self.expire_snoozes(None, Some(&group.id)).await?;
+            let alerts = self.state.db.get_alerts(None, Some(&group.id)).await?;
+            if alerts.is_empty() {
+                continue;
+            }
+
+            let member_usage: Vec<&ProviderUsage> = usage
+                .iter()
+                .filter(|usage| members.contains(&usage.provider.id))
+                .collect();
+            self.evaluate_alerts(alerts, &Subject::group(&group, &member_usage))
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn evaluate_alerts(&self, alerts: Vec<Alert>, subject: &Subject) -> Result<()> {
+        let now = Utc::now();
+
+        for mut alert in alerts {
+            match (self.check(&alert.rule, subject).await?, alert.status) {
+                (Some(breach), AlertStatus::Active) => {
+                    alert.last_fired_at = Some(now);
+                    self.set_status(&mut alert, AlertStatus::Triggered, "fired")
+                        .await?;
+                    self.fire(&alert, subject, breach, now).await;
+                }
+                (None, AlertStatus::Triggered | AlertStatus::Acknowledged) => {
+                    self.set_status(&mut alert, AlertStatus::Active, "cleared")
+                        .await?;
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn fire(&self, alert: &Alert, subject: &Subject, breach: Breach, now: DateTime<Utc>) {
+        let event = Self::event(alert, subject, breach, now);
+        info!("Alert fired: {}", event.message);
+
+        let (provider_id, group_id) = (alert.provider_id.as_deref(), alert.group_id.as_deref());
+        if let Err(e) = self
+            .state
+            .db
+            .insert_event(provider_id, group_id, "alert_triggered", &event)
+            .await
+        {
+            error!("Failed to record alert {}: {}", alert.id, e);
+        }
+        (self.on_alert)(&event);
+
+        // Deliveries retry for up to a few seconds each; do not hold up polling.
+        for channel_id in &alert.channel_ids {
+            let channel = match self.state.db.get_channel(channel_id).await {
+                Ok(Some(channel)) if channel.enabled => channel,
+                Ok(_) => continue,
+                Err(e) => {
+                    error!("Failed to load channel {}: {}", channel_id, e);
+                    continue;
+                }
+            };
+
+            let state = Arc::clone(&self.state);
+            let client = self.client.clone();
+            let event = event.clone();
+            tokio::spawn(async move {
+                channels::deliver_and_log(&state.db, &client, &channel, &event).await;
+            });
+        }
+    }
+
+    /// Whether `rule` holds for `subject`. Rules whose data no provider
+    /// reports never fire.
+    async fn check(&self, rule: &AlertRule, subject: &Subject) -> Result<Option<Breach>> {
+        match rule {
+            AlertRule::BudgetPercentage { percent, period } => {
+                self.check_budget(*percent, *period, subject).await
+            }
+            AlertRule::Anomaly {
+                metric,
+                sensitivity,
+            } => self.check_anomaly(*metric, *sensitivity, subject).await,
+            AlertRule::Expression {
+                expression,
+                is_soft,
+            } => self.check_expression(expression, *is_soft, subject).await,
+            _ => Ok(Self::check_amount(rule, subject)),
+        }
+    }
+
+    fn check_amount(rule: &AlertRule, subject: &Subject) -> Option<Breach> {
+        let severity = |is_soft: bool| {
+            if is_soft {
+                AlertSeverity::Warning
+            } else {
+                AlertSeverity::Critical
+            }
+        };
+
+        match rule {
+            AlertRule::SpendThreshold { amount, is_soft } => {
+                if !subject.cost || subject.mtd_cost < *amount {
+                    return None;
+                }
+                Some(Breach {
+                    current_value: subject.mtd_cost,
+                    threshold: *amount,
+                    unit: "usd",
+                    period: Some(BudgetPeriod::Monthly),
+                    severity: severity(*is_soft),
+                    detail: None,
+                })
+            }
+            AlertRule::CreditThreshold { amount, is_soft } => {
+                let remaining = subject.remaining?;
+                if remaining > *amount {
+                    return None;
+                }
+                Some(Breach {
+                    current_value: remaining,
+                    threshold: *amount,
+                    unit: "credits",
+                    period: None,
+                    severity: severity(*is_soft),
+                    detail: None,
+                })
+            }
+            AlertRule::ProjectedRunOut { days_before } => {
+                let run_out_at = subject.projected_run_out_at?;
+                let days_left = (run_out_at - Utc::now()).num_seconds().max(0) as f64 / 86_400.0;
+                if days_left > f64::from(*days_before) {
+                    return None;
+                }
+                Some(Breach {
+                    current_value: days_left,
+                    threshold: f64::from(*days_before),
+                    unit: "days",
+                    period: None,
+                    severity: if days_left < 1.0 {
+                        AlertSeverity::Critical
+                    } else {
+                        AlertSeverity::Warning
+                    },
+                    detail: None,
+                })
+            }
+            // Need stored budgets and metrics; see the async checks below.
+            AlertRule::BudgetPercentage { .. }
+            | AlertRule::Anomaly { .. }
+            | AlertRule::Expression { .. } => None,
+        }
+    }
+
+    /// Fires below 100% as a warning and at or above it as critical, so the
+    /// same pair of rules suits every provider whatever its budget.
+    async fn check_budget(
+        &self,
+        percent: f64,
+        period: BudgetPeriod,
+        subject: &Subject,
+    ) -> Result<Option<Breach>> {
+        if !subject.cost {
+            return Ok(None);
+        }
+        let Some(budget) = self.state.db.get_budget(&subject.id, period).await? else {
+            return Ok(None);
+        };
+        let Some(limit) = budget.hard_limit.or(budget.soft_limit) else {
+            return Ok(None);
+        };
+
+        let spent = match period {
+            // Adapters report month-to-date spend directly.
+            BudgetPeriod::Monthly => subject.mtd_cost,
+            BudgetPeriod::Daily | BudgetPeriod::Weekly => {
+                let now = Utc::now();
+                let mut start = now.date_naive();
+                if period == BudgetPeriod::Weekly {
+                    start -= Duration::days(start.weekday().num_days_from_monday().into());
+                }
+                let from = start.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc();
+                let spend = self.state.db.get_spend_by_provider(from, now).await?;
+                subject
+                    .cost_provider_ids
+                    .iter()
+                    .filter_map(|id| spend.get(id))
+                    .sum()
+            }
+        };
+
+        let threshold = limit * percent / 100.0;
+        if spent < threshold {
+            return Ok(None);
+        }
+        Ok(Some(Breach {
+            current_value: spent,
+            threshold,
+            unit: "usd",
+            period: Some(period),
+            severity: if percent >= 100.0 {
+                AlertSeverity::Critical
+            } else {
+                AlertSeverity::Warning
+            },
+            detail: None,
+        }))
+    }
+
+    /// Fires as critical when the hour is twice as far out as the
+    /// sensitivity requires.
+    async fn check_anomaly(
+        &self,
+        metric: AnomalyMetric,
+        sensitivity: AnomalySensitivity,
+        subject: &Subject,
+    ) -> Result<Option<Breach>> {
+        let supported = match metric {
+            AnomalyMetric::Spend => subject.cost,
+            AnomalyMetric::Tokens => subject.token_usage,
+        };
+        if !supported {
+            return Ok(None);
+        }
+        let provider_ids = match metric {
+            AnomalyMetric::Spend => &subject.cost_provider_ids,
+            AnomalyMetric::Tokens => &subject.provider_ids,
+        };
+        let Some(anomaly) = anomaly::detect(
+            &self.state.db,
+            provider_ids,
+            metric,
+            sensitivity,
+            Utc::now(),
+        )
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        let unit = match metric {
+            AnomalyMetric::Spend => "usd",
+            AnomalyMetric::Tokens => "tokens",
+        };
+        let subject = match &anomaly.model {
+            Some(model) => format!("{} on {}", subject.name, model),
+            None => subject.name.clone(),
+        };
+        let detail = format!(
+            "{} used {} in the hour from {}, against a usual {}.",
+            subject,
+            channels::format_value(anomaly.value, unit),
+            anomaly.hour.format("%H:%M UTC"),
+            channels::format_value(anomaly.baseline, unit)
+        );
+
+        Ok(Some(Breach {
+            current_value: anomaly.value,
+            threshold: anomaly.threshold,
+            unit,
+            period: None,
+            severity: if anomaly.score >= 2.0 * sensitivity.threshold() {
+                AlertSeverity::Critical
+            } else {
+                AlertSeverity::Warning
+            },
+            detail: Some(detail),
+        }))
+    }
+
+    async fn check_expression(
+        &self,
+        expression: &str,
+        is_soft: bool,
+        subject: &Subject,
+    ) -> Result<Option<Breach>> {
+        // Validated when the alert was created.
+        let expression =
+            Expression::parse(expression).map_err(|e| anyhow!("Invalid expression at {}", e))?;
+        let evaluation = expression
+            .evaluate(&self.state.db, &subject.provider_ids, Utc::now())
+            .await?;
+        if !evaluation.matched {
+            return Ok(None);
+        }
+
+        let values = evaluation
+            .values
+            .iter()
+            .map(|(text, value)| format!("{} = {}", text, channels::format_value(*value, "value")))
+            .collect::<Vec<_>>()
+            .join(", ");
+        Ok(Some(Breach {
+            current_value: evaluation.left,
+            threshold: evaluation.right,
+            unit: "value",
+            period: None,
+            severity: if is_soft {
+                AlertSeverity::Warning
+            } else {
+                AlertSeverity::Critical
+            },
+            detail: Some(values),
+        }))
+    }
+
+    fn event(alert: &Alert, subject: &Subject, breach: Breach, now: DateTime<Utc>) -> AlertEvent {
+        let name = &subject.name;
+        let level = match breach.severity {
+            AlertSeverity::Warning => "warning",
+            AlertSeverity::Critical => "limit reached",
+        };
+
+        let (title, message) = match &alert.rule {
+            AlertRule::BudgetPercentage { percent, period } => {
+                let (adjective, current) = match period {
+                    BudgetPeriod::Daily => ("daily", "today"),
+                    BudgetPeriod::Weekly => ("weekly", "this week"),
+                    BudgetPeriod::Monthly => ("monthly", "this month"),
+                };
+                let limit = breach.threshold / percent * 100.0;
+                (
+                    format!("{}: {:.0}% of {} budget", name, percent, adjective),
+                    format!(
+                        "{} has spent ${:.2} {}, {:.0}% of its ${:.2} {} budget.",
+                        name,
+                        breach.current_value,
+                        current,
+                        breach.current_value / limit.max(f64::EPSILON) * 100.0,
+                        limit,
+                        adjective
+                    ),
+                )
+            }
+            AlertRule::Expression { expression, .. } => (
+                format!("{}: alert condition met", name),
+                format!(
+                    "{} matched {}: {}.",
+                    name,
+                    expression,
+                    breach.detail.clone().unwrap_or_default()
+                ),
+            ),
+            AlertRule::Anomaly { .. } => (
+                format!("{}: unusual usage", name),
+                breach.detail.clone().unwrap_or_default(),
+            ),
+            AlertRule::ProjectedRunOut { .. } => (
+                format!("{}: projected to run out", name),
+                format!(
+                    "At its recent burn rate, {} runs out of credit or budget in {:.1} days.",
+                    name, breach.current_value
+                ),
+            ),
+            AlertRule::CreditThreshold { .. } => (
+                format!("{}: low credit {}", name, level),
+                format!(
+                    "{} has {:.2} left, at or below the {:.2} threshold.",
+                    name, breach.current_value, breach.threshold
+                ),
+            ),
+            _ => (
+                format!("{}: budget {}", name, level),
+                format!(
+                    "{} has spent ${:.2} this month, {:.0}% of the ${:.2} limit.",
+                    name,
+                    breach.current_value,
+                    breach.current_value / breach.threshold.max(f64::EPSILON) * 100.0,
+                    breach.threshold
+                ),
+            ),
+        };
+
+        AlertEvent {
+            alert_id: alert.id.clone(),
+            provider_id: alert.provider_id.clone(),
+            group_id: alert.group_id.clone(),
+            provider_name: name.clone(),
+            rule: alert.rule.clone(),
+            severity: breach.severity,
+            current_value: breach.current_value,
+            threshold: breach.threshold,
+            unit: breach.unit.to_string(),
+            period: breach.period,
+            title,
+            message,
+            notify_desktop: alert.notify_desktop,
+            triggered_at: now,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Database;
+    use crate::models::{Provider, ProviderCapabilities, ProviderType};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    async fn engine() -> (AlertEngine, Arc<AtomicUsize>) {
+        let db = Database::in_memory().await.unwrap();
+        db.insert_provider(&provider()).await.unwrap();
+        let state = Arc::new(AppState::new(db).await.unwrap());
+        let fired = Arc::new(AtomicUsize::new(0));
+        let count = Arc::clone(&fired);
+        let engine = AlertEngine::new(
+            state,
+            Arc::new(move |_: &AlertEvent| {
+                count.fetch_add(1, Ordering::SeqCst);
+            }),
+        )
+        .unwrap();
+        (engine, fired)
+    }
+
+    fn provider() -> Provider {
+        Provider {
+            id: "p1".to_string(),
+            name: "OpenAI".to_string(),
+            provider_type: ProviderType::OpenAI,
+            api_key_ref: None,
+            enabled: true,
+            created_at: Utc::now(),
+            settings: Default::default(),
+        }
+    }
+
+    fn usage(mtd_cost: f64) -> ProviderUsage {
+        ProviderUsage {
+            provider: provider(),
+            today_tokens: 0,
+            today_cost: 0.0,
+            mtd_tokens: 0,
+            mtd_cost,
+            balance: None,
+            credits: None,
+            budget_used_percentage: None,
+            last_error: None,
+            capabilities: ProviderCapabilities {
+                cost: true,
+                ..Default::default()
+            },
+            last_synced_at: None,
+            stale: false,
+            projected_run_out_at: None,
+            projected_period_spend: None,
+            notional_mtd_cost: None,
+        }
+    }
+
+    async fn spend_alert(engine: &AlertEngine) -> Alert {
+        engine
+            .create_alert(
+                Some("p1"),
+                None,
+                AlertRule::SpendThreshold {
+                    amount: 10.0,
+                    is_soft: false,
+                },
+                true,
+                Vec::new(),
+            )
+            .await
+            .unwrap()
+    }
+
+    async fn status(engine: &AlertEngine, alert_id: &str) -> AlertStatus {
+        engine.get_alert(alert_id).await.unwrap().status
+    }
+
+    #[tokio::test]
+    async fn fires_once_then_re_arms_when_the_condition_clears() {
+        let (engine, fired) = engine().await;
+        let alert = spend_alert(&engine).await;
+
+        engine.evaluate(&usage(20.0)).await.unwrap();
+        engine.evaluate(&usage(25.0)).await.unwrap();
+        assert_eq!(status(&engine, &alert.id).await, AlertStatus::Triggered);
+        assert_eq!(fired.load(Ordering::SeqCst), 1);
+
+        engine.acknowledge(&alert.id).await.unwrap();
+        assert!(matches!(
+            engine.acknowledge(&alert.id).await,
+            Err(AppError::InvalidRequest(_))
+        ));
+        engine.evaluate(&usage(30.0)).await.unwrap();
+        assert_eq!(status(&engine, &alert.id).await, AlertStatus::Acknowledged);
+
+        // A new month starts.
+        engine.evaluate(&usage(1.0)).await.unwrap();
+        assert_eq!(status(&engine, &alert.id).await, AlertStatus::Active);
+        engine.evaluate(&usage(12.0)).await.unwrap();
+        assert_eq!(fired.load(Ordering::SeqCst), 2);
+
+        let now = Utc::now();
+        let changes = engine
+            .state
+            .db
+            .get_events(
+                "alert_status_changed",
+                now - Duration::minutes(1),
+                now + Duration::minutes(1),
+            )
+            .await
+            .unwrap();
+        assert_eq!(changes.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn snoozed_alerts_stay_quiet_until_the_snooze_ends() {
+        let (engine, fired) = engine().await;
+        let alert = spend_alert(&engine).await;
+
+        assert!(matches!(
+            engine
+                .snooze(&alert.id, Utc::now() - Duration::minutes(1))
+                .await,
+            Err(AppError::InvalidRequest(_))
+        ));
+        let snoozed = engine
+            .snooze(&alert.id, Utc::now() + Duration::hours(1))
+            .await
+            .unwrap();
+        engine.evaluate(&usage(20.0)).await.unwrap();
+        assert_eq!(status(&engine, &alert.id).await, AlertStatus::Snoozed);
+        assert_eq!(fired.load(Ordering::SeqCst), 0);
+
+        // The snooze ran out while nothing was polling.
+        let mut expired = snoozed;
+        expired.snoozed_until = Some(Utc::now() - Duration::minutes(1));
+        engine.state.db.update_alert(&expired).await.unwrap();
+        let alerts = engine.list_alerts(Some("p1"), None).await.unwrap();
+        assert_eq!(alerts[0].status, AlertStatus::Active);
+        assert_eq!(alerts[0].snoozed_until, None);
+
+        engine.evaluate(&usage(20.0)).await.unwrap();
+        assert_eq!(fired.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn disabled_alerts_are_not_evaluated() {
+        let (engine, fired) = engine().await;
+        let alert = spend_alert(&engine).await;
+
+        engine.disable(&alert.id).await.unwrap();
+        assert!(matches!(
+            engine
+                .snooze(&alert.id, Utc::now() + Duration::hours(1))
+                .await,
+            Err(AppError::InvalidRequest(_))
+        ));
+        engine.evaluate(&usage(20.0)).await.unwrap();
+        assert_eq!(status(&engine, &alert.id).await, AlertStatus::Disabled);
+        assert_eq!(fired.load(Ordering::SeqCst), 0);
+
+        engine.enable(&alert.id).await.unwrap();
+        engine.evaluate(&usage(20.0)).await.unwrap();
+        assert_eq!(status(&engine, &alert.id).await, AlertStatus::Triggered);
+        assert_eq!(fired.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn unknown_alerts_are_not_found() {
+        let (engine, _) = engine().await;
+
+        assert!(matches!(
+            engine.acknowledge("missing").await,
+            Err(AppError::NotFound { kind: "Alert", .. })
+        ));
+    }
+}
@@ -0,0 +1,426 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::db::Database;
+use crate::models::{AnomalyMetric, AnomalySensitivity, Metric, MetricType};
+
+/// Hours of history each hour is compared with.
+const BASELINE_DAYS: i64 = 14;
+/// Too little history makes every busy hour look unusual.
+const MIN_BASELINE_HOURS: usize = 24;
+/// Growth over a longer gap between polls, e.g. after the app was closed,
+/// is not recorded; it would look like a spike in a single hour.
+const MAX_GROWTH_GAP_HOURS: i64 = 2;
+/// Scales the median absolute deviation to a standard deviation for
+/// normally distributed data.
+const MAD_SCALE: f64 = 1.4826;
+
+/// An hour far above the usual level of its series.
+#[derive(Debug, Clone)]
+pub struct Anomaly {
+    /// `None` for the provider's total.
+    pub model: Option<String>,
+    pub hour: DateTime<Utc>,
+    pub value: f64,
+    /// The median hour.
+    pub baseline: f64,
+    /// The value above which an hour counts as unusual.
+    pub threshold: f64,
+    /// Deviations above the median.
+    pub score: f64,
+}
+
+impl AnomalySensitivity {
+    /// Deviations above the median at which an hour fires.
+    pub fn threshold(self) -> f64 {
+        match self {
+            AnomalySensitivity::Low => 8.0,
+            AnomalySensitivity::Medium => 5.0,
+            AnomalySensitivity::High => 3.5,
+        }
+    }
+}
+
+/// The smallest deviation that counts, so a provider that is usually idle
+/// does not fire on the first few cents or tokens of the day.
+fn min_deviation(metric: AnomalyMetric) -> f64 {
+    match metric {
+        AnomalyMetric::Spend => 1.0,
+        AnomalyMetric::Tokens => 50_000.0,
+    }
+}
+
+/// Stored USD spend and tokens per model, taken before and after a poll
+/// stores its metrics.
+pub type UsageTotals = HashMap<Option<String>, (f64, f64)>;
+
+pub async fn usage_totals(
+    db: &Database,
+    provider_id: &str,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<UsageTotals> {
+    Ok(db
+        .get_usage_by_model(provider_id, from, to)
+        .await?
+        .into_iter()
+        .map(|(model, cost, tokens)| (model, (cost, tokens)))
+        .collect())
+}
+
+/// What a poll added, per model, as `SpendDelta` and `TokensDelta` metrics
+/// stamped at `now`. Providers report hourly, daily or running totals, and
+/// rows are replaced as buckets fill up, so the change in stored totals is
+/// the only measure of hourly usage that works for all of them. Nothing is
+/// recorded for the first poll or after a long gap.
+pub fn growth(
+    before: &UsageTotals,
+    after: &UsageTotals,
+    last_synced_at: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> Vec<Metric> {
+    if last_synced_at.is_none_or(|at| now - at > Duration::hours(MAX_GROWTH_GAP_HOURS)) {
+        return Vec::new();
+    }
+
+    let mut metrics = Vec::new();
+    for (model, (cost, tokens)) in after {
+        let (cost_before, tokens_before) = before.get(model).copied().unwrap_or_default();
+        let dimensions: HashMap<String, String> = model
+            .iter()
+            .map(|model| ("model".to_string(), model.clone()))
+            .collect();
+
+        // Revised figures can shrink; that is not negative usage.
+        for (metric_type, value, unit) in [
+            (MetricType::SpendDelta, cost - cost_before, "usd"),
+            (MetricType::TokensDelta, tokens - tokens_before, "tokens"),
+        ] {
+            if value > 0.0 {
+                metrics.push(Metric {
+                    id: uuid::Uuid::new_v4().to_string(),
+                    provider_id: String::new(),
+                    metric_type,
+                    value,
+                    unit: unit.to_string(),
+                    timestamp: now,
+                    dimensions: dimensions.clone(),
+                });
+            }
+        }
+    }
+
+    metrics
+}
+
+/// Compares the growth in the current and previous hour, for the
+/// providers' total and each model, with the median and median absolute
+/// deviation of the hours before them. Returns the most unusual series, if
+/// any exceeds the sensitivity. The current hour is included while still in
+/// progress, so a runaway loop is caught within the hour.
+pub async fn detect(
+    db: &Database,
+    provider_ids: &[String],
+    metric: AnomalyMetric,
+    sensitivity: AnomalySensitivity,
+    now: DateTime<Utc>,
+) -> Result<Option<Anomaly>> {
+    let current_hour = now.duration_trunc(Duration::hours(1))?;
+    let recent_from = current_hour - Duration::hours(1);
+    let from = recent_from - Duration::days(BASELINE_DAYS);
+
+    let mut series: HashMap<Option<String>, BTreeMap<DateTime<Utc>, f64>> = HashMap::new();
+    for (hour, model, cost, tokens) in db.get_hourly_usage(provider_ids, from, now).await? {
+        let value = match metric {
+            AnomalyMetric::Spend => cost,
+            AnomalyMetric::Tokens => tokens,
+        };
+        *series.entry(None).or_default().entry(hour).or_default() += value;
+        if model.is_some() {
+            *series.entry(model).or_default().entry(hour).or_default() += value;
+        }
+    }
+
+    let threshold = sensitivity.threshold();
+    let min_deviation = min_deviation(metric);
+
+    let mut worst: Option<Anomaly> = None;
+    for (model, hours) in series {
+        let Some(first) = hours.keys().next().copied() else {
+            continue;
+        };
+        // Quiet hours since the series started count as zero.
+        let baseline: Vec<f64> =
+            std::iter::successors(Some(first), |hour| Some(*hour + Duration::hours(1)))
+                .take_while(|hour| *hour < recent_from)
+                .map(|hour| hours.get(&hour).copied().unwrap_or_default())
+                .collect();
+        if baseline.len() < MIN_BASELINE_HOURS {
+            continue;
+        }
+
+        let median = median(baseline.clone());
+        let mad = median_of_deviations(&baseline, median);
+        let scale = (MAD_SCALE * mad).max(min_deviation);
+
+        for hour in [recent_from, current_hour] {
+            let value = hours.get(&hour).copied().unwrap_or_default();
+            let score = (value - median) / scale;
+            if score < threshold || value - median < min_deviation {
+                continue;
+            }
+            // On a tie, name the model rather than the provider's total.
+            if worst
+                .as_ref()
+                .is_none_or(|w| score > w.score || (score == w.score && w.model.is_none()))
+            {
+                worst = Some(Anomaly {
+                    model: model.clone(),
+                    hour,
+                    value,
+                    baseline: median,
+                    threshold: median + threshold * scale,
+                    score,
+                });
+            }
+        }
+    }
+
+    Ok(worst)
+}
+
+fn median(mut values: Vec<f64>) -> f64 {
+    values.sort_by(f64::total_cmp);
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    }
+}
+
+fn median_of_deviations(values: &[f64], median_value: f64) -> f64 {
+    median(
+        values
+            .iter()
+            .map(|value| (value - median_value).abs())
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Provider, ProviderType};
+
+    async fn database() -> Database {
+        let db = Database::in_memory().await.unwrap();
+        db.insert_provider(&Provider {
+            id: "p1".to_string(),
+            name: "Provider".to_string(),
+            provider_type: ProviderType::OpenAI,
+            api_key_ref: None,
+            enabled: true,
+            created_at: Utc::now(),
+            settings: Default::default(),
+        })
+        .await
+        .unwrap();
+        db
+    }
+
+    async fn spend(db: &Database, hour: DateTime<Utc>, model: &str, value: f64) {
+        let mut metric = Metric {
+            id: uuid::Uuid::new_v4().to_string(),
+            provider_id: "p1".to_string(),
+            metric_type: MetricType::SpendDelta,
+            value,
+            unit: "usd".to_string(),
+            timestamp: hour + Duration::minutes(10),
+            dimensions: HashMap::from([("model".to_string(), model.to_string())]),
+        };
+        db.insert_metric(&metric).await.unwrap();
+        metric.id = uuid::Uuid::new_v4().to_string();
+        metric.metric_type = MetricType::TokensDelta;
+        metric.unit = "tokens".to_string();
+        metric.value = value * 10_000.0;
+        db.insert_metric(&metric).await.unwrap();
+    }
+
+    /// Half past the current hour, so metrics stamped early in the hour are
+    /// in the past.
+    fn half_past() -> DateTime<Utc> {
+        Utc::now().duration_trunc(Duration::hours(1)).unwrap() + Duration::minutes(30)
+    }
+
+    /// Three days of $2-4 an hour on one model, ending before `now`'s hour.
+    async fn history(db: &Database, now: DateTime<Utc>) {
+        let current_hour = now.duration_trunc(Duration::hours(1)).unwrap();
+        for hours_ago in 2..72 {
+            spend(
+                db,
+                current_hour - Duration::hours(hours_ago),
+                "gpt-4o",
+                2.0 + (hours_ago % 3) as f64,
+            )
+            .await;
+        }
+    }
+
+    #[test]
+    fn median_and_deviation() {
+        assert_eq!(median(vec![3.0, 1.0, 2.0]), 2.0);
+        assert_eq!(median(vec![4.0, 1.0, 3.0, 2.0]), 2.5);
+        assert_eq!(median_of_deviations(&[1.0, 2.0, 3.0, 4.0, 100.0], 3.0), 1.0);
+    }
+
+    #[test]
+    fn growth_is_recorded_per_model_between_polls() {
+        let now = Utc::now();
+        let before = UsageTotals::from([
+            (Some("gpt-4o".to_string()), (10.0, 1000.0)),
+            (None, (1.0, 0.0)),
+        ]);
+        let after = UsageTotals::from([
+            (Some("gpt-4o".to_string()), (12.5, 1500.0)),
+            (Some("o1".to_string()), (3.0, 200.0)),
+            // Revised down; not negative usage.
+            (None, (0.5, 0.0)),
+        ]);
+
+        let mut growth = growth(&before, &after, Some(now - Duration::minutes(5)), now);
+        growth.sort_by(|a, b| a.value.total_cmp(&b.value));
+        let values: Vec<_> = growth
+            .iter()
+            .map(|m| {
+                (
+                    format!("{:?}", m.metric_type),
+                    m.dimensions.get("model").cloned(),
+                    m.value,
+                )
+            })
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                ("SpendDelta".to_string(), Some("gpt-4o".to_string()), 2.5),
+                ("SpendDelta".to_string(), Some("o1".to_string()), 3.0),
+                ("TokensDelta".to_string(), Some("o1".to_string()), 200.0),
+                ("TokensDelta".to_string(), Some("gpt-4o".to_string()), 500.0),
+            ]
+        );
+        assert!(growth.iter().all(|m| m.timestamp == now));
+    }
+
+    #[test]
+    fn growth_is_skipped_after_a_gap() {
+        let now = Utc::now();
+        let after = UsageTotals::from([(None, (5.0, 100.0))]);
+
+        assert!(growth(&UsageTotals::new(), &after, None, now).is_empty());
+        assert!(growth(
+            &UsageTotals::new(),
+            &after,
+            Some(now - Duration::hours(3)),
+            now
+        )
+        .is_empty());
+        assert_eq!(
+            growth(
+                &UsageTotals::new(),
+                &after,
+                Some(now - Duration::hours(1)),
+                now
+            )
+            .len(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn flags_a_spike_on_a_model() {
+        let db = database().await;
+        let now = half_past();
+        history(&db, now).await;
+        spend(
+            &db,
+            now.duration_trunc(Duration::hours(1)).unwrap(),
+            "gpt-4o",
+            40.0,
+        )
+        .await;
+        let providers = ["p1".to_string()];
+
+        let anomaly = detect(
+            &db,
+            &providers,
+            AnomalyMetric::Spend,
+            AnomalySensitivity::Medium,
+            now,
+        )
+        .await
+        .unwrap()
+        .expect("a $40 hour should be unusual");
+        // The model and the provider's total score the same; the model is named.
+        assert_eq!(anomaly.model.as_deref(), Some("gpt-4o"));
+        assert_eq!(anomaly.value, 40.0);
+        assert_eq!(anomaly.baseline, 3.0);
+        assert!(anomaly.score >= AnomalySensitivity::Medium.threshold());
+
+        assert!(detect(
+            &db,
+            &providers,
+            AnomalyMetric::Tokens,
+            AnomalySensitivity::Medium,
+            now
+        )
+        .await
+        .unwrap()
+        .is_some());
+    }
+
+    #[tokio::test]
+    async fn ignores_usual_hours_and_short_history() {
+        let db = database().await;
+        let now = half_past();
+        let providers = ["p1".to_string()];
+
+        // Less than a day of history.
+        let current_hour = now.duration_trunc(Duration::hours(1)).unwrap();
+        for hours_ago in 2..12 {
+            spend(
+                &db,
+                current_hour - Duration::hours(hours_ago),
+                "gpt-4o",
+                3.0,
+            )
+            .await;
+        }
+        spend(&db, current_hour, "gpt-4o", 40.0).await;
+        assert!(detect(
+            &db,
+            &providers,
+            AnomalyMetric::Spend,
+            AnomalySensitivity::High,
+            now
+        )
+        .await
+        .unwrap()
+        .is_none());
+
+        let db = database().await;
+        history(&db, now).await;
+        spend(&db, current_hour, "gpt-4o", 4.0).await;
+        assert!(detect(
+            &db,
+            &providers,
+            AnomalyMetric::Spend,
+            AnomalySensitivity::High,
+            now
+        )
+        .await
+        .unwrap()
+        .is_none());
+    }
+}
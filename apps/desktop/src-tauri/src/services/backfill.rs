@@ -0,0 +1,271 @@
+use anyhow::Result;
+use chrono::{DateTime, Datelike, Duration, Utc};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::task::AbortHandle;
+use tracing::{error, info, warn};
+
+use crate::db::BackfillCheckpoint;
+use crate::error::AppError;
+use crate::models::{BackfillProgress, Metric, Provider, ProviderErrorState};
+use crate::providers::{http::ProviderError, ProviderAdapter};
+use crate::services::AppState;
+
+// Backfills share the provider's quota with live polling, so they go one day
+// at a time with a pause in between rather than as fast as the API allows.
+const CHUNK_PAUSE: std::time::Duration = std::time::Duration::from_secs(1);
+const OUTAGE_PAUSE: std::time::Duration = std::time::Duration::from_secs(60);
+const RATE_LIMIT_PAUSE: std::time::Duration = std::time::Duration::from_secs(300);
+const MAX_OUTAGE_RETRIES: u32 = 3;
+
+/// Called with every progress update; the app forwards these as
+/// `backfill-progress` events.
+pub type ProgressSink = Arc<dyn Fn(BackfillProgress) + Send + Sync>;
+
+/// Fetches usage history older than the current month, which
+/// `MonitorService` already keeps up to date. Progress is checkpointed after
+/// every day so an interrupted backfill resumes where it stopped.
+pub struct BackfillService {
+    state: Arc<AppState>,
+    on_progress: ProgressSink,
+    running: Mutex<HashMap<String, AbortHandle>>,
+}
+
+impl BackfillService {
+    pub fn new(state: Arc<AppState>, on_progress: ProgressSink) -> Self {
+        Self {
+            state,
+            on_progress,
+            running: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Starts a backfill for `provider_id`, resuming an unfinished one or
+    /// starting over if the last one completed. Returns `false` if one is
+    /// already running.
+    pub async fn start(self: &Arc<Self>, provider_id: &str) -> Result<bool, AppError> {
+        let provider = self
+            .state
+            .providers
+            .read()
+            .await
+            .iter()
+            .find(|p| p.id == provider_id)
+            .cloned()
+            .ok_or_else(|| AppError::NotFound {
+                kind: "Provider",
+                id: provider_id.to_string(),
+            })?;
+
+        let mut running = self.running.lock().unwrap();
+        if running
+            .get(provider_id)
+            .is_some_and(|handle| !handle.is_finished())
+        {
+            return Ok(false);
+        }
+
+        let service = Arc::clone(self);
+        let handle = tokio::spawn(async move {
+            if let Err(e) = service.run(&provider).await {
+                error!("Backfill for {} stopped: {}", provider.name, e);
+                service
+                    .report_error(&provider.id, AppError::from(e).to_state())
+                    .await;
+            }
+        });
+        running.insert(provider_id.to_string(), handle.abort_handle());
+
+        Ok(true)
+    }
+
+    /// Resumes every backfill that was interrupted, e.g. by quitting the app.
+    pub async fn resume_all(self: &Arc<Self>) -> Result<()> {
+        let provider_ids: Vec<String> = self
+            .state
+            .providers
+            .read()
+            .await
+            .iter()
+            .filter(|p| p.enabled)
+            .map(|p| p.id.clone())
+            .collect();
+
+        for provider_id in provider_ids {
+            let unfinished = self
+                .state
+                .db
+                .get_backfill_checkpoint(&provider_id)
+                .await?
+                .is_some_and(|checkpoint| checkpoint.completed_at.is_none());
+
+            if unfinished {
+                self.start(&provider_id).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Stops a running backfill. Its checkpoint is kept, so it can resume.
+    /// Returns `false` if none was running.
+    pub async fn cancel(&self, provider_id: &str) -> Result<bool> {
+        let Some(handle) = self.running.lock().unwrap().remove(provider_id) else {
+            return Ok(false);
+        };
+        if handle.is_finished() {
+            return Ok(false);
+        }
+        handle.abort();
+
+        if let Some(checkpoint) = self.state.db.get_backfill_checkpoint(provider_id).await? {
+            let mut progress = self.progress(&checkpoint, Self::start_of_month(), None);
+            progress.cancelled = true;
+            (self.on_progress)(progress);
+        }
+
+        Ok(true)
+    }
+
+    async fn run(&self, provider: &Provider) -> Result<()> {
+        let adapter = AppState::get_provider_adapter(provider)?;
+        let backfill_days = adapter.capabilities().backfill_days;
+        if backfill_days == 0 {
+            return Ok(());
+        }
+
+        let api_key = AppState::api_key_for(provider)?;
+        let start_of_month = Self::start_of_month();
+
+        let mut checkpoint = match self.state.db.get_backfill_checkpoint(&provider.id).await? {
+            Some(checkpoint) if checkpoint.completed_at.is_none() => checkpoint,
+            _ => BackfillCheckpoint {
+                provider_id: provider.id.clone(),
+                fetched_back_to: start_of_month,
+                target: start_of_month - Duration::days(backfill_days as i64),
+                completed_at: None,
+            },
+        };
+
+        info!(
+            "Backfilling {} from {} back to {}",
+            provider.name, checkpoint.fetched_back_to, checkpoint.target
+        );
+
+        while checkpoint.fetched_back_to > checkpoint.target {
+            let to = checkpoint.fetched_back_to;
+            let from = (to - Duration::days(1)).max(checkpoint.target);
+
+            let metrics = Self::fetch_chunk(adapter.as_ref(), &api_key, from, to).await?;
+            self.state.store_metrics(&provider.id, metrics).await?;
+
+            checkpoint.fetched_back_to = from;
+            self.state.db.save_backfill_checkpoint(&checkpoint).await?;
+            self.report(&checkpoint, start_of_month, None);
+
+            tokio::time::sleep(CHUNK_PAUSE).await;
+        }
+
+        checkpoint.completed_at = Some(Utc::now());
+        self.state.db.save_backfill_checkpoint(&checkpoint).await?;
+        self.report(&checkpoint, start_of_month, None);
+
+        info!("Backfill for {} complete", provider.name);
+        Ok(())
+    }
+
+    /// One day of history. Rate limits wait as long as the provider asks and
+    /// never give up; outages are retried a few times.
+    async fn fetch_chunk(
+        adapter: &dyn ProviderAdapter,
+        api_key: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Metric>> {
+        let mut outages = 0;
+
+        loop {
+            let error = match adapter.fetch_usage(api_key, from, to).await {
+                Ok(metrics) => return Ok(metrics),
+                Err(e) => e,
+            };
+
+            let pause = match error.downcast_ref::<ProviderError>() {
+                Some(ProviderError::RateLimited { retry_after }) => {
+                    retry_after.unwrap_or(RATE_LIMIT_PAUSE)
+                }
+                Some(e) if e.is_retryable() && outages < MAX_OUTAGE_RETRIES => {
+                    outages += 1;
+                    OUTAGE_PAUSE
+                }
+                _ => return Err(error),
+            };
+
+            warn!("Backfill paused for {:?} after: {}", pause, error);
+            tokio::time::sleep(pause).await;
+        }
+    }
+
+    fn report(
+        &self,
+        checkpoint: &BackfillCheckpoint,
+        start_of_month: DateTime<Utc>,
+        error: Option<ProviderErrorState>,
+    ) {
+        (self.on_progress)(self.progress(checkpoint, start_of_month, error));
+    }
+
+    fn progress(
+        &self,
+        checkpoint: &BackfillCheckpoint,
+        start_of_month: DateTime<Utc>,
+        error: Option<ProviderErrorState>,
+    ) -> BackfillProgress {
+        let span = (start_of_month - checkpoint.target).num_seconds();
+        let fetched = (start_of_month - checkpoint.fetched_back_to).num_seconds();
+        let percent = if span > 0 {
+            (fetched as f64 / span as f64 * 100.0).clamp(0.0, 100.0)
+        } else if checkpoint.completed_at.is_some() {
+            100.0
+        } else {
+            0.0
+        };
+
+        BackfillProgress {
+            provider_id: checkpoint.provider_id.clone(),
+            fetched_back_to: checkpoint.fetched_back_to,
+            target: checkpoint.target,
+            percent,
+            done: checkpoint.completed_at.is_some(),
+            cancelled: false,
+            error,
+        }
+    }
+
+    async fn report_error(&self, provider_id: &str, error: ProviderErrorState) {
+        let checkpoint = match self.state.db.get_backfill_checkpoint(provider_id).await {
+            Ok(Some(checkpoint)) => checkpoint,
+            _ => {
+                let start_of_month = Self::start_of_month();
+                BackfillCheckpoint {
+                    provider_id: provider_id.to_string(),
+                    fetched_back_to: start_of_month,
+                    target: start_of_month,
+                    completed_at: None,
+                }
+            }
+        };
+
+        self.report(&checkpoint, Self::start_of_month(), Some(error));
+    }
+
+    fn start_of_month() -> DateTime<Utc> {
+        Utc::now()
+            .date_naive()
+            .with_day(1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+            .and_utc()
+    }
+}
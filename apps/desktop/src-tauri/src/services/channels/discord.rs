@@ -0,0 +1,54 @@
+use serde_json::{json, Value};
+
+use super::format_value;
+use crate::models::{AlertEvent, AlertSeverity};
+
+const WARNING_COLOR: u32 = 0xf59e0b;
+const CRITICAL_COLOR: u32 = 0xef4444;
+
+/// A channel-webhook message with a single embed, colored by severity.
+pub fn payload(event: &AlertEvent) -> Value {
+    let color = match event.severity {
+        AlertSeverity::Warning => WARNING_COLOR,
+        AlertSeverity::Critical => CRITICAL_COLOR,
+    };
+
+    json!({
+        "username": "AI Usage Monitor",
+        "embeds": [
+            {
+                "title": event.title,
+                "description": event.message,
+                "color": color,
+                "timestamp": event.triggered_at.to_rfc3339(),
+                "fields": [
+                    { "name": "Current", "value": format_value(event.current_value, &event.unit), "inline": true },
+                    { "name": "Threshold", "value": format_value(event.threshold, &event.unit), "inline": true },
+                    { "name": "Provider", "value": event.provider_name, "inline": true }
+                ]
+            }
+        ]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::channels::tests::event;
+
+    #[test]
+    fn builds_embed_colored_by_severity() {
+        let mut event = event();
+        let embed = payload(&event)["embeds"][0].clone();
+
+        assert_eq!(embed["title"], "Spend limit reached");
+        assert_eq!(embed["color"], CRITICAL_COLOR);
+        assert_eq!(embed["timestamp"], "2026-10-18T12:30:00+00:00");
+        assert_eq!(embed["fields"][0]["value"], "$123.46");
+        assert_eq!(embed["fields"][1]["value"], "$100.00");
+        assert_eq!(embed["fields"][2]["value"], "Production OpenAI");
+
+        event.severity = AlertSeverity::Warning;
+        assert_eq!(payload(&event)["embeds"][0]["color"], WARNING_COLOR);
+    }
+}
@@ -0,0 +1,174 @@
+use anyhow::{anyhow, bail, Result};
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::time::Duration;
+use tracing::warn;
+
+use super::{format_value, RETRY_BACKOFF};
+use crate::error::AppError;
+use crate::models::{AlertEvent, AlertSeverity, ChannelConfig, SmtpSecurity};
+
+const SMTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A rendered email: plain text for clients that want it, HTML for the rest.
+pub struct Email {
+    pub subject: String,
+    pub text: String,
+    pub html: String,
+}
+
+/// Rejects addresses and servers that cannot work before saving.
+pub fn check(config: &ChannelConfig, password: &str) -> Result<(), AppError> {
+    let ChannelConfig::Email {
+        host,
+        username,
+        from,
+        to,
+        digest_hour,
+        ..
+    } = config
+    else {
+        return Ok(());
+    };
+    let invalid = |message: String| Err(AppError::InvalidRequest(message));
+
+    if host.trim().is_empty() {
+        return invalid("An SMTP host is required".to_string());
+    }
+    if username.is_some() && password.is_empty() {
+        return invalid("A password is required when a username is set".to_string());
+    }
+    if to.is_empty() {
+        return invalid("At least one recipient is required".to_string());
+    }
+    for address in std::iter::once(from).chain(to) {
+        if address.parse::<Mailbox>().is_err() {
+            return invalid(format!("Invalid email address \"{}\"", address));
+        }
+    }
+    if digest_hour.is_some_and(|hour| hour > 23) {
+        return invalid("The digest hour must be between 0 and 23".to_string());
+    }
+
+    Ok(())
+}
+
+pub fn alert_email(event: &AlertEvent) -> Email {
+    let severity = match event.severity {
+        AlertSeverity::Warning => "Warning",
+        AlertSeverity::Critical => "Critical",
+    };
+    let current = format_value(event.current_value, &event.unit);
+    let threshold = format_value(event.threshold, &event.unit);
+    let triggered_at = event.triggered_at.format("%Y-%m-%d %H:%M UTC");
+
+    Email {
+        subject: format!("[{}] {}", severity, event.title),
+        text: format!(
+            "{}\n\nProvider: {}\nCurrent: {}\nThreshold: {}\nTriggered: {}\n",
+            event.message, event.provider_name, current, threshold, triggered_at
+        ),
+        html: format!(
+            "<h2>{}</h2><p>{}</p><table>\
+             <tr><th align=\"left\">Provider</th><td>{}</td></tr>\
+             <tr><th align=\"left\">Current</th><td>{}</td></tr>\
+             <tr><th align=\"left\">Threshold</th><td>{}</td></tr>\
+             <tr><th align=\"left\">Triggered</th><td>{}</td></tr>\
+             </table>",
+            escape(&event.title),
+            escape(&event.message),
+            escape(&event.provider_name),
+            current,
+            threshold,
+            triggered_at
+        ),
+    }
+}
+
+/// Sends `email` to the channel's recipients, retrying transient SMTP
+/// failures. Returns how many attempts were made.
+pub async fn send(
+    config: &ChannelConfig,
+    password: Option<&str>,
+    email: &Email,
+    max_attempts: u32,
+) -> (u32, Result<()>) {
+    let (message, transport) = match build(config, password, email) {
+        Ok(built) => built,
+        Err(e) => return (0, Err(e)),
+    };
+
+    let mut delay = RETRY_BACKOFF;
+    let mut attempt = 0;
+
+    loop {
+        attempt += 1;
+        let error = match transport.send(message.clone()).await {
+            Ok(_) => return (attempt, Ok(())),
+            Err(e) => e,
+        };
+
+        if error.is_permanent() || attempt >= max_attempts {
+            return (attempt, Err(anyhow!(error)));
+        }
+
+        warn!("Retrying email delivery in {:?} after: {}", delay, error);
+        tokio::time::sleep(delay).await;
+        delay *= 2;
+    }
+}
+
+fn build(
+    config: &ChannelConfig,
+    password: Option<&str>,
+    email: &Email,
+) -> Result<(Message, AsyncSmtpTransport<Tokio1Executor>)> {
+    let ChannelConfig::Email {
+        host,
+        port,
+        security,
+        username,
+        from,
+        to,
+        ..
+    } = config
+    else {
+        bail!("Not an email channel");
+    };
+
+    let mut builder = Message::builder()
+        .from(from.parse::<Mailbox>()?)
+        .subject(email.subject.clone());
+    for address in to {
+        builder = builder.to(address.parse::<Mailbox>()?);
+    }
+    let message = builder.multipart(MultiPart::alternative_plain_html(
+        email.text.clone(),
+        email.html.clone(),
+    ))?;
+
+    let mut transport = match security {
+        SmtpSecurity::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?,
+        SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host)?,
+        SmtpSecurity::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+    }
+    .port(*port)
+    .timeout(Some(SMTP_TIMEOUT));
+
+    if let Some(username) = username {
+        transport = transport.credentials(Credentials::new(
+            username.clone(),
+            password.unwrap_or_default().to_string(),
+        ));
+    }
+
+    Ok((message, transport.build()))
+}
+
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,466 @@
+pub mod discord;
+pub mod email;
+pub mod slack;
+pub mod webhook;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use reqwest::{Client, RequestBuilder, StatusCode};
+use serde::Serialize;
+use std::time::Duration;
+use tracing::{error, warn};
+
+use crate::db::Database;
+use crate::error::AppError;
+use crate::models::{
+    AlertEvent, AlertRule, AlertSeverity, BudgetPeriod, ChannelConfig, NotificationChannel,
+};
+use crate::services::keychain::KeychainService;
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 4;
+// Doubles after every failed attempt: 2s, 4s, 8s.
+const RETRY_BACKOFF: Duration = Duration::from_secs(2);
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// One delivery of an alert to a channel, recorded in `events` as
+/// `alert_delivered` or `alert_delivery_failed`.
+#[derive(Debug, Serialize)]
+pub struct DeliveryRecord {
+    pub alert_id: String,
+    pub channel_id: String,
+    pub channel_name: String,
+    pub channel_type: &'static str,
+    pub attempts: u32,
+    pub error: Option<String>,
+    pub at: DateTime<Utc>,
+}
+
+pub fn channel_type(config: &ChannelConfig) -> &'static str {
+    match config {
+        ChannelConfig::Slack => "slack",
+        ChannelConfig::Discord => "discord",
+        ChannelConfig::Webhook { .. } => "webhook",
+        ChannelConfig::Email { .. } => "email",
+    }
+}
+
+/// Delivers `event` and records the outcome in `events`, so there is a
+/// record of who was told and when.
+pub async fn deliver_and_log(
+    db: &Database,
+    client: &Client,
+    channel: &NotificationChannel,
+    event: &AlertEvent,
+) {
+    let (attempts, result) = deliver(client, channel, event).await;
+
+    let record = DeliveryRecord {
+        alert_id: event.alert_id.clone(),
+        channel_id: channel.id.clone(),
+        channel_name: channel.name.clone(),
+        channel_type: channel_type(&channel.config),
+        attempts,
+        error: result.as_ref().err().map(|e| e.to_string()),
+        at: Utc::now(),
+    };
+
+    let kind = match &result {
+        Ok(()) => "alert_delivered",
+        Err(e) => {
+            error!(
+                "Failed to deliver alert {} to {}: {}",
+                event.alert_id, channel.name, e
+            );
+            "alert_delivery_failed"
+        }
+    };
+
+    let (provider_id, group_id) = (event.provider_id.as_deref(), event.group_id.as_deref());
+    if let Err(e) = db.insert_event(provider_id, group_id, kind, &record).await {
+        error!(
+            "Failed to record delivery of alert {}: {}",
+            event.alert_id, e
+        );
+    }
+}
+
+/// Sends a sample alert without recording it, so a channel can be checked
+/// when it is set up.
+pub async fn test(client: &Client, channel: &NotificationChannel) -> DeliveryRecord {
+    let now = Utc::now();
+    let event = AlertEvent {
+        alert_id: "test".to_string(),
+        provider_id: Some("test".to_string()),
+        group_id: None,
+        provider_name: "AI Usage Monitor".to_string(),
+        rule: AlertRule::SpendThreshold {
+            amount: 100.0,
+            is_soft: true,
+        },
+        severity: AlertSeverity::Warning,
+        current_value: 80.0,
+        threshold: 100.0,
+        unit: "usd".to_string(),
+        period: Some(BudgetPeriod::Monthly),
+        title: "Test alert".to_string(),
+        message: format!(
+            "This is a test message for the \"{}\" channel.",
+            channel.name
+        ),
+        notify_desktop: false,
+        triggered_at: now,
+    };
+
+    let (attempts, result) = deliver(client, channel, &event).await;
+    DeliveryRecord {
+        alert_id: event.alert_id,
+        channel_id: channel.id.clone(),
+        channel_name: channel.name.clone(),
+        channel_type: channel_type(&channel.config),
+        attempts,
+        error: result.err().map(|e| e.to_string()),
+        at: now,
+    }
+}
+
+/// Returns how many attempts were made, and whether the last one succeeded.
+async fn deliver(
+    client: &Client,
+    channel: &NotificationChannel,
+    event: &AlertEvent,
+) -> (u32, Result<()>) {
+    // Email servers without authentication have no secret.
+    let secret = if matches!(&channel.config, ChannelConfig::Email { username: None, .. }) {
+        String::new()
+    } else {
+        match KeychainService::get_channel_secret(&channel.id) {
+            Ok(secret) => secret,
+            Err(e) => return (0, Err(e)),
+        }
+    };
+
+    match &channel.config {
+        ChannelConfig::Slack => {
+            let payload = slack::payload(event);
+            post_with_retries(
+                || Ok(client.post(&secret).json(&payload)),
+                DEFAULT_MAX_ATTEMPTS,
+            )
+            .await
+        }
+        ChannelConfig::Discord => {
+            let payload = discord::payload(event);
+            post_with_retries(
+                || Ok(client.post(&secret).json(&payload)),
+                DEFAULT_MAX_ATTEMPTS,
+            )
+            .await
+        }
+        ChannelConfig::Webhook {
+            url,
+            headers,
+            timeout_secs,
+            max_attempts,
+        } => {
+            // Retries keep the delivery id, so receivers can drop duplicates,
+            // but are signed again so the timestamp is current.
+            let delivery_id = uuid::Uuid::new_v4().to_string();
+            post_with_retries(
+                || {
+                    webhook::request(
+                        client,
+                        url,
+                        headers,
+                        *timeout_secs,
+                        &secret,
+                        event,
+                        &delivery_id,
+                    )
+                },
+                max_attempts.unwrap_or(DEFAULT_MAX_ATTEMPTS).max(1),
+            )
+            .await
+        }
+        ChannelConfig::Email { username, .. } => {
+            let password = username.as_ref().map(|_| secret.as_str());
+            email::send(
+                &channel.config,
+                password,
+                &email::alert_email(event),
+                DEFAULT_MAX_ATTEMPTS,
+            )
+            .await
+        }
+    }
+}
+
+/// Retries network failures, rate limits and 5xx responses with exponential
+/// backoff. Other responses mean the request itself is wrong and are not
+/// retried. `request` builds each attempt afresh.
+async fn post_with_retries(
+    request: impl Fn() -> Result<RequestBuilder>,
+    max_attempts: u32,
+) -> (u32, Result<()>) {
+    let mut delay = RETRY_BACKOFF;
+    let mut attempt = 0;
+
+    loop {
+        let attempt_request = match request() {
+            Ok(attempt_request) => attempt_request,
+            Err(e) => return (attempt, Err(e)),
+        };
+        attempt += 1;
+
+        let (error, retry_after) = match attempt_request.send().await {
+            Ok(response) if response.status().is_success() => return (attempt, Ok(())),
+            Ok(response) => {
+                let status = response.status();
+                let retry_after = response
+                    .headers()
+                    .get(reqwest::header::RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.trim().parse::<f64>().ok())
+                    .map(Duration::from_secs_f64);
+                let body = response.text().await.unwrap_or_default();
+                let error = anyhow!("Channel responded {}: {}", status, body.trim());
+
+                if status != StatusCode::TOO_MANY_REQUESTS && !status.is_server_error() {
+                    return (attempt, Err(error));
+                }
+                (error, retry_after)
+            }
+            Err(e) if e.is_builder() => return (attempt, Err(anyhow!(e))),
+            Err(e) => (anyhow!(e), None),
+        };
+
+        if attempt >= max_attempts {
+            return (attempt, Err(error));
+        }
+
+        let wait = retry_after.unwrap_or(delay).min(MAX_RETRY_AFTER);
+        warn!("Retrying alert delivery in {:?} after: {}", wait, error);
+        tokio::time::sleep(wait).await;
+        delay *= 2;
+    }
+}
+
+/// `$12.34` for spend, `12.34` for credits.
+pub fn format_value(value: f64, unit: &str) -> String {
+    match unit {
+        "usd" => format!("${:.2}", value),
+        "days" => format!("{:.1} days", value),
+        "tokens" => format!("{:.0} tokens", value),
+        _ => format!("{:.2}", value),
+    }
+}
+
+/// Rejects settings that cannot work before saving, e.g. a Slack URL pasted
+/// into a Discord channel.
+pub fn check_channel(config: &ChannelConfig, secret: &str) -> Result<(), AppError> {
+    let invalid = |hint: &str| {
+        Err(AppError::InvalidRequest(format!(
+            "Invalid webhook URL: {}",
+            hint
+        )))
+    };
+
+    match config {
+        ChannelConfig::Slack if !secret.starts_with("https://hooks.slack.com/") => {
+            invalid("Slack webhook URLs start with \"https://hooks.slack.com/\"")
+        }
+        ChannelConfig::Discord
+            if !secret.starts_with("https://discord.com/api/webhooks/")
+                && !secret.starts_with("https://discordapp.com/api/webhooks/") =>
+        {
+            invalid("Discord webhook URLs start with \"https://discord.com/api/webhooks/\"")
+        }
+        // Plain HTTP is allowed for receivers on the local machine or network.
+        ChannelConfig::Webhook { url, .. }
+            if !url.starts_with("https://") && !url.starts_with("http://") =>
+        {
+            invalid("the URL must start with \"https://\" or \"http://\"")
+        }
+        ChannelConfig::Webhook { .. } if secret.is_empty() => Err(AppError::InvalidRequest(
+            "A signing secret is required for webhook channels".to_string(),
+        )),
+        ChannelConfig::Webhook { headers, .. } => {
+            for (name, value) in headers {
+                if webhook::is_reserved_header(name) {
+                    return Err(AppError::InvalidRequest(format!(
+                        "Header \"{}\" is reserved",
+                        name
+                    )));
+                }
+                if reqwest::header::HeaderName::from_bytes(name.as_bytes()).is_err()
+                    || reqwest::header::HeaderValue::from_str(value).is_err()
+                {
+                    return Err(AppError::InvalidRequest(format!(
+                        "Invalid header \"{}\"",
+                        name
+                    )));
+                }
+            }
+            Ok(())
+        }
+        ChannelConfig::Email { .. } => email::check(config, secret),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{AlertRule, AlertSeverity, BudgetPeriod, SmtpSecurity};
+    use chrono::TimeZone;
+
+    /// A critical spend alert for a provider, shared by the payload tests.
+    pub(super) fn event() -> AlertEvent {
+        AlertEvent {
+            alert_id: "alert-1".to_string(),
+            provider_id: Some("openai-prod".to_string()),
+            group_id: None,
+            provider_name: "Production OpenAI".to_string(),
+            rule: AlertRule::SpendThreshold {
+                amount: 100.0,
+                is_soft: false,
+            },
+            severity: AlertSeverity::Critical,
+            current_value: 123.456,
+            threshold: 100.0,
+            unit: "usd".to_string(),
+            period: Some(BudgetPeriod::Monthly),
+            title: "Spend limit reached".to_string(),
+            message: "Production OpenAI has spent $123.46 this month".to_string(),
+            notify_desktop: true,
+            triggered_at: Utc.with_ymd_and_hms(2026, 10, 18, 12, 30, 0).unwrap(),
+        }
+    }
+
+    fn webhook(url: &str, headers: &[(&str, &str)]) -> ChannelConfig {
+        ChannelConfig::Webhook {
+            url: url.to_string(),
+            headers: headers
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            timeout_secs: None,
+            max_attempts: None,
+        }
+    }
+
+    fn email(username: Option<&str>, to: &[&str]) -> ChannelConfig {
+        ChannelConfig::Email {
+            host: "smtp.example.com".to_string(),
+            port: 587,
+            security: SmtpSecurity::default(),
+            username: username.map(str::to_string),
+            from: "alerts@example.com".to_string(),
+            to: to.iter().map(|s| s.to_string()).collect(),
+            digest: None,
+            digest_hour: None,
+        }
+    }
+
+    fn rejected(result: Result<(), AppError>) -> String {
+        match result {
+            Err(AppError::InvalidRequest(message)) => message,
+            other => panic!("expected InvalidRequest, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn formats_values_by_unit() {
+        assert_eq!(format_value(12.345, "usd"), "$12.35");
+        assert_eq!(format_value(2.25, "days"), "2.2 days");
+        assert_eq!(format_value(1500.4, "tokens"), "1500 tokens");
+        assert_eq!(format_value(7.0, "credits"), "7.00");
+    }
+
+    #[test]
+    fn checks_slack_and_discord_urls() {
+        assert!(check_channel(
+            &ChannelConfig::Slack,
+            "https://hooks.slack.com/services/T0/B0/x"
+        )
+        .is_ok());
+        assert!(rejected(check_channel(
+            &ChannelConfig::Slack,
+            "https://discord.com/api/webhooks/1/x"
+        ))
+        .contains("hooks.slack.com"));
+
+        assert!(check_channel(
+            &ChannelConfig::Discord,
+            "https://discord.com/api/webhooks/1/x"
+        )
+        .is_ok());
+        assert!(check_channel(
+            &ChannelConfig::Discord,
+            "https://discordapp.com/api/webhooks/1/x"
+        )
+        .is_ok());
+        assert!(rejected(check_channel(
+            &ChannelConfig::Discord,
+            "https://hooks.slack.com/services/T0/B0/x"
+        ))
+        .contains("discord.com"));
+    }
+
+    #[test]
+    fn checks_webhooks() {
+        assert!(check_channel(
+            &webhook("https://example.com/hook", &[("X-Route", "ops")]),
+            "secret"
+        )
+        .is_ok());
+        // Receivers on the local network may not have TLS.
+        assert!(check_channel(&webhook("http://192.168.1.10:8080/", &[]), "secret").is_ok());
+
+        assert!(
+            rejected(check_channel(&webhook("ftp://example.com", &[]), "secret"))
+                .contains("https://")
+        );
+        assert!(
+            rejected(check_channel(&webhook("https://example.com/hook", &[]), ""))
+                .contains("signing secret")
+        );
+        assert!(rejected(check_channel(
+            &webhook("https://example.com/hook", &[("Bad Header", "x")]),
+            "secret"
+        ))
+        .contains("Bad Header"));
+        assert!(rejected(check_channel(
+            &webhook("https://example.com/hook", &[("X-Route", "a\nb")]),
+            "secret"
+        ))
+        .contains("X-Route"));
+        for reserved in [
+            "content-type",
+            "X-AIMonitor-Signature",
+            "x-aimonitor-custom",
+        ] {
+            assert!(rejected(check_channel(
+                &webhook("https://example.com/hook", &[(reserved, "x")]),
+                "secret"
+            ))
+            .contains("reserved"));
+        }
+    }
+
+    #[test]
+    fn checks_email_settings() {
+        assert!(check_channel(&email(None, &["ops@example.com"]), "").is_ok());
+        assert!(check_channel(&email(Some("alerts"), &["ops@example.com"]), "password").is_ok());
+
+        assert!(rejected(check_channel(
+            &email(Some("alerts"), &["ops@example.com"]),
+            ""
+        ))
+        .contains("password"));
+        assert!(rejected(check_channel(&email(None, &[]), "")).contains("recipient"));
+        assert!(
+            rejected(check_channel(&email(None, &["not an address"]), ""))
+                .contains("not an address")
+        );
+    }
+}
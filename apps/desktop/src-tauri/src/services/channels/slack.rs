@@ -0,0 +1,91 @@
+use serde_json::{json, Value};
+
+use super::format_value;
+use crate::models::{AlertEvent, AlertSeverity};
+
+/// An incoming-webhook message using Block Kit. `text` is the fallback shown
+/// in notifications and by clients that cannot render blocks.
+pub fn payload(event: &AlertEvent) -> Value {
+    let icon = match event.severity {
+        AlertSeverity::Warning => ":warning:",
+        AlertSeverity::Critical => ":rotating_light:",
+    };
+
+    json!({
+        "text": format!("{} {}", icon, event.message),
+        "blocks": [
+            {
+                "type": "header",
+                "text": { "type": "plain_text", "text": event.title, "emoji": true }
+            },
+            {
+                "type": "section",
+                "text": { "type": "mrkdwn", "text": format!("{} {}", icon, event.message) },
+                "fields": [
+                    { "type": "mrkdwn", "text": format!("*Current*\n{}", format_value(event.current_value, &event.unit)) },
+                    { "type": "mrkdwn", "text": format!("*Threshold*\n{}", format_value(event.threshold, &event.unit)) }
+                ]
+            },
+            {
+                "type": "context",
+                "elements": [
+                    {
+                        "type": "mrkdwn",
+                        "text": format!(
+                            "{} · {:?} · <!date^{}^{{date_short_pretty}} {{time}}|{}>",
+                            event.provider_name,
+                            event.severity,
+                            event.triggered_at.timestamp(),
+                            event.triggered_at.to_rfc3339()
+                        )
+                    }
+                ]
+            }
+        ]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::channels::tests::event;
+
+    #[test]
+    fn builds_block_kit_message() {
+        let payload = payload(&event());
+
+        assert_eq!(
+            payload["text"],
+            ":rotating_light: Production OpenAI has spent $123.46 this month"
+        );
+        assert_eq!(payload["blocks"][0]["text"]["text"], "Spend limit reached");
+        assert_eq!(
+            payload["blocks"][1]["fields"][0]["text"],
+            "*Current*\n$123.46"
+        );
+        assert_eq!(
+            payload["blocks"][1]["fields"][1]["text"],
+            "*Threshold*\n$100.00"
+        );
+
+        let context = payload["blocks"][2]["elements"][0]["text"]
+            .as_str()
+            .unwrap();
+        assert!(
+            context.starts_with("Production OpenAI · Critical · <!date^1792326600^"),
+            "{}",
+            context
+        );
+    }
+
+    #[test]
+    fn uses_warning_icon_for_warnings() {
+        let mut event = event();
+        event.severity = AlertSeverity::Warning;
+
+        assert!(payload(&event)["text"]
+            .as_str()
+            .unwrap()
+            .starts_with(":warning: "));
+    }
+}
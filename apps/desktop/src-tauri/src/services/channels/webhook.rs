@@ -0,0 +1,194 @@
+use anyhow::Result;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use reqwest::{Client, RequestBuilder};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::models::AlertEvent;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Bumped on any change receivers would have to handle.
+pub const PAYLOAD_VERSION: u32 = 2;
+pub const SIGNATURE_HEADER: &str = "X-AIMonitor-Signature";
+pub const EVENT_HEADER: &str = "X-AIMonitor-Event";
+pub const DELIVERY_HEADER: &str = "X-AIMonitor-Delivery";
+const DEFAULT_TIMEOUT_SECS: u64 = 10;
+
+/// Headers the app sets on every delivery. Custom headers may not use them:
+/// reqwest appends rather than replaces, so the request would carry both.
+pub fn is_reserved_header(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    name == "content-type" || name.starts_with("x-aimonitor-")
+}
+
+pub fn payload(event: &AlertEvent, delivery_id: &str) -> Value {
+    json!({
+        "version": PAYLOAD_VERSION,
+        "type": "alert.triggered",
+        "delivery_id": delivery_id,
+        "alert": {
+            "id": event.alert_id,
+            "severity": event.severity,
+            "title": event.title,
+            "message": event.message,
+            "triggered_at": event.triggered_at,
+        },
+        // Exactly one of these is set.
+        "provider": event.provider_id.as_ref().map(|id| json!({ "id": id, "name": event.provider_name })),
+        "group": event.group_id.as_ref().map(|id| json!({ "id": id, "name": event.provider_name })),
+        "rule": event.rule,
+        "current_value": event.current_value,
+        "threshold": event.threshold,
+        "unit": event.unit,
+        "period": event.period,
+    })
+}
+
+/// `t=<unix seconds>,v1=<hex HMAC-SHA256 of "<t>.<body>">`. Receivers should
+/// recompute it over the raw body and reject old timestamps to stop replays.
+pub fn signature(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    format!(
+        "t={},v1={}",
+        timestamp,
+        hex::encode(mac.finalize().into_bytes())
+    )
+}
+
+/// The request for one delivery attempt, signed now. The body is serialized
+/// here rather than by reqwest so the signature covers exactly the bytes sent.
+pub fn request(
+    client: &Client,
+    url: &str,
+    headers: &HashMap<String, String>,
+    timeout_secs: Option<u64>,
+    secret: &str,
+    event: &AlertEvent,
+    delivery_id: &str,
+) -> Result<RequestBuilder> {
+    let body = serde_json::to_vec(&payload(event, delivery_id))?;
+
+    let mut request = client.post(url).timeout(Duration::from_secs(
+        timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS),
+    ));
+    for (name, value) in headers {
+        request = request.header(name, value);
+    }
+
+    Ok(request
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(EVENT_HEADER, "alert.triggered")
+        .header(DELIVERY_HEADER, delivery_id)
+        .header(
+            SIGNATURE_HEADER,
+            signature(secret, Utc::now().timestamp(), &body),
+        )
+        .body(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::channels::tests::event;
+
+    #[test]
+    fn provider_alert_payload() {
+        let payload = payload(&event(), "delivery-1");
+
+        assert_eq!(payload["version"], PAYLOAD_VERSION);
+        assert_eq!(payload["type"], "alert.triggered");
+        assert_eq!(payload["delivery_id"], "delivery-1");
+        assert_eq!(payload["alert"]["id"], "alert-1");
+        assert_eq!(payload["alert"]["severity"], "critical");
+        assert_eq!(
+            payload["provider"],
+            json!({ "id": "openai-prod", "name": "Production OpenAI" })
+        );
+        assert!(payload["group"].is_null());
+        assert_eq!(
+            payload["rule"],
+            json!({ "spend_threshold": { "amount": 100.0, "is_soft": false } })
+        );
+        assert_eq!(payload["current_value"], 123.456);
+        assert_eq!(payload["unit"], "usd");
+        assert_eq!(payload["period"], "monthly");
+    }
+
+    #[test]
+    fn group_alert_payload() {
+        let mut event = event();
+        event.provider_id = None;
+        event.group_id = Some("team-a".to_string());
+        event.provider_name = "Team A".to_string();
+
+        let payload = payload(&event, "delivery-1");
+        assert!(payload["provider"].is_null());
+        assert_eq!(
+            payload["group"],
+            json!({ "id": "team-a", "name": "Team A" })
+        );
+    }
+
+    #[test]
+    fn signature_is_hmac_of_timestamp_and_body() {
+        // echo -n '1700000000.{"a":1}' | openssl dgst -sha256 -hmac secret
+        assert_eq!(
+            signature("secret", 1_700_000_000, br#"{"a":1}"#),
+            "t=1700000000,v1=49f24e537407743fa4a0242bb63b94b9a47ee99cbbe071ccd8a22550ae411686",
+        );
+    }
+
+    #[test]
+    fn signature_depends_on_secret_timestamp_and_body() {
+        let base = signature("secret", 1_700_000_000, b"body");
+        assert_ne!(base, signature("other", 1_700_000_000, b"body"));
+        assert_ne!(base, signature("secret", 1_700_000_001, b"body"));
+        assert_ne!(base, signature("secret", 1_700_000_000, b"body!"));
+    }
+
+    #[test]
+    fn request_signs_the_body_it_sends() {
+        let client = Client::new();
+        let headers = HashMap::from([("X-Route".to_string(), "ops".to_string())]);
+        let request = request(
+            &client,
+            "https://example.com/hook",
+            &headers,
+            Some(5),
+            "secret",
+            &event(),
+            "delivery-1",
+        )
+        .unwrap()
+        .build()
+        .unwrap();
+
+        let body = request.body().and_then(|b| b.as_bytes()).unwrap();
+        let sent: Value = serde_json::from_slice(body).unwrap();
+        assert_eq!(sent["delivery_id"], "delivery-1");
+
+        let header = |name: &str| request.headers()[name].to_str().unwrap().to_string();
+        assert_eq!(header(DELIVERY_HEADER), "delivery-1");
+        assert_eq!(header(EVENT_HEADER), "alert.triggered");
+        assert_eq!(header("X-Route"), "ops");
+        assert_eq!(request.timeout(), Some(&Duration::from_secs(5)));
+
+        let signed = header(SIGNATURE_HEADER);
+        let timestamp: i64 = signed
+            .trim_start_matches("t=")
+            .split(',')
+            .next()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert_eq!(signed, signature("secret", timestamp, body));
+    }
+}
@@ -0,0 +1,211 @@
+use anyhow::Result;
+use chrono::{DateTime, Datelike, Duration, Local, Timelike, Utc, Weekday};
+use std::fmt::Write;
+use std::sync::Arc;
+use tracing::{error, info};
+
+use crate::models::{AlertEvent, ChannelConfig, DigestFrequency};
+use crate::services::channels::email::{self, Email};
+use crate::services::keychain::KeychainService;
+use crate::services::AppState;
+
+const CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+const DEFAULT_DIGEST_HOUR: u32 = 8;
+const MAX_ATTEMPTS: u32 = 4;
+
+/// Sends the daily or weekly spend digest for email channels that ask for
+/// one, built from stored metrics and the alerts recorded in `events`.
+pub struct DigestService {
+    state: Arc<AppState>,
+}
+
+impl DigestService {
+    pub fn new(state: Arc<AppState>) -> Self {
+        Self { state }
+    }
+
+    pub async fn start(self: &Arc<Self>) {
+        let service = Arc::clone(self);
+
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(CHECK_INTERVAL);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = service.send_due().await {
+                    error!("Failed to send digests: {}", e);
+                }
+            }
+        });
+    }
+
+    async fn send_due(&self) -> Result<()> {
+        for channel in self.state.db.get_channels().await? {
+            let ChannelConfig::Email {
+                digest: Some(frequency),
+                digest_hour,
+                username,
+                ..
+            } = &channel.config
+            else {
+                continue;
+            };
+            if !channel.enabled {
+                continue;
+            }
+
+            let hour = digest_hour.unwrap_or(DEFAULT_DIGEST_HOUR);
+            let Some(scheduled) = Self::last_scheduled(*frequency, hour, Local::now()) else {
+                continue;
+            };
+            let last_sent = self
+                .state
+                .db
+                .get_last_digest_at(&channel.id)
+                .await?
+                .unwrap_or(channel.created_at);
+            if last_sent >= scheduled {
+                continue;
+            }
+
+            let from = scheduled - Self::period(*frequency);
+            let digest = self.render(*frequency, from, scheduled).await?;
+            let password = match username {
+                Some(_) => Some(KeychainService::get_channel_secret(&channel.id)?),
+                None => None,
+            };
+
+            // A failed digest waits for the next one rather than retrying
+            // every few minutes; alerts are what must get through.
+            match email::send(&channel.config, password.as_deref(), &digest, MAX_ATTEMPTS).await {
+                (_, Ok(())) => info!("Sent {:?} digest to {}", frequency, channel.name),
+                (attempts, Err(e)) => error!(
+                    "Failed to send digest to {} after {} attempts: {}",
+                    channel.name, attempts, e
+                ),
+            }
+            self.state
+                .db
+                .set_last_digest_at(&channel.id, Utc::now())
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// The most recent time a digest was due: today's `hour`, or yesterday's
+    /// if that is still ahead; for weekly digests, the latest Monday's.
+    fn last_scheduled(
+        frequency: DigestFrequency,
+        hour: u32,
+        now: DateTime<Local>,
+    ) -> Option<DateTime<Utc>> {
+        let mut date = now.date_naive();
+        if now.hour() < hour {
+            date = date.pred_opt()?;
+        }
+        if frequency == DigestFrequency::Weekly {
+            while date.weekday() != Weekday::Mon {
+                date = date.pred_opt()?;
+            }
+        }
+
+        let at = date
+            .and_hms_opt(hour, 0, 0)?
+            .and_local_timezone(Local)
+            .earliest()?;
+        Some(at.with_timezone(&Utc))
+    }
+
+    fn period(frequency: DigestFrequency) -> Duration {
+        match frequency {
+            DigestFrequency::Daily => Duration::days(1),
+            DigestFrequency::Weekly => Duration::weeks(1),
+        }
+    }
+
+    async fn render(
+        &self,
+        frequency: DigestFrequency,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Email> {
+        let spend = self.state.db.get_spend_by_provider(from, to).await?;
+        let alerts: Vec<AlertEvent> = self
+            .state
+            .db
+            .get_events("alert_triggered", from, to)
+            .await?
+            .into_iter()
+            .filter_map(|event| serde_json::from_str(event.payload.as_deref()?).ok())
+            .collect();
+        let usage = self.state.usage_snapshot().await;
+
+        let label = match frequency {
+            DigestFrequency::Daily => "Daily",
+            DigestFrequency::Weekly => "Weekly",
+        };
+        let range = format!(
+            "{} – {}",
+            from.with_timezone(&Local).format("%a %d %b %H:%M"),
+            to.with_timezone(&Local).format("%a %d %b %H:%M")
+        );
+        let total: f64 = spend.values().sum();
+
+        let mut text = format!(
+            "{} AI spend digest, {}\n\nTotal spend: ${:.2}\n\n",
+            label, range, total
+        );
+        let mut html = format!(
+            "<h2>{} AI spend digest</h2><p>{}</p><p><strong>Total spend: ${:.2}</strong></p>\
+             <table cellpadding=\"4\"><tr><th align=\"left\">Provider</th><th align=\"right\">Period</th>\
+             <th align=\"right\">Month to date</th><th align=\"right\">Budget used</th></tr>",
+            label, range, total
+        );
+
+        for entry in usage.iter().filter(|u| u.provider.enabled) {
+            let period_spend = spend
+                .get(&entry.provider.id)
+                .map_or("—".to_string(), |cost| format!("${:.2}", cost));
+            let mtd = if entry.capabilities.cost {
+                format!("${:.2}", entry.mtd_cost)
+            } else {
+                "—".to_string()
+            };
+            let budget = entry
+                .budget_used_percentage
+                .map_or("—".to_string(), |percent| format!("{:.0}%", percent));
+
+            let _ = writeln!(
+                text,
+                "{}: {} this period, {} this month, budget {}",
+                entry.provider.name, period_spend, mtd, budget
+            );
+            let _ = write!(
+                html,
+                "<tr><td>{}</td><td align=\"right\">{}</td><td align=\"right\">{}</td><td align=\"right\">{}</td></tr>",
+                email::escape(&entry.provider.name), period_spend, mtd, budget
+            );
+        }
+        html.push_str("</table>");
+
+        if alerts.is_empty() {
+            text.push_str("\nNo alerts fired.\n");
+            html.push_str("<p>No alerts fired.</p>");
+        } else {
+            let _ = writeln!(text, "\nAlerts fired ({}):", alerts.len());
+            html.push_str("<h3>Alerts fired</h3><ul>");
+            for alert in &alerts {
+                let at = alert.triggered_at.with_timezone(&Local).format("%a %H:%M");
+                let _ = writeln!(text, "- {} {}", at, alert.message);
+                let _ = write!(html, "<li>{} {}</li>", at, email::escape(&alert.message));
+            }
+            html.push_str("</ul>");
+        }
+
+        Ok(Email {
+            subject: format!("{} AI spend digest: ${:.2}", label, total),
+            text,
+            html,
+        })
+    }
+}
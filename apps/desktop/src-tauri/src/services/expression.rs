@@ -0,0 +1,1101 @@
+use anyhow::Result;
+use chrono::{DateTime, Datelike, Duration, Months, Utc};
+use std::fmt;
+
+use crate::db::Database;
+
+/// Why an expression was rejected, at the 1-based column where it went wrong.
+#[derive(Debug, Clone)]
+pub struct ExpressionError {
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ExpressionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "column {}: {}", self.column, self.message)
+    }
+}
+
+fn error<T>(column: usize, message: impl Into<String>) -> Result<T, ExpressionError> {
+    Err(ExpressionError {
+        column,
+        message: message.into(),
+    })
+}
+
+const FUNCTIONS: &str = "sum, rate, avg_over, previous or last";
+const SERIES: &str =
+    "cost, tokens, tokens_in, tokens_out, tokens_cached, requests, credits or balance";
+const WINDOWS: &str = "today, yesterday, month or a duration such as 24h or 7d";
+/// Windows and buckets are limited to a year; metrics are not kept longer.
+const MAX_DURATION_DAYS: i64 = 366;
+
+/// A metric as it is named in expressions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Metric {
+    Cost,
+    Tokens,
+    TokensIn,
+    TokensOut,
+    TokensCached,
+    Requests,
+    Credits,
+    Balance,
+}
+
+impl Metric {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "cost" => Metric::Cost,
+            "tokens" => Metric::Tokens,
+            "tokens_in" => Metric::TokensIn,
+            "tokens_out" => Metric::TokensOut,
+            "tokens_cached" => Metric::TokensCached,
+            "requests" => Metric::Requests,
+            "credits" => Metric::Credits,
+            "balance" => Metric::Balance,
+            _ => return None,
+        })
+    }
+
+    /// The `metrics.metric_type` values summed for this metric.
+    fn metric_types(self) -> &'static [&'static str] {
+        match self {
+            Metric::Cost => &["CostUsd"],
+            Metric::Tokens => &["TokensIn", "TokensOut"],
+            Metric::TokensIn => &["TokensIn"],
+            Metric::TokensOut => &["TokensOut"],
+            Metric::TokensCached => &["TokensCached"],
+            Metric::Requests => &["Requests"],
+            Metric::Credits => &["CreditsRemaining"],
+            Metric::Balance => &["Balance"],
+        }
+    }
+
+    /// Some providers record cumulative cost in credits; only USD rows add up.
+    fn unit(self) -> Option<&'static str> {
+        (self == Metric::Cost).then_some("usd")
+    }
+
+    /// Levels are read with `last`; everything else is a total over time.
+    fn is_level(self) -> bool {
+        matches!(self, Metric::Credits | Metric::Balance)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Series {
+    name: String,
+    metric: Metric,
+    /// Dimension filters such as `model="gpt-4o"`.
+    filters: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Window {
+    /// The last stretch of time up to now.
+    Last(Duration),
+    /// Since midnight UTC.
+    Today,
+    Yesterday,
+    /// Since the 1st of the month, UTC.
+    Month,
+}
+
+impl Window {
+    fn range(self, now: DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>) {
+        let midnight = now
+            .date_naive()
+            .and_hms_opt(0, 0, 0)
+            .unwrap_or_default()
+            .and_utc();
+        match self {
+            Window::Last(length) => (
+                now.checked_sub_signed(length)
+                    .unwrap_or(DateTime::<Utc>::MIN_UTC),
+                now,
+            ),
+            Window::Today => (midnight, now),
+            Window::Yesterday => (midnight - Duration::days(1), midnight),
+            Window::Month => (midnight - Duration::days(now.day0().into()), now),
+        }
+    }
+
+    /// The same window one period earlier: the 24h before the last 24h,
+    /// yesterday up to this time of day, last month up to this day.
+    fn previous(self, now: DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>) {
+        let (from, to) = self.range(now);
+        match self {
+            Window::Last(length) => (
+                from.checked_sub_signed(length)
+                    .unwrap_or(DateTime::<Utc>::MIN_UTC),
+                to.checked_sub_signed(length)
+                    .unwrap_or(DateTime::<Utc>::MIN_UTC),
+            ),
+            Window::Today | Window::Yesterday => (from - Duration::days(1), to - Duration::days(1)),
+            Window::Month => (
+                from.checked_sub_months(Months::new(1)).unwrap_or(from),
+                to.checked_sub_months(Months::new(1)).unwrap_or(to),
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Function {
+    Sum,
+    /// Per hour.
+    Rate,
+    /// Mean of the totals of each bucket in the window.
+    AvgOver,
+    /// `sum` over the previous period.
+    Previous,
+    /// The most recent value of a level.
+    Last,
+}
+
+/// One function call, evaluated with a single query.
+#[derive(Debug, Clone)]
+struct Query {
+    function: Function,
+    series: Series,
+    window: Window,
+    bucket: Option<Duration>,
+    /// As written, for alert messages.
+    text: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Compare {
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    Eq,
+    Ne,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Arithmetic {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Number(f64),
+    Query(usize),
+    Neg(Box<Node>),
+    Arithmetic(Arithmetic, Box<Node>, Box<Node>),
+    Compare(Compare, Box<Node>, Box<Node>),
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    Not(Box<Node>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Type {
+    Number,
+    Condition,
+}
+
+/// A parsed alert condition such as
+/// `sum(cost, today) > 2 * sum(cost, yesterday) and sum(tokens_out{model="gpt-4o"}, today) > 5M`.
+#[derive(Debug, Clone)]
+pub struct Expression {
+    root: Node,
+    queries: Vec<Query>,
+}
+
+/// The outcome of evaluating an expression.
+#[derive(Debug, Clone)]
+pub struct Evaluation {
+    pub matched: bool,
+    /// Both sides of the expression if it is a single comparison, or of its
+    /// first comparison otherwise.
+    pub left: f64,
+    pub right: f64,
+    /// Every function call as written, with its value.
+    pub values: Vec<(String, f64)>,
+}
+
+impl Expression {
+    pub fn parse(source: &str) -> Result<Self, ExpressionError> {
+        let tokens = lex(source)?;
+        let mut parser = Parser {
+            source,
+            tokens,
+            pos: 0,
+            queries: Vec::new(),
+        };
+
+        let (root, ty) = parser.parse_or()?;
+        let next = parser.peek();
+        if next.token != Token::End {
+            return error(
+                next.column,
+                format!("unexpected {} after the end of the condition", next.token),
+            );
+        }
+        if ty != Type::Condition {
+            return error(
+                1,
+                "the expression must be a condition, e.g. sum(cost, today) > 10",
+            );
+        }
+
+        Ok(Self {
+            root,
+            queries: parser.queries,
+        })
+    }
+
+    /// Evaluates the expression against the stored metrics of the providers
+    /// combined.
+    pub async fn evaluate(
+        &self,
+        db: &Database,
+        provider_ids: &[String],
+        now: DateTime<Utc>,
+    ) -> Result<Evaluation> {
+        let mut values = Vec::with_capacity(self.queries.len());
+        for query in &self.queries {
+            values.push(query.run(db, provider_ids, now).await?);
+        }
+
+        let (left, right) = first_comparison(&self.root)
+            .map(|(left, right)| (number(left, &values), number(right, &values)))
+            .unwrap_or((0.0, 0.0));
+
+        Ok(Evaluation {
+            matched: condition(&self.root, &values) == Some(true),
+            // Non-finite values do not survive JSON.
+            left: if left.is_finite() { left } else { 0.0 },
+            right: if right.is_finite() { right } else { 0.0 },
+            values: self
+                .queries
+                .iter()
+                .map(|q| q.text.clone())
+                .zip(values)
+                .collect(),
+        })
+    }
+}
+
+impl Query {
+    async fn run(&self, db: &Database, provider_ids: &[String], now: DateTime<Utc>) -> Result<f64> {
+        let metric = self.series.metric;
+        // The sum of each provider's latest value, e.g. credits left across a group.
+        if self.function == Function::Last {
+            let mut total = None;
+            for provider_id in provider_ids {
+                if let Some(value) = db
+                    .last_metric(
+                        provider_id,
+                        metric.metric_types(),
+                        &self.series.filters,
+                        now,
+                    )
+                    .await?
+                {
+                    *total.get_or_insert(0.0) += value;
+                }
+            }
+            return Ok(total.unwrap_or(f64::NAN));
+        }
+
+        let (from, to) = match self.function {
+            Function::Previous => self.window.previous(now),
+            _ => self.window.range(now),
+        };
+        let total = db
+            .sum_metrics(
+                provider_ids,
+                metric.metric_types(),
+                metric.unit(),
+                &self.series.filters,
+                from,
+                to,
+            )
+            .await?;
+        let seconds = (to - from).num_seconds() as f64;
+
+        Ok(match self.function {
+            Function::Rate => divide(total, seconds / 3600.0),
+            Function::AvgOver => divide(
+                total,
+                seconds / self.bucket.unwrap_or(to - from).num_seconds() as f64,
+            ),
+            _ => total,
+        })
+    }
+}
+
+/// A division by zero gives NaN, which makes every comparison unknown.
+fn divide(left: f64, right: f64) -> f64 {
+    if right == 0.0 {
+        f64::NAN
+    } else {
+        left / right
+    }
+}
+
+fn number(node: &Node, values: &[f64]) -> f64 {
+    match node {
+        Node::Number(value) => *value,
+        Node::Query(index) => values[*index],
+        Node::Neg(operand) => -number(operand, values),
+        Node::Arithmetic(op, left, right) => {
+            let (left, right) = (number(left, values), number(right, values));
+            match op {
+                Arithmetic::Add => left + right,
+                Arithmetic::Sub => left - right,
+                Arithmetic::Mul => left * right,
+                Arithmetic::Div => divide(left, right),
+            }
+        }
+        // Type checking keeps conditions out of arithmetic.
+        _ => f64::NAN,
+    }
+}
+
+/// `None` when the condition is unknown: a comparison with a missing value
+/// (NaN) neither holds nor fails, and `not` keeps it unknown, so missing data
+/// never fires an alert. `and` and `or` follow Kleene logic.
+fn condition(node: &Node, values: &[f64]) -> Option<bool> {
+    match node {
+        Node::Compare(op, left, right) => {
+            let (left, right) = (number(left, values), number(right, values));
+            if left.is_nan() || right.is_nan() {
+                return None;
+            }
+            Some(match op {
+                Compare::Gt => left > right,
+                Compare::Ge => left >= right,
+                Compare::Lt => left < right,
+                Compare::Le => left <= right,
+                Compare::Eq => left == right,
+                Compare::Ne => left != right,
+            })
+        }
+        Node::And(left, right) => match (condition(left, values), condition(right, values)) {
+            (Some(false), _) | (_, Some(false)) => Some(false),
+            (Some(true), Some(true)) => Some(true),
+            _ => None,
+        },
+        Node::Or(left, right) => match (condition(left, values), condition(right, values)) {
+            (Some(true), _) | (_, Some(true)) => Some(true),
+            (Some(false), Some(false)) => Some(false),
+            _ => None,
+        },
+        Node::Not(operand) => condition(operand, values).map(|holds| !holds),
+        _ => None,
+    }
+}
+
+fn first_comparison(node: &Node) -> Option<(&Node, &Node)> {
+    match node {
+        Node::Compare(_, left, right) => Some((left, right)),
+        Node::And(left, right) | Node::Or(left, right) => {
+            first_comparison(left).or_else(|| first_comparison(right))
+        }
+        Node::Not(operand) => first_comparison(operand),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// Digits with any suffix, e.g. `5M` or `24h`; read as a number or a
+    /// duration depending on where it appears.
+    Number(String),
+    Ident(String),
+    Str(String),
+    Symbol(&'static str),
+    End,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(text) | Token::Ident(text) => write!(f, "`{}`", text),
+            Token::Str(text) => write!(f, "\"{}\"", text),
+            Token::Symbol(symbol) => write!(f, "`{}`", symbol),
+            Token::End => write!(f, "the end of the expression"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Lexed {
+    token: Token,
+    column: usize,
+}
+
+const SYMBOLS: [&str; 18] = [
+    "==", "!=", ">=", "<=", "&&", "||", ">", "<", "=", "(", ")", "{", "}", ",", "+", "-", "*", "/",
+];
+
+fn lex(source: &str) -> Result<Vec<Lexed>, ExpressionError> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    let column = |pos: usize| source[..pos].chars().count() + 1;
+
+    while let Some(&(pos, c)) = chars.peek() {
+        let start = column(pos);
+        let rest = &source[pos..];
+
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let word_end = |allow_dot: bool| {
+            rest.find(|c: char| !(c.is_alphanumeric() || c == '_' || (allow_dot && c == '.')))
+                .unwrap_or(rest.len())
+        };
+        let (token, len) = if c.is_ascii_digit() || c == '.' {
+            let len = word_end(true);
+            (Token::Number(rest[..len].to_string()), len)
+        } else if c.is_alphabetic() || c == '_' {
+            let len = word_end(false);
+            (Token::Ident(rest[..len].to_string()), len)
+        } else if c == '"' || c == '\'' {
+            let Some(end) = rest[1..].find(c) else {
+                return error(start, "this string is never closed");
+            };
+            (Token::Str(rest[1..end + 1].to_string()), end + 2)
+        } else if let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(*symbol)) {
+            match *symbol {
+                "&&" => return error(start, "use `and` instead of `&&`"),
+                "||" => return error(start, "use `or` instead of `||`"),
+                _ => (Token::Symbol(symbol), symbol.len()),
+            }
+        } else if c == '!' {
+            return error(start, "use `not` instead of `!`");
+        } else {
+            return error(start, format!("unexpected character `{}`", c));
+        };
+
+        tokens.push(Lexed {
+            token,
+            column: start,
+        });
+        while chars.peek().is_some_and(|&(next, _)| next < pos + len) {
+            chars.next();
+        }
+    }
+
+    tokens.push(Lexed {
+        token: Token::End,
+        column: column(source.len()),
+    });
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<Lexed>,
+    pos: usize,
+    queries: Vec<Query>,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> &Lexed {
+        &self.tokens[self.pos.min(self.tokens.len() - 1)]
+    }
+
+    fn next(&mut self) -> Lexed {
+        let lexed = self.peek().clone();
+        if lexed.token != Token::End {
+            self.pos += 1;
+        }
+        lexed
+    }
+
+    fn is_symbol(&self, symbol: &str) -> bool {
+        matches!(&self.peek().token, Token::Symbol(s) if *s == symbol)
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(&self.peek().token, Token::Ident(word) if word == keyword)
+    }
+
+    fn expect(&mut self, symbol: &str, hint: &str) -> Result<(), ExpressionError> {
+        if self.is_symbol(symbol) {
+            self.next();
+            return Ok(());
+        }
+        let found = self.peek();
+        error(
+            found.column,
+            format!("expected `{}` but found {}; {}", symbol, found.token, hint),
+        )
+    }
+
+    fn parse_or(&mut self) -> Result<(Node, Type), ExpressionError> {
+        let (mut node, ty) = self.parse_and()?;
+        while self.is_keyword("or") {
+            let column = self.next().column;
+            let (right, right_ty) = self.parse_and()?;
+            require_conditions("or", column, ty, right_ty)?;
+            node = Node::Or(Box::new(node), Box::new(right));
+        }
+        Ok((node, ty))
+    }
+
+    fn parse_and(&mut self) -> Result<(Node, Type), ExpressionError> {
+        let (mut node, ty) = self.parse_not()?;
+        while self.is_keyword("and") {
+            let column = self.next().column;
+            let (right, right_ty) = self.parse_not()?;
+            require_conditions("and", column, ty, right_ty)?;
+            node = Node::And(Box::new(node), Box::new(right));
+        }
+        Ok((node, ty))
+    }
+
+    fn parse_not(&mut self) -> Result<(Node, Type), ExpressionError> {
+        if !self.is_keyword("not") {
+            return self.parse_compare();
+        }
+        let column = self.next().column;
+        let (operand, ty) = self.parse_not()?;
+        if ty != Type::Condition {
+            return error(
+                column,
+                "`not` needs a condition, e.g. not (sum(cost, today) > 10)",
+            );
+        }
+        Ok((Node::Not(Box::new(operand)), Type::Condition))
+    }
+
+    fn parse_compare(&mut self) -> Result<(Node, Type), ExpressionError> {
+        let (left, left_ty) = self.parse_sum()?;
+        let Some(op) = self.compare_op() else {
+            return Ok((left, left_ty));
+        };
+        let column = self.next().column;
+        let (right, right_ty) = self.parse_sum()?;
+        if left_ty != Type::Number || right_ty != Type::Number {
+            return error(column, "comparisons need a number on each side");
+        }
+        if self.compare_op().is_some() {
+            return error(
+                self.peek().column,
+                "comparisons cannot be chained; join them with `and`",
+            );
+        }
+        Ok((
+            Node::Compare(op, Box::new(left), Box::new(right)),
+            Type::Condition,
+        ))
+    }
+
+    fn compare_op(&self) -> Option<Compare> {
+        match &self.peek().token {
+            Token::Symbol(">") => Some(Compare::Gt),
+            Token::Symbol(">=") => Some(Compare::Ge),
+            Token::Symbol("<") => Some(Compare::Lt),
+            Token::Symbol("<=") => Some(Compare::Le),
+            Token::Symbol("==") => Some(Compare::Eq),
+            Token::Symbol("!=") => Some(Compare::Ne),
+            _ => None,
+        }
+    }
+
+    fn parse_sum(&mut self) -> Result<(Node, Type), ExpressionError> {
+        let (mut node, ty) = self.parse_product()?;
+        loop {
+            let op = match &self.peek().token {
+                Token::Symbol("+") => Arithmetic::Add,
+                Token::Symbol("-") => Arithmetic::Sub,
+                _ => return Ok((node, ty)),
+            };
+            let column = self.next().column;
+            let (right, right_ty) = self.parse_product()?;
+            require_numbers(column, ty, right_ty)?;
+            node = Node::Arithmetic(op, Box::new(node), Box::new(right));
+        }
+    }
+
+    fn parse_product(&mut self) -> Result<(Node, Type), ExpressionError> {
+        let (mut node, ty) = self.parse_unary()?;
+        loop {
+            let op = match &self.peek().token {
+                Token::Symbol("*") => Arithmetic::Mul,
+                Token::Symbol("/") => Arithmetic::Div,
+                _ => return Ok((node, ty)),
+            };
+            let column = self.next().column;
+            let (right, right_ty) = self.parse_unary()?;
+            require_numbers(column, ty, right_ty)?;
+            node = Node::Arithmetic(op, Box::new(node), Box::new(right));
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<(Node, Type), ExpressionError> {
+        if !self.is_symbol("-") {
+            return self.parse_primary();
+        }
+        let column = self.next().column;
+        let (operand, ty) = self.parse_unary()?;
+        require_numbers(column, ty, Type::Number)?;
+        Ok((Node::Neg(Box::new(operand)), Type::Number))
+    }
+
+    fn parse_primary(&mut self) -> Result<(Node, Type), ExpressionError> {
+        let Lexed { token, column } = self.next();
+        match token {
+            Token::Number(text) => Ok((Node::Number(parse_number(&text, column)?), Type::Number)),
+            Token::Symbol("(") => {
+                let inner = self.parse_or()?;
+                self.expect(")", "every `(` needs a matching `)`")?;
+                Ok(inner)
+            }
+            Token::Ident(name) if self.is_symbol("(") => self.parse_call(&name, column),
+            Token::Ident(name) if Metric::from_name(&name).is_some() => error(
+                column,
+                format!(
+                    "`{0}` is a series; use it in a function such as sum({0}, today)",
+                    name
+                ),
+            ),
+            Token::Ident(name) => error(
+                column,
+                format!(
+                    "unknown name `{}`; expected a number or one of the functions {}",
+                    name, FUNCTIONS
+                ),
+            ),
+            other => error(
+                column,
+                format!("expected a number or a function call but found {}", other),
+            ),
+        }
+    }
+
+    fn parse_call(&mut self, name: &str, column: usize) -> Result<(Node, Type), ExpressionError> {
+        let (function, usage) = match name {
+            "sum" => (Function::Sum, "sum(cost, today)"),
+            "rate" => (Function::Rate, "rate(tokens, 1h)"),
+            "avg_over" => (Function::AvgOver, "avg_over(cost, 7d, 1d)"),
+            "previous" => (Function::Previous, "previous(cost, today)"),
+            "last" => (Function::Last, "last(credits)"),
+            _ => {
+                return error(
+                    column,
+                    format!("unknown function `{}`; expected {}", name, FUNCTIONS),
+                )
+            }
+        };
+        let hint = format!("write it as {}", usage);
+
+        self.expect("(", &hint)?;
+        let series_column = self.peek().column;
+        let series = self.parse_series()?;
+
+        if function == Function::Last && !series.metric.is_level() {
+            return error(
+                series_column,
+                format!(
+                    "`last` reads a level such as credits or balance; use sum({}, ...) for totals",
+                    series.name
+                ),
+            );
+        }
+        if function != Function::Last && series.metric.is_level() {
+            return error(
+                series_column,
+                format!("`{0}` is a level, not a total; use last({0})", series.name),
+            );
+        }
+
+        let mut window = Window::Today;
+        let mut bucket = None;
+        if function != Function::Last {
+            self.expect(",", &hint)?;
+            window = self.parse_window()?;
+        }
+        if function == Function::AvgOver {
+            self.expect(",", &hint)?;
+            let bucket_column = self.peek().column;
+            let Token::Number(text) = self.next().token else {
+                return error(
+                    bucket_column,
+                    format!("expected a bucket size such as 1h or 1d; {}", hint),
+                );
+            };
+            let length = parse_duration(&text, bucket_column)?;
+            if let Window::Last(window_length) = window {
+                if length > window_length {
+                    return error(bucket_column, "the bucket is longer than the window");
+                }
+            }
+            bucket = Some(length);
+        }
+        self.expect(")", &hint)?;
+
+        let end = self.tokens[self.pos - 1].column;
+        let text = self
+            .source
+            .chars()
+            .skip(column - 1)
+            .take(end - column + 1)
+            .collect();
+        self.queries.push(Query {
+            function,
+            series,
+            window,
+            bucket,
+            text,
+        });
+        Ok((Node::Query(self.queries.len() - 1), Type::Number))
+    }
+
+    fn parse_series(&mut self) -> Result<Series, ExpressionError> {
+        let Lexed { token, column } = self.next();
+        let Token::Ident(name) = token else {
+            return error(
+                column,
+                format!("expected a series ({}) but found {}", SERIES, token),
+            );
+        };
+        let Some(metric) = Metric::from_name(&name) else {
+            return error(
+                column,
+                format!("unknown series `{}`; expected {}", name, SERIES),
+            );
+        };
+
+        let mut filters = Vec::new();
+        if self.is_symbol("{") {
+            self.next();
+            loop {
+                let Lexed { token, column } = self.next();
+                let Token::Ident(key) = token else {
+                    return error(
+                        column,
+                        format!("expected a dimension such as model but found {}", token),
+                    );
+                };
+                self.expect("=", "filters are written as {model=\"gpt-4o\"}")?;
+                let Lexed { token, column } = self.next();
+                let Token::Str(value) = token else {
+                    return error(column, "filter values are quoted, e.g. {model=\"gpt-4o\"}");
+                };
+                filters.push((key, value));
+
+                if self.is_symbol("}") {
+                    self.next();
+                    break;
+                }
+                self.expect(",", "separate filters with `,` and close them with `}`")?;
+            }
+        }
+
+        Ok(Series {
+            name,
+            metric,
+            filters,
+        })
+    }
+
+    fn parse_window(&mut self) -> Result<Window, ExpressionError> {
+        let Lexed { token, column } = self.next();
+        match token {
+            Token::Ident(name) if name == "today" => Ok(Window::Today),
+            Token::Ident(name) if name == "yesterday" => Ok(Window::Yesterday),
+            Token::Ident(name) if name == "month" => Ok(Window::Month),
+            Token::Number(text) => Ok(Window::Last(parse_duration(&text, column)?)),
+            other => error(
+                column,
+                format!("expected a window ({}) but found {}", WINDOWS, other),
+            ),
+        }
+    }
+}
+
+fn require_conditions(
+    keyword: &str,
+    column: usize,
+    left: Type,
+    right: Type,
+) -> Result<(), ExpressionError> {
+    if left != Type::Condition || right != Type::Condition {
+        return error(
+            column,
+            format!(
+                "`{}` needs a condition on each side, e.g. sum(cost, today) > 10",
+                keyword
+            ),
+        );
+    }
+    Ok(())
+}
+
+fn require_numbers(column: usize, left: Type, right: Type) -> Result<(), ExpressionError> {
+    if left != Type::Number || right != Type::Number {
+        return error(column, "arithmetic needs numbers, not conditions");
+    }
+    Ok(())
+}
+
+fn split_suffix(text: &str) -> (&str, &str) {
+    let at = text
+        .find(|c: char| c.is_alphabetic() || c == '_')
+        .unwrap_or(text.len());
+    text.split_at(at)
+}
+
+/// Plain numbers, or with a `k`, `M` or `B` suffix: `5M` is five million.
+fn parse_number(text: &str, column: usize) -> Result<f64, ExpressionError> {
+    let (digits, suffix) = split_suffix(text);
+    let scale = match suffix {
+        "" => 1.0,
+        "k" => 1e3,
+        "M" => 1e6,
+        "B" => 1e9,
+        "m" | "h" | "d" | "w" => {
+            return error(
+                column,
+                format!(
+                    "`{}` is a duration; durations only go in a function's window",
+                    text
+                ),
+            );
+        }
+        _ => {
+            return error(
+                column,
+                format!(
+                "`{}` is not a number; use a k, M or B suffix for thousands, millions or billions",
+                text
+            ),
+            )
+        }
+    };
+    match digits.parse::<f64>() {
+        Ok(value) => Ok(value * scale),
+        Err(_) => error(column, format!("`{}` is not a number", text)),
+    }
+}
+
+/// A whole number of minutes, hours, days or weeks, e.g. `24h`.
+fn parse_duration(text: &str, column: usize) -> Result<Duration, ExpressionError> {
+    let (digits, unit) = split_suffix(text);
+    let invalid = || {
+        error(
+            column,
+            format!(
+                "`{}` is not a duration; use a whole number followed by m, h, d or w, e.g. 24h",
+                text
+            ),
+        )
+    };
+
+    let Ok(count) = digits.parse::<i64>() else {
+        return invalid();
+    };
+    if count <= 0 {
+        return error(column, "durations must be longer than zero");
+    }
+    let duration = match unit {
+        "m" => Duration::try_minutes(count),
+        "h" => Duration::try_hours(count),
+        "d" => Duration::try_days(count),
+        "w" => Duration::try_weeks(count),
+        _ => return invalid(),
+    };
+    match duration {
+        Some(duration) if duration <= Duration::days(MAX_DURATION_DAYS) => Ok(duration),
+        _ => error(
+            column,
+            format!(
+                "`{}` is too long; durations go up to {}d",
+                text, MAX_DURATION_DAYS
+            ),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(source: &str) -> ExpressionError {
+        Expression::parse(source).expect_err("expression should be rejected")
+    }
+
+    #[test]
+    fn parses_windows_up_to_a_year() {
+        assert!(Expression::parse("sum(cost, 366d) > 1").is_ok());
+        assert!(Expression::parse("sum(cost, 52w) > 1").is_ok());
+        assert!(Expression::parse("avg_over(tokens, 7d, 1h) > 5M").is_ok());
+    }
+
+    #[test]
+    fn rejects_durations_longer_than_a_year() {
+        let error = parse_error("sum(cost, 367d) > 1");
+        assert_eq!(error.column, 11);
+        assert!(error.message.contains("too long"), "{}", error.message);
+
+        assert!(parse_error("sum(cost, 100000000d) > 1")
+            .message
+            .contains("too long"));
+        assert!(parse_error("avg_over(cost, month, 53w) > 1")
+            .message
+            .contains("too long"));
+    }
+
+    #[test]
+    fn rejects_durations_that_overflow() {
+        assert!(parse_error("sum(cost, 999999999999999d) > 1")
+            .message
+            .contains("too long"));
+        assert!(parse_error("sum(cost, 999999999999999w) > 1")
+            .message
+            .contains("too long"));
+        // Too many digits for an i64 at all.
+        assert!(parse_error("sum(cost, 99999999999999999999m) > 1")
+            .message
+            .contains("not a duration"));
+    }
+
+    #[test]
+    fn window_range_saturates_instead_of_panicking() {
+        let now = Utc::now();
+        let (from, to) = Window::Last(Duration::MAX).range(now);
+        assert_eq!(from, DateTime::<Utc>::MIN_UTC);
+        assert_eq!(to, now);
+    }
+
+    /// The condition's value with the function calls replaced by `values`.
+    fn condition_with(source: &str, values: &[f64]) -> Option<bool> {
+        condition(&Expression::parse(source).unwrap().root, values)
+    }
+
+    #[test]
+    fn rejects_mistakes_with_the_column_and_a_hint() {
+        let error = parse_error("sum(cots, today) > 1");
+        assert_eq!(error.column, 5);
+        assert!(error.message.contains("cots"), "{}", error.message);
+
+        assert!(parse_error("sum(cost, today)")
+            .message
+            .contains("must be a condition"));
+        assert!(parse_error("sum(cost, today) > 1 and").column > 1);
+        assert!(parse_error("sum(cost, today) > 1)")
+            .message
+            .contains("after the end"));
+    }
+
+    #[test]
+    fn missing_values_are_unknown_rather_than_false() {
+        let missing = [f64::NAN];
+
+        assert_eq!(condition_with("last(credits) < 5", &missing), None);
+        assert_eq!(condition_with("last(credits) != 5", &missing), None);
+        assert_eq!(condition_with("not (last(credits) > 5)", &missing), None);
+        assert_eq!(condition_with("last(credits) / 0 == 1", &[5.0]), None);
+    }
+
+    #[test]
+    fn and_or_follow_three_valued_logic() {
+        let source = "last(credits) < 5 and sum(cost, today) > 1";
+        assert_eq!(condition_with(source, &[f64::NAN, 0.5]), Some(false));
+        assert_eq!(condition_with(source, &[f64::NAN, 2.0]), None);
+        assert_eq!(condition_with(source, &[4.0, 2.0]), Some(true));
+
+        let source = "last(credits) < 5 or sum(cost, today) > 1";
+        assert_eq!(condition_with(source, &[f64::NAN, 2.0]), Some(true));
+        assert_eq!(condition_with(source, &[f64::NAN, 0.5]), None);
+        assert_eq!(condition_with(source, &[6.0, 0.5]), Some(false));
+
+        assert_eq!(
+            condition_with(
+                "not (last(credits) < 5 and sum(cost, today) > 1)",
+                &[f64::NAN, 0.5]
+            ),
+            Some(true)
+        );
+    }
+
+    #[tokio::test]
+    async fn evaluates_the_example_from_the_request() {
+        use crate::models::{Metric as StoredMetric, MetricType, Provider, ProviderType};
+        use chrono::TimeZone;
+        use std::collections::HashMap;
+
+        let db = Database::in_memory().await.unwrap();
+        db.insert_provider(&Provider {
+            id: "openai".to_string(),
+            name: "OpenAI".to_string(),
+            provider_type: ProviderType::OpenAI,
+            api_key_ref: None,
+            enabled: true,
+            created_at: Utc::now(),
+            settings: Default::default(),
+        })
+        .await
+        .unwrap();
+
+        let now = Utc.with_ymd_and_hms(2026, 10, 15, 12, 0, 0).unwrap();
+        let insert =
+            |metric_type, value, unit: &str, hours_ago, model: Option<&str>| StoredMetric {
+                id: uuid::Uuid::new_v4().to_string(),
+                provider_id: "openai".to_string(),
+                metric_type,
+                value,
+                unit: unit.to_string(),
+                timestamp: now - Duration::hours(hours_ago),
+                dimensions: model
+                    .map(|m| HashMap::from([("model".to_string(), m.to_string())]))
+                    .unwrap_or_default(),
+            };
+        for metric in [
+            insert(MetricType::CostUsd, 4.0, "usd", 26, None),
+            insert(MetricType::CostUsd, 9.0, "usd", 2, None),
+            insert(
+                MetricType::TokensOut,
+                6_000_000.0,
+                "tokens",
+                2,
+                Some("gpt-4o"),
+            ),
+            insert(MetricType::TokensOut, 9_000_000.0, "tokens", 2, Some("o1")),
+        ] {
+            db.insert_metric(&metric).await.unwrap();
+        }
+
+        let expression = Expression::parse(
+            r#"sum(cost, today) > 2 * sum(cost, yesterday) and sum(tokens_out{model="gpt-4o"}, today) > 5M"#,
+        )
+        .unwrap();
+        let evaluation = expression
+            .evaluate(&db, &["openai".to_string()], now)
+            .await
+            .unwrap();
+
+        assert!(evaluation.matched);
+        assert_eq!((evaluation.left, evaluation.right), (9.0, 8.0));
+        assert_eq!(
+            evaluation
+                .values
+                .iter()
+                .map(|(_, v)| *v)
+                .collect::<Vec<_>>(),
+            vec![9.0, 4.0, 6_000_000.0]
+        );
+
+        // Nothing has been spent yet the next day.
+        let evaluation = expression
+            .evaluate(&db, &["openai".to_string()], now + Duration::days(1))
+            .await
+            .unwrap();
+        assert!(!evaluation.matched);
+    }
+}
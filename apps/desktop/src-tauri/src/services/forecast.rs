@@ -0,0 +1,247 @@
+use anyhow::Result;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+use std::collections::BTreeMap;
+
+use crate::db::Database;
+use crate::models::{BudgetPeriod, MetricType, ProviderUsage};
+
+const DEFAULT_LOOKBACK_DAYS: u32 = 14;
+const DEFAULT_HALF_LIFE_DAYS: f64 = 3.0;
+/// Run-outs further away than this are reported as not expected.
+const MAX_HORIZON_DAYS: f64 = 365.0;
+
+/// Where a provider's spend is heading at its recent burn rate.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Forecast {
+    /// Weighted average USD spend per day.
+    pub spend_per_day: Option<f64>,
+    /// Weighted average use of credits or balance per day.
+    pub credits_per_day: Option<f64>,
+    pub run_out_at: Option<DateTime<Utc>>,
+    pub period_spend: Option<f64>,
+}
+
+/// Projects `usage` forward from the completed days in the provider's
+/// lookback window, weighting each day by how recent it is. Today is left
+/// out until it is over, so a quiet morning does not drag the rate down.
+pub async fn forecast(
+    db: &Database,
+    usage: &ProviderUsage,
+    now: DateTime<Utc>,
+) -> Result<Forecast> {
+    let provider_id = &usage.provider.id;
+    let settings = &usage.provider.settings.forecast;
+    let lookback = settings
+        .lookback_days
+        .unwrap_or(DEFAULT_LOOKBACK_DAYS)
+        .max(1);
+    let half_life = settings
+        .half_life_days
+        .filter(|days| *days > 0.0)
+        .unwrap_or(DEFAULT_HALF_LIFE_DAYS);
+
+    let today = now.date_naive();
+    let from = start_of(today - Duration::days(lookback.into()));
+    let to = start_of(today);
+
+    let spend_per_day = if usage.capabilities.cost {
+        let spend = db
+            .get_daily_spend(provider_id, from, to)
+            .await?
+            .into_iter()
+            .collect();
+        burn_rate(&spend, today, half_life)
+    } else {
+        None
+    };
+
+    // Credits and balances are drawn down by spend, so fall back to the
+    // spend rate for providers that do not store their history.
+    let remaining = usage.credits.or(usage.balance);
+    let credits_per_day = match remaining {
+        Some(_) => {
+            let metric = if usage.credits.is_some() {
+                MetricType::CreditsRemaining
+            } else {
+                MetricType::Balance
+            };
+            let values = db.get_metric_values(provider_id, &metric, from, to).await?;
+            burn_rate(&daily_drawdown(&values), today, half_life).or(spend_per_day)
+        }
+        None => None,
+    };
+
+    let month_end = start_of_next_month(today);
+    let days_left_in_month = (month_end - now).num_seconds() as f64 / 86_400.0;
+
+    let period_spend = spend_per_day.map(|rate| usage.mtd_cost + rate * days_left_in_month);
+
+    let credits_run_out = match (remaining, credits_per_day) {
+        (Some(remaining), Some(rate)) => run_out(now, remaining, rate),
+        _ => None,
+    };
+    let budget_run_out = match (
+        db.get_budget(provider_id, BudgetPeriod::Monthly).await?,
+        spend_per_day,
+    ) {
+        (Some(budget), Some(rate)) => budget
+            .hard_limit
+            .or(budget.soft_limit)
+            .and_then(|limit| run_out(now, limit - usage.mtd_cost, rate))
+            .filter(|at| *at < month_end),
+        _ => None,
+    };
+
+    Ok(Forecast {
+        spend_per_day,
+        credits_per_day,
+        run_out_at: credits_run_out.into_iter().chain(budget_run_out).min(),
+        period_spend,
+    })
+}
+
+/// Weighted mean of the daily values from the first day with data up to
+/// yesterday; missing days in between count as zero. Yesterday has weight 1
+/// and a day `half_life` older has weight 0.5.
+fn burn_rate(daily: &BTreeMap<NaiveDate, f64>, today: NaiveDate, half_life: f64) -> Option<f64> {
+    let first = *daily.keys().next()?;
+
+    let mut weighted = 0.0;
+    let mut weights = 0.0;
+    for day in first.iter_days().take_while(|day| *day < today) {
+        let age = (today - day).num_days() as f64;
+        let weight = 0.5_f64.powf((age - 1.0) / half_life);
+        weighted += weight * daily.get(&day).copied().unwrap_or_default();
+        weights += weight;
+    }
+
+    (weights > 0.0).then(|| weighted / weights)
+}
+
+/// How much a remaining-credit series fell each day. Increases are top-ups
+/// and are ignored rather than netted against use.
+fn daily_drawdown(values: &[(DateTime<Utc>, f64)]) -> BTreeMap<NaiveDate, f64> {
+    let mut daily = BTreeMap::new();
+    if let Some((at, _)) = values.first() {
+        daily.insert(at.date_naive(), 0.0);
+    }
+    for pair in values.windows(2) {
+        let ((_, before), (at, after)) = (pair[0], pair[1]);
+        *daily.entry(at.date_naive()).or_insert(0.0) += (before - after).max(0.0);
+    }
+    daily
+}
+
+/// When `remaining` is used up at `per_day`; now if it already is.
+fn run_out(now: DateTime<Utc>, remaining: f64, per_day: f64) -> Option<DateTime<Utc>> {
+    if remaining <= 0.0 {
+        return Some(now);
+    }
+    if per_day <= 0.0 {
+        return None;
+    }
+
+    let days = remaining / per_day;
+    (days <= MAX_HORIZON_DAYS).then(|| now + Duration::seconds((days * 86_400.0) as i64))
+}
+
+fn start_of(day: NaiveDate) -> DateTime<Utc> {
+    day.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc()
+}
+
+fn start_of_next_month(today: NaiveDate) -> DateTime<Utc> {
+    let (year, month) = if today.month() == 12 {
+        (today.year() + 1, 1)
+    } else {
+        (today.year(), today.month() + 1)
+    };
+    start_of(NaiveDate::from_ymd_opt(year, month, 1).unwrap_or(today))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, 10, d).unwrap()
+    }
+
+    #[test]
+    fn burn_rate_weights_recent_days() {
+        let today = day(15);
+
+        let flat = BTreeMap::from([
+            (day(10), 4.0),
+            (day(12), 4.0),
+            (day(13), 4.0),
+            (day(14), 4.0),
+        ]);
+        // Day 11 is missing and counts as zero, so the rate is just under 4.
+        let rate = burn_rate(&flat, today, 3.0).unwrap();
+        assert!(rate > 3.0 && rate < 4.0, "{}", rate);
+
+        let rising = BTreeMap::from([(day(12), 1.0), (day(13), 1.0), (day(14), 10.0)]);
+        let falling = BTreeMap::from([(day(12), 10.0), (day(13), 1.0), (day(14), 1.0)]);
+        assert!(burn_rate(&rising, today, 3.0).unwrap() > burn_rate(&falling, today, 3.0).unwrap());
+
+        // A shorter half-life follows the latest day more closely.
+        assert!(burn_rate(&rising, today, 0.5).unwrap() > burn_rate(&rising, today, 3.0).unwrap());
+    }
+
+    #[test]
+    fn burn_rate_leaves_out_today() {
+        let today = day(15);
+        assert_eq!(burn_rate(&BTreeMap::new(), today, 3.0), None);
+        assert_eq!(
+            burn_rate(&BTreeMap::from([(today, 50.0)]), today, 3.0),
+            None
+        );
+        assert_eq!(
+            burn_rate(&BTreeMap::from([(day(14), 2.0), (today, 50.0)]), today, 3.0),
+            Some(2.0)
+        );
+    }
+
+    #[test]
+    fn drawdown_ignores_top_ups() {
+        let at = |d: u32, h: u32| Utc.with_ymd_and_hms(2026, 10, d, h, 0, 0).unwrap();
+        let values = [
+            (at(1, 9), 100.0),
+            (at(1, 18), 90.0),
+            (at(2, 9), 150.0),
+            (at(2, 18), 140.0),
+            (at(3, 9), 135.0),
+        ];
+
+        let daily = daily_drawdown(&values);
+        assert_eq!(
+            daily,
+            BTreeMap::from([(day(1), 10.0), (day(2), 10.0), (day(3), 5.0)])
+        );
+    }
+
+    #[test]
+    fn run_out_projects_remaining_at_rate() {
+        let now = Utc.with_ymd_and_hms(2026, 10, 18, 12, 0, 0).unwrap();
+
+        assert_eq!(run_out(now, 10.0, 2.0), Some(now + Duration::days(5)));
+        assert_eq!(run_out(now, 0.0, 2.0), Some(now));
+        assert_eq!(run_out(now, -5.0, 0.0), Some(now));
+        assert_eq!(run_out(now, 10.0, 0.0), None);
+        // Further out than a year is not worth reporting.
+        assert_eq!(run_out(now, 1000.0, 1.0), None);
+    }
+
+    #[test]
+    fn next_month_rolls_over_the_year() {
+        assert_eq!(
+            start_of_next_month(day(18)),
+            Utc.with_ymd_and_hms(2026, 11, 1, 0, 0, 0).unwrap()
+        );
+        assert_eq!(
+            start_of_next_month(NaiveDate::from_ymd_opt(2026, 12, 31).unwrap()),
+            Utc.with_ymd_and_hms(2027, 1, 1, 0, 0, 0).unwrap(),
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use anyhow::{Result, anyhow};
+use anyhow::{Context, Result};
 use keyring::Entry;
 
 const SERVICE_NAME: &str = "AIMonitor";
@@ -14,8 +14,8 @@ impl KeychainService {
 
     pub fn get_api_key(provider: &str) -> Result<String> {
         let entry = Entry::new(SERVICE_NAME, provider)?;
-        entry.get_password()
-            .map_err(|e| anyhow!("Failed to retrieve API key: {}", e))
+        // Keep the keyring error as the source so it surfaces as `AppError::Keychain`.
+        entry.get_password().context("Failed to retrieve API key")
     }
 
     pub fn delete_api_key(provider: &str) -> Result<()> {
@@ -24,10 +24,31 @@ impl KeychainService {
         Ok(())
     }
 
+    /// Notification channel secrets share the keychain service with API
+    /// keys, under a `channel:` prefix so the two cannot collide.
+    pub fn store_channel_secret(channel_id: &str, secret: &str) -> Result<()> {
+        Self::store_api_key(&format!("channel:{}", channel_id), secret)
+    }
+
+    pub fn get_channel_secret(channel_id: &str) -> Result<String> {
+        let entry = Entry::new(SERVICE_NAME, &format!("channel:{}", channel_id))?;
+        entry
+            .get_password()
+            .context("Failed to retrieve channel secret")
+    }
+
+    pub fn delete_channel_secret(channel_id: &str) -> Result<()> {
+        Self::delete_api_key(&format!("channel:{}", channel_id))
+    }
+
+    pub fn has_channel_secret(channel_id: &str) -> bool {
+        Self::has_api_key(&format!("channel:{}", channel_id))
+    }
+
     pub fn has_api_key(provider: &str) -> bool {
         Entry::new(SERVICE_NAME, provider)
             .ok()
             .and_then(|entry| entry.get_password().ok())
             .is_some()
     }
-}
\ No newline at end of file
+}
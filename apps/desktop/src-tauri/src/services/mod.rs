@@ -1,31 +1,383 @@
-pub mod monitor;
+pub mod adaptive;
+pub mod alerts;
+pub mod anomaly;
+pub mod backfill;
+pub mod channels;
+pub mod digest;
+pub mod expression;
+pub mod forecast;
 pub mod keychain;
+pub mod monitor;
+pub mod power;
+pub mod validation;
 
+use crate::db::Database;
+use crate::error::AppError;
+use crate::models::{
+    Budget, BudgetPeriod, Metric, Provider, ProviderErrorState, ProviderGroup, ProviderSettings,
+    ProviderType, ProviderUsage,
+};
+use crate::providers::{
+    anthropic::AnthropicAdapter, azure_openai::AzureOpenAIAdapter, bedrock::BedrockAdapter,
+    custom::CustomAdapter, gemini::GeminiAdapter, local::LocalAdapter, openai::OpenAIAdapter,
+    openrouter::OpenRouterAdapter, ProviderAdapter,
+};
+use adaptive::IntervalBounds;
 use anyhow::Result;
+use chrono::{DateTime, Utc};
+use keychain::KeychainService;
+use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use crate::db::Database;
-use crate::providers::{ProviderAdapter, openai::OpenAIAdapter, anthropic::AnthropicAdapter, openrouter::OpenRouterAdapter};
-use crate::models::{Provider, ProviderType};
 
 pub struct AppState {
     pub db: Arc<Database>,
     pub providers: Arc<RwLock<Vec<Provider>>>,
+    /// Latest successful poll per provider id, persisted so the last-known
+    /// values survive a restart while offline.
+    pub usage: Arc<RwLock<HashMap<String, ProviderUsage>>>,
+    /// Why the most recent poll of a provider failed; cleared on success.
+    pub last_errors: Arc<RwLock<HashMap<String, ProviderErrorState>>>,
+    /// Set by `MonitorService` while no provider can be reached.
+    pub offline_since: Arc<RwLock<Option<DateTime<Utc>>>>,
 }
 
 impl AppState {
-    pub async fn new(db: Database) -> Self {
-        Self {
+    pub async fn new(db: Database) -> Result<Self> {
+        let providers = db.get_providers().await?;
+        let usage = db.get_usage_snapshots().await?;
+
+        Ok(Self {
             db: Arc::new(db),
-            providers: Arc::new(RwLock::new(Vec::new())),
+            providers: Arc::new(RwLock::new(providers)),
+            usage: Arc::new(RwLock::new(usage)),
+            last_errors: Arc::new(RwLock::new(HashMap::new())),
+            offline_since: Arc::new(RwLock::new(None)),
+        })
+    }
+
+    pub async fn add_provider(&self, provider: Provider, api_key: &str) -> Result<()> {
+        if provider.api_key_ref.is_some() {
+            KeychainService::store_api_key(&provider.id, api_key)?;
+        }
+        self.db.insert_provider(&provider).await?;
+        self.providers.write().await.push(provider);
+        Ok(())
+    }
+
+    pub async fn remove_provider(&self, provider_id: &str) -> Result<(), AppError> {
+        let provider = {
+            let mut providers = self.providers.write().await;
+            let index = providers
+                .iter()
+                .position(|p| p.id == provider_id)
+                .ok_or_else(|| AppError::NotFound {
+                    kind: "Provider",
+                    id: provider_id.to_string(),
+                })?;
+            providers.remove(index)
+        };
+
+        self.db.delete_provider(provider_id).await?;
+        if provider.api_key_ref.is_some() {
+            KeychainService::delete_api_key(provider_id)?;
+        }
+        self.usage.write().await.remove(provider_id);
+        self.last_errors.write().await.remove(provider_id);
+        Ok(())
+    }
+
+    pub async fn set_provider_enabled(
+        &self,
+        provider_id: &str,
+        enabled: bool,
+    ) -> Result<(), AppError> {
+        {
+            let mut providers = self.providers.write().await;
+            let provider = providers
+                .iter_mut()
+                .find(|p| p.id == provider_id)
+                .ok_or_else(|| AppError::NotFound {
+                    kind: "Provider",
+                    id: provider_id.to_string(),
+                })?;
+            provider.enabled = enabled;
+        }
+
+        self.db.set_provider_enabled(provider_id, enabled).await?;
+        Ok(())
+    }
+
+    /// Checks that exactly one of a provider and a group is given and that
+    /// it exists, for budgets and alerts.
+    pub async fn check_owner(
+        &self,
+        provider_id: Option<&str>,
+        group_id: Option<&str>,
+    ) -> Result<(), AppError> {
+        match (provider_id, group_id) {
+            (Some(provider_id), None) => {
+                if !self
+                    .providers
+                    .read()
+                    .await
+                    .iter()
+                    .any(|p| p.id == provider_id)
+                {
+                    return Err(AppError::NotFound {
+                        kind: "Provider",
+                        id: provider_id.to_string(),
+                    });
+                }
+            }
+            (None, Some(group_id)) => {
+                if self.db.get_group(group_id).await?.is_none() {
+                    return Err(AppError::NotFound {
+                        kind: "Group",
+                        id: group_id.to_string(),
+                    });
+                }
+            }
+            _ => {
+                return Err(AppError::InvalidRequest(
+                    "Give either a provider or a group".to_string(),
+                ));
+            }
         }
+        Ok(())
     }
 
-    pub fn get_provider_adapter(provider_type: &ProviderType) -> Box<dyn ProviderAdapter> {
-        match provider_type {
-            ProviderType::OpenAI => Box::new(OpenAIAdapter::new()),
-            ProviderType::Anthropic => Box::new(AnthropicAdapter::new()),
-            ProviderType::OpenRouter => Box::new(OpenRouterAdapter::new()),
+    /// Saves a provider's or group's budget for its period, replacing the
+    /// previous one.
+    pub async fn set_budget(&self, budget: Budget) -> Result<Budget, AppError> {
+        self.check_owner(budget.provider_id.as_deref(), budget.group_id.as_deref())
+            .await?;
+        let limits = [budget.soft_limit, budget.hard_limit];
+        if limits.iter().all(Option::is_none) {
+            return Err(AppError::InvalidRequest(
+                "A budget needs a soft or hard limit".to_string(),
+            ));
+        }
+        if limits
+            .iter()
+            .flatten()
+            .any(|limit| !limit.is_finite() || *limit <= 0.0)
+        {
+            return Err(AppError::InvalidRequest(
+                "Budget limits must be positive".to_string(),
+            ));
         }
+        if let (Some(soft), Some(hard)) = (budget.soft_limit, budget.hard_limit) {
+            if soft > hard {
+                return Err(AppError::InvalidRequest(
+                    "The soft limit cannot exceed the hard limit".to_string(),
+                ));
+            }
+        }
+
+        self.db.upsert_budget(&budget).await?;
+        Ok(budget)
+    }
+
+    pub async fn remove_budget(
+        &self,
+        provider_id: Option<&str>,
+        group_id: Option<&str>,
+        period: BudgetPeriod,
+    ) -> Result<(), AppError> {
+        self.check_owner(provider_id, group_id).await?;
+        let owner_id = provider_id.or(group_id).unwrap_or_default();
+        if !self.db.delete_budget(owner_id, period).await? {
+            return Err(AppError::NotFound {
+                kind: "Budget",
+                id: format!("{:?} for {}", period, owner_id),
+            });
+        }
+        Ok(())
+    }
+
+    pub async fn create_group(
+        &self,
+        name: &str,
+        all_providers: bool,
+        provider_ids: Vec<String>,
+    ) -> Result<ProviderGroup, AppError> {
+        let group = ProviderGroup {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: name.trim().to_string(),
+            all_providers,
+            provider_ids,
+            created_at: Utc::now(),
+        };
+        self.check_group(&group).await?;
+
+        self.db.insert_group(&group).await?;
+        Ok(group)
+    }
+
+    /// Renames a group or changes its members. Its budgets and alerts cover
+    /// the new members from the next poll.
+    pub async fn update_group(
+        &self,
+        group_id: &str,
+        name: &str,
+        all_providers: bool,
+        provider_ids: Vec<String>,
+    ) -> Result<ProviderGroup, AppError> {
+        let mut group = self
+            .db
+            .get_group(group_id)
+            .await?
+            .ok_or_else(|| AppError::NotFound {
+                kind: "Group",
+                id: group_id.to_string(),
+            })?;
+        group.name = name.trim().to_string();
+        group.all_providers = all_providers;
+        group.provider_ids = provider_ids;
+        self.check_group(&group).await?;
+
+        self.db.update_group(&group).await?;
+        Ok(group)
     }
-}
\ No newline at end of file
+
+    async fn check_group(&self, group: &ProviderGroup) -> Result<(), AppError> {
+        if group.name.is_empty() {
+            return Err(AppError::InvalidRequest("A group needs a name".to_string()));
+        }
+        if !group.all_providers && group.provider_ids.is_empty() {
+            return Err(AppError::InvalidRequest(
+                "A group needs at least one provider".to_string(),
+            ));
+        }
+
+        let providers = self.providers.read().await;
+        for provider_id in &group.provider_ids {
+            if !providers.iter().any(|p| &p.id == provider_id) {
+                return Err(AppError::NotFound {
+                    kind: "Provider",
+                    id: provider_id.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Deletes a group along with its budgets and alerts.
+    pub async fn remove_group(&self, group_id: &str) -> Result<(), AppError> {
+        if !self.db.delete_group(group_id).await? {
+            return Err(AppError::NotFound {
+                kind: "Group",
+                id: group_id.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Ids of the providers currently in the group.
+    pub async fn group_members(&self, group: &ProviderGroup) -> Vec<String> {
+        self.providers
+            .read()
+            .await
+            .iter()
+            .filter(|p| group.all_providers || group.provider_ids.contains(&p.id))
+            .map(|p| p.id.clone())
+            .collect()
+    }
+
+    /// Local runtimes are usually unauthenticated and have no keychain entry.
+    pub fn api_key_for(provider: &Provider) -> Result<String> {
+        match &provider.api_key_ref {
+            Some(_) => KeychainService::get_api_key(&provider.id),
+            None => Ok(String::new()),
+        }
+    }
+
+    /// Stores metrics under the provider's own id; adapters only know their type.
+    pub async fn store_metrics(&self, provider_id: &str, metrics: Vec<Metric>) -> Result<()> {
+        for mut metric in metrics {
+            metric.provider_id = provider_id.to_string();
+            self.db.insert_metric(&metric).await?;
+        }
+        Ok(())
+    }
+
+    /// Records a successful poll, in memory and in the database.
+    pub async fn store_usage(&self, mut usage: ProviderUsage) -> Result<ProviderUsage> {
+        usage.last_synced_at = Some(Utc::now());
+        usage.stale = false;
+
+        self.db.save_usage_snapshot(&usage).await?;
+        self.usage
+            .write()
+            .await
+            .insert(usage.provider.id.clone(), usage.clone());
+        Ok(usage)
+    }
+
+    /// Every configured provider with its latest usage and last poll error.
+    /// Providers that have not been polled yet report zero usage. Usage is
+    /// stale if the last poll failed or the last successful one is more than
+    /// twice the provider's longest poll interval old.
+    pub async fn usage_snapshot(&self) -> Vec<ProviderUsage> {
+        let providers = self.providers.read().await;
+        let usage = self.usage.read().await;
+        let last_errors = self.last_errors.read().await;
+        let now = Utc::now();
+
+        providers
+            .iter()
+            .map(|provider| {
+                let mut entry = usage
+                    .get(&provider.id)
+                    .cloned()
+                    .unwrap_or_else(|| ProviderUsage {
+                        provider: provider.clone(),
+                        today_tokens: 0,
+                        today_cost: 0.0,
+                        mtd_tokens: 0,
+                        mtd_cost: 0.0,
+                        balance: None,
+                        credits: None,
+                        budget_used_percentage: None,
+                        last_error: None,
+                        capabilities: Self::get_provider_adapter(provider)
+                            .map(|adapter| adapter.capabilities())
+                            .unwrap_or_default(),
+                        last_synced_at: None,
+                        stale: false,
+                        projected_run_out_at: None,
+                        projected_period_spend: None,
+                        notional_mtd_cost: None,
+                    });
+                let max_age = IntervalBounds::for_provider(provider, &entry.capabilities).max * 2;
+
+                entry.provider = provider.clone();
+                entry.last_error = last_errors.get(&provider.id).cloned();
+                entry.stale = entry.last_error.is_some()
+                    || entry.last_synced_at.is_some_and(|at| now - at > max_age);
+                entry
+            })
+            .collect()
+    }
+
+    pub fn get_provider_adapter(provider: &Provider) -> Result<Box<dyn ProviderAdapter>> {
+        Self::adapter_for(&provider.provider_type, &provider.settings)
+    }
+
+    pub fn adapter_for(
+        provider_type: &ProviderType,
+        settings: &ProviderSettings,
+    ) -> Result<Box<dyn ProviderAdapter>> {
+        Ok(match provider_type {
+            ProviderType::OpenAI => Box::new(OpenAIAdapter::new(settings)?),
+            ProviderType::Anthropic => Box::new(AnthropicAdapter::new(settings)?),
+            ProviderType::OpenRouter => Box::new(OpenRouterAdapter::new(settings)?),
+            ProviderType::Gemini => Box::new(GeminiAdapter::new(settings)?),
+            ProviderType::AzureOpenAI => Box::new(AzureOpenAIAdapter::new(settings)?),
+            ProviderType::Custom => Box::new(CustomAdapter::new(settings)?),
+            ProviderType::Local => Box::new(LocalAdapter::new(settings)?),
+            ProviderType::Bedrock => Box::new(BedrockAdapter::new(settings)?),
+        })
+    }
+}
@@ -1,64 +1,407 @@
 use anyhow::Result;
-use chrono::Utc;
-use std::sync::Arc;
-use tokio::time::{Duration, interval};
-use tracing::{info, error};
+use chrono::{DateTime, Datelike, Utc};
+use rand::Rng;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{mpsc, watch, Semaphore};
+use tokio::time::{interval, Duration};
+use tracing::{error, info, warn};
 
-use crate::db::Database;
-use crate::models::{Provider, ProviderUsage};
-use crate::services::{AppState, keychain::KeychainService};
+use crate::error::AppError;
+use crate::models::{BudgetPeriod, Provider, ProviderCapabilities, ProviderUsage};
+use crate::providers::{http::ProviderError, local, ProviderAdapter};
+use crate::services::adaptive::{self, Activity, IntervalBounds};
+use crate::services::alerts::AlertEngine;
+use crate::services::anomaly;
+use crate::services::forecast;
+use crate::services::power::{self, PowerState};
+use crate::services::AppState;
+
+// Polls run concurrently, but not so many that a large provider list
+// saturates the connection.
+const MAX_CONCURRENT_POLLS: usize = 4;
+const SCHEDULER_TICK: Duration = Duration::from_secs(1);
+const POWER_CHECK_INTERVAL_SECS: i64 = 30;
+// While offline only one provider is tried at a time, this often.
+const OFFLINE_PROBE_INTERVAL_SECS: i64 = 30;
+
+struct PollOutcome {
+    provider_id: String,
+    bounds: IntervalBounds,
+    result: Result<ProviderUsage>,
+}
 
 pub struct MonitorService {
     state: Arc<AppState>,
+    alerts: Arc<AlertEngine>,
+    // Polls that are running, so a manual refresh joins one instead of
+    // starting another. The receiver flips to `true` when it finishes.
+    in_flight: Mutex<HashMap<String, watch::Receiver<bool>>>,
+    limit: Arc<Semaphore>,
+    outcomes: mpsc::UnboundedSender<PollOutcome>,
+    outcomes_rx: Mutex<Option<mpsc::UnboundedReceiver<PollOutcome>>>,
 }
 
 impl MonitorService {
-    pub fn new(state: Arc<AppState>) -> Self {
-        Self { state }
+    pub fn new(state: Arc<AppState>, alerts: Arc<AlertEngine>) -> Self {
+        let (outcomes, outcomes_rx) = mpsc::unbounded_channel();
+
+        Self {
+            state,
+            alerts,
+            in_flight: Mutex::new(HashMap::new()),
+            limit: Arc::new(Semaphore::new(MAX_CONCURRENT_POLLS)),
+            outcomes,
+            outcomes_rx: Mutex::new(Some(outcomes_rx)),
+        }
     }
 
-    pub async fn start_polling(&self) {
-        let state = Arc::clone(&self.state);
+    pub async fn start_polling(self: &Arc<Self>) {
+        let Some(mut outcomes) = self.outcomes_rx.lock().unwrap().take() else {
+            return;
+        };
+        let service = Arc::clone(self);
 
         tokio::spawn(async move {
-            let mut ticker = interval(Duration::from_secs(60));
+            let mut ticker = interval(SCHEDULER_TICK);
+            // When each provider should next be polled; absent means now.
+            let mut next_due: HashMap<String, DateTime<Utc>> = HashMap::new();
+            let mut activity: HashMap<String, Activity> = HashMap::new();
+            let mut power = PowerState::default();
+            let mut power_checked_at: Option<DateTime<Utc>> = None;
+            // Providers whose last poll could not reach them at all.
+            let mut unreachable: HashSet<String> = HashSet::new();
+            let mut next_probe: Option<DateTime<Utc>> = None;
+            let mut probes: usize = 0;
 
             loop {
-                ticker.tick().await;
+                tokio::select! {
+                    _ = ticker.tick() => {
+                        // Work from a copy so the lock is not held while polling.
+                        let providers = service.state.providers.read().await.clone();
+                        next_due.retain(|id, _| providers.iter().any(|p| &p.id == id));
+                        activity.retain(|id, _| providers.iter().any(|p| &p.id == id));
+                        unreachable.retain(|id| providers.iter().any(|p| &p.id == id));
 
-                let providers = state.providers.read().await;
+                        let now = Utc::now();
+                        if service.state.offline_since.read().await.is_some() {
+                            if next_probe.is_none_or(|at| at <= now) {
+                                let enabled: Vec<Provider> = providers.into_iter().filter(|p| p.enabled).collect();
+                                if !enabled.is_empty() {
+                                    service.poll(enabled[probes % enabled.len()].clone());
+                                    probes += 1;
+                                }
+                                next_probe = Some(now + chrono::Duration::seconds(OFFLINE_PROBE_INTERVAL_SECS));
+                            }
+                            continue;
+                        }
 
-                for provider in providers.iter() {
-                    if !provider.enabled {
-                        continue;
+                        if power_checked_at.is_none_or(|at| now - at >= chrono::Duration::seconds(POWER_CHECK_INTERVAL_SECS)) {
+                            power = tokio::task::spawn_blocking(power::current).await.unwrap_or_default();
+                            power_checked_at = Some(now);
+                        }
+
+                        for provider in providers {
+                            if !provider.enabled
+                                || service.is_polling(&provider.id)
+                                || next_due.get(&provider.id).is_some_and(|due| *due > now)
+                            {
+                                continue;
+                            }
+
+                            service.poll(provider);
+                        }
                     }
+                    Some(outcome) = outcomes.recv() => {
+                        let network_down = outcome.result
+                            .as_ref()
+                            .err()
+                            .is_some_and(|e| matches!(AppError::from(e), AppError::NetworkUnavailable));
+                        if network_down {
+                            unreachable.insert(outcome.provider_id.clone());
+                        } else {
+                            unreachable.remove(&outcome.provider_id);
+                        }
+
+                        if service.update_connectivity(&unreachable).await {
+                            // Poll everything straight away; each poll fetches
+                            // the gap since its last sync.
+                            next_due.clear();
+                            next_probe = None;
+                            continue;
+                        }
+
+                        let wait = match &outcome.result {
+                            Ok(usage) => {
+                                let trend = activity
+                                    .entry(outcome.provider_id.clone())
+                                    .or_default()
+                                    .record(usage);
+                                adaptive::next_interval(&outcome.bounds, trend, usage.budget_used_percentage, &power)
+                            }
+                            Err(e) => Self::pause_after(e)
+                                .map_or(outcome.bounds.base, |pause| pause.max(outcome.bounds.base)),
+                        };
 
-                    if let Err(e) = Self::poll_provider(&state, provider).await {
-                        error!("Failed to poll provider {}: {}", provider.name, e);
+                        next_due.insert(outcome.provider_id, Utc::now() + Self::with_jitter(wait));
                     }
                 }
             }
         });
     }
 
-    async fn poll_provider(state: &AppState, provider: &Provider) -> Result<()> {
-        let api_key = KeychainService::get_api_key(&provider.id)?;
+    /// Polls now instead of waiting for the schedule, and returns fresh usage
+    /// for `provider_id`, or for every enabled provider. Joins polls that are
+    /// already running. A provider whose poll fails comes back with its last
+    /// known usage, marked stale, and `last_error` set. Works while offline
+    /// too, so a manual refresh doubles as a connectivity check.
+    pub async fn poll_now(
+        self: &Arc<Self>,
+        provider_id: Option<&str>,
+    ) -> Result<Vec<ProviderUsage>, AppError> {
+        let providers: Vec<Provider> = {
+            let providers = self.state.providers.read().await;
+            match provider_id {
+                Some(id) => {
+                    vec![providers
+                        .iter()
+                        .find(|p| p.id == id)
+                        .cloned()
+                        .ok_or_else(|| AppError::NotFound {
+                            kind: "Provider",
+                            id: id.to_string(),
+                        })?]
+                }
+                None => providers.iter().filter(|p| p.enabled).cloned().collect(),
+            }
+        };
 
-        let adapter = AppState::get_provider_adapter(&provider.provider_type);
-        let usage = adapter.get_current_usage(&api_key).await?;
+        let ids: HashSet<String> = providers.iter().map(|p| p.id.clone()).collect();
+        let polls: Vec<_> = providers.into_iter().map(|p| self.poll(p)).collect();
+
+        for mut done in polls {
+            // An error only means the poll task went away; report what we have.
+            let _ = done.wait_for(|finished| *finished).await;
+        }
+
+        Ok(self
+            .state
+            .usage_snapshot()
+            .await
+            .into_iter()
+            .filter(|usage| ids.contains(&usage.provider.id))
+            .collect())
+    }
+
+    /// The network counts as down once every enabled provider's last poll
+    /// failed to connect, so one unreachable host does not pause the rest.
+    /// Returns `true` when the network has just come back.
+    async fn update_connectivity(&self, unreachable: &HashSet<String>) -> bool {
+        let all_unreachable = {
+            let providers = self.state.providers.read().await;
+            let mut enabled = providers.iter().filter(|p| p.enabled).peekable();
+            enabled.peek().is_some() && enabled.all(|p| unreachable.contains(&p.id))
+        };
+
+        let mut offline_since = self.state.offline_since.write().await;
+        match (*offline_since, all_unreachable) {
+            (None, true) => {
+                warn!("No provider is reachable; pausing polls until the network is back");
+                *offline_since = Some(Utc::now());
+                false
+            }
+            (Some(since), false) => {
+                info!(
+                    "Network is back after {}s offline; catching up",
+                    (Utc::now() - since).num_seconds()
+                );
+                *offline_since = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn is_polling(&self, provider_id: &str) -> bool {
+        self.in_flight.lock().unwrap().contains_key(provider_id)
+    }
+
+    /// Starts a poll unless one is already running, and returns a receiver
+    /// that turns `true` when it is done.
+    fn poll(self: &Arc<Self>, provider: Provider) -> watch::Receiver<bool> {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        if let Some(done) = in_flight.get(&provider.id) {
+            return done.clone();
+        }
+
+        let (finished, done) = watch::channel(false);
+        in_flight.insert(provider.id.clone(), done.clone());
+
+        let service = Arc::clone(self);
+        tokio::spawn(async move {
+            let outcome = {
+                let _permit = service
+                    .limit
+                    .acquire()
+                    .await
+                    .expect("poll semaphore is never closed");
+                service.run_poll(provider).await
+            };
+
+            service
+                .in_flight
+                .lock()
+                .unwrap()
+                .remove(&outcome.provider_id);
+            let _ = finished.send(true);
+            let _ = service.outcomes.send(outcome);
+        });
+
+        done
+    }
+
+    /// Polls one provider, records the result in `AppState` and evaluates its
+    /// alerts. The outcome tells the scheduler when to come back.
+    async fn run_poll(&self, provider: Provider) -> PollOutcome {
+        let state = &self.state;
+        let (bounds, result) = match AppState::get_provider_adapter(&provider) {
+            Ok(adapter) => (
+                IntervalBounds::for_provider(&provider, &adapter.capabilities()),
+                Self::poll_provider(state, &provider, adapter.as_ref()).await,
+            ),
+            Err(e) => (
+                IntervalBounds::for_provider(&provider, &ProviderCapabilities::default()),
+                Err(e),
+            ),
+        };
+
+        match &result {
+            Ok(usage) => {
+                state.last_errors.write().await.remove(&provider.id);
+                if let Err(e) = self.alerts.evaluate(usage).await {
+                    error!("Failed to evaluate alerts for {}: {}", provider.name, e);
+                }
+            }
+            Err(e) => {
+                error!("Failed to poll provider {}: {}", provider.name, e);
+                state
+                    .last_errors
+                    .write()
+                    .await
+                    .insert(provider.id.clone(), AppError::from(e).to_state());
+            }
+        }
+
+        PollOutcome {
+            provider_id: provider.id,
+            bounds,
+            result,
+        }
+    }
+
+    /// Spreads polls by up to 10% either way so providers added together do
+    /// not stay in lockstep.
+    fn with_jitter(wait: chrono::Duration) -> chrono::Duration {
+        let factor = rand::thread_rng().gen_range(0.9..=1.1);
+        chrono::Duration::milliseconds((wait.num_milliseconds() as f64 * factor) as i64)
+    }
+
+    /// How long to leave a provider alone after a failed poll. Outages are
+    /// already retried inside the request, so those just wait for the next poll.
+    fn pause_after(error: &anyhow::Error) -> Option<chrono::Duration> {
+        match error.downcast_ref::<ProviderError>()? {
+            ProviderError::RateLimited { retry_after } => Some(
+                retry_after
+                    .and_then(|d| chrono::Duration::from_std(d).ok())
+                    .unwrap_or_else(|| chrono::Duration::minutes(5)),
+            ),
+            // A rejected key will not fix itself; avoid hammering the API with it.
+            ProviderError::Unauthorized { .. } | ProviderError::TokenExpired => {
+                Some(chrono::Duration::hours(1))
+            }
+            ProviderError::SchemaChanged(_) => Some(chrono::Duration::minutes(15)),
+            // Nothing reaches the provider, so retrying costs nothing and
+            // picks up fixed settings straight away.
+            ProviderError::Misconfigured(_) => None,
+            ProviderError::UpstreamDown { .. } | ProviderError::Rejected { .. } => None,
+        }
+    }
+
+    async fn poll_provider(
+        state: &AppState,
+        provider: &Provider,
+        adapter: &dyn ProviderAdapter,
+    ) -> Result<ProviderUsage> {
+        let api_key = AppState::api_key_for(provider)?;
 
         let now = Utc::now();
-        let start_of_month = now.date_naive()
+        let start_of_month = now
+            .date_naive()
             .with_day(1)
             .unwrap()
             .and_hms_opt(0, 0, 0)
             .unwrap()
             .and_utc();
 
-        let metrics = adapter.fetch_usage(&api_key, start_of_month, now).await?;
+        // Every poll covers the month so far. If the last successful one was
+        // before that, e.g. after a long time offline, fetch the gap as well,
+        // as far back as the provider keeps history.
+        let last_synced_at = state
+            .usage
+            .read()
+            .await
+            .get(&provider.id)
+            .and_then(|u| u.last_synced_at);
+        let from = match last_synced_at {
+            Some(synced) if synced < start_of_month => {
+                let oldest = start_of_month
+                    - chrono::Duration::days(adapter.capabilities().backfill_days as i64);
+                synced.max(oldest)
+            }
+            _ => start_of_month,
+        };
+
+        // Usage calls are often billed, so the metrics are fetched once and
+        // the current usage is built from them.
+        let metrics = adapter.fetch_usage(&api_key, from, now).await?;
+        let mut usage = adapter.get_current_usage(&api_key, &metrics).await?;
+        // Adapters fill in placeholder provider details; keep the configured ones.
+        usage.provider = provider.clone();
+
+        // Buckets can be stamped at the end of their period, after `now`.
+        let totals_to = now + chrono::Duration::days(1);
+        let before = anomaly::usage_totals(&state.db, &provider.id, from, totals_to).await?;
+        state.store_metrics(&provider.id, metrics).await?;
+        let after = anomaly::usage_totals(&state.db, &provider.id, from, totals_to).await?;
+        state
+            .store_metrics(
+                &provider.id,
+                anomaly::growth(&before, &after, last_synced_at, now),
+            )
+            .await?;
+
+        if usage.capabilities.usage_from_history {
+            Self::usage_from_history(state, &mut usage, start_of_month, now).await?;
+        }
 
-        for metric in metrics {
-            state.db.insert_metric(&metric).await?;
+        match forecast::forecast(&state.db, &usage, now).await {
+            Ok(forecast) => {
+                usage.projected_run_out_at = forecast.run_out_at;
+                usage.projected_period_spend = forecast.period_spend;
+            }
+            Err(e) => warn!("Failed to forecast {}: {}", provider.name, e),
+        }
+
+        match Self::budget_used_percentage(state, &usage, now).await {
+            Ok(Some(percent)) => {
+                usage.budget_used_percentage = Some(
+                    usage
+                        .budget_used_percentage
+                        .map_or(percent, |p| p.max(percent)),
+                );
+            }
+            Ok(None) => {}
+            Err(e) => warn!("Failed to check budgets for {}: {}", provider.name, e),
         }
 
         info!(
@@ -66,30 +409,92 @@ impl MonitorService {
             provider.name, usage.today_tokens, usage.mtd_cost
         );
 
-        Ok(())
+        state.store_usage(usage).await
     }
 
-    pub async fn get_all_usage(&self) -> Result<Vec<ProviderUsage>> {
-        let mut all_usage = Vec::new();
-        let providers = self.state.providers.read().await;
+    /// The most used of the provider's own budgets, as a percentage of its
+    /// hard limit, or its soft limit if there is no hard one. Measured the
+    /// same way as `budget_percentage` alerts.
+    async fn budget_used_percentage(
+        state: &AppState,
+        usage: &ProviderUsage,
+        now: DateTime<Utc>,
+    ) -> Result<Option<f64>> {
+        if !usage.capabilities.cost {
+            return Ok(None);
+        }
 
-        for provider in providers.iter() {
-            if !provider.enabled {
+        let provider_ids = [usage.provider.id.clone()];
+        let mut most_used: Option<f64> = None;
+        for budget in state.db.get_budgets(Some(&usage.provider.id), None).await? {
+            let Some(limit) = budget.hard_limit.or(budget.soft_limit).filter(|l| *l > 0.0) else {
                 continue;
-            }
+            };
 
-            match self.get_provider_usage(provider).await {
-                Ok(usage) => all_usage.push(usage),
-                Err(e) => error!("Failed to get usage for {}: {}", provider.name, e),
-            }
+            let spent = match budget.period {
+                BudgetPeriod::Monthly => usage.mtd_cost,
+                BudgetPeriod::Daily | BudgetPeriod::Weekly => {
+                    let mut start = now.date_naive();
+                    if budget.period == BudgetPeriod::Weekly {
+                        start -=
+                            chrono::Duration::days(start.weekday().num_days_from_monday().into());
+                    }
+                    let from = start.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc();
+                    state
+                        .db
+                        .sum_metrics(&provider_ids, &["CostUsd"], Some("usd"), &[], from, now)
+                        .await?
+                }
+            };
+
+            let percent = spent / limit * 100.0;
+            most_used = Some(most_used.map_or(percent, |m| m.max(percent)));
         }
 
-        Ok(all_usage)
+        Ok(most_used)
     }
 
-    async fn get_provider_usage(&self, provider: &Provider) -> Result<ProviderUsage> {
-        let api_key = KeychainService::get_api_key(&provider.id)?;
-        let adapter = AppState::get_provider_adapter(&provider.provider_type);
-        adapter.get_current_usage(&api_key).await
+    /// Today and month-to-date from the stored per-poll deltas. Only USD cost
+    /// is spend; a local runtime's notional cost is reported on its own.
+    async fn usage_from_history(
+        state: &AppState,
+        usage: &mut ProviderUsage,
+        start_of_month: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        let provider_ids = [usage.provider.id.clone()];
+        let start_of_day = now.date_naive().and_hms_opt(0, 0, 0).unwrap().and_utc();
+        // Deltas are stamped when they are read, so include this poll's.
+        let to = now + chrono::Duration::seconds(1);
+
+        let tokens = |from| {
+            state.db.sum_metrics(
+                &provider_ids,
+                &["TokensIn", "TokensOut"],
+                None,
+                &[],
+                from,
+                to,
+            )
+        };
+        let cost = |from, unit| {
+            state
+                .db
+                .sum_metrics(&provider_ids, &["CostUsd"], Some(unit), &[], from, to)
+        };
+
+        usage.today_tokens = tokens(start_of_day).await? as u64;
+        usage.mtd_tokens = tokens(start_of_month).await? as u64;
+        usage.today_cost = cost(start_of_day, "usd").await?;
+        usage.mtd_cost = cost(start_of_month, "usd").await?;
+        if usage
+            .provider
+            .settings
+            .notional_cost_per_million_tokens
+            .is_some()
+        {
+            usage.notional_mtd_cost = Some(cost(start_of_month, local::NOTIONAL_USD).await?);
+        }
+        Ok(())
     }
-}
\ No newline at end of file
+}
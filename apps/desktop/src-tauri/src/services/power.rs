@@ -0,0 +1,145 @@
+use std::time::Duration;
+
+/// Whether the machine is running on battery and how long since the user
+/// last touched it. Where a platform offers no cheap way to ask, these read
+/// as on mains power and not idle.
+#[derive(Debug, Clone, Default)]
+pub struct PowerState {
+    pub on_battery: bool,
+    pub idle_for: Option<Duration>,
+}
+
+/// Blocking: may run a system command. Call from `spawn_blocking`.
+pub fn current() -> PowerState {
+    PowerState {
+        on_battery: platform::on_battery(),
+        idle_for: platform::idle_for(),
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::path::Path;
+    use std::time::Duration;
+
+    pub fn on_battery() -> bool {
+        let Ok(entries) = std::fs::read_dir("/sys/class/power_supply") else {
+            return false;
+        };
+
+        let read = |path: &Path| std::fs::read_to_string(path).unwrap_or_default();
+        let mut discharging = false;
+        let mut on_mains = false;
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            match read(&path.join("type")).trim() {
+                "Mains" => on_mains |= read(&path.join("online")).trim() == "1",
+                "Battery" => discharging |= read(&path.join("status")).trim() == "Discharging",
+                _ => {}
+            }
+        }
+
+        discharging && !on_mains
+    }
+
+    // Idle time is only available through the display server (X11, or a
+    // compositor-specific protocol on Wayland).
+    pub fn idle_for() -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::process::Command;
+    use std::time::Duration;
+
+    pub fn on_battery() -> bool {
+        Command::new("pmset")
+            .args(["-g", "batt"])
+            .output()
+            .map(|output| String::from_utf8_lossy(&output.stdout).contains("'Battery Power'"))
+            .unwrap_or(false)
+    }
+
+    pub fn idle_for() -> Option<Duration> {
+        let output = Command::new("ioreg")
+            .args(["-c", "IOHIDSystem", "-d", "4", "-r", "-k", "HIDIdleTime"])
+            .output()
+            .ok()?;
+        let text = String::from_utf8_lossy(&output.stdout);
+
+        // `"HIDIdleTime" = 1234567890`, in nanoseconds.
+        let line = text.lines().find(|line| line.contains("\"HIDIdleTime\""))?;
+        let nanos = line.rsplit('=').next()?.trim().parse().ok()?;
+        Some(Duration::from_nanos(nanos))
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::time::Duration;
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct SystemPowerStatus {
+        ac_line_status: u8,
+        battery_flag: u8,
+        battery_life_percent: u8,
+        system_status_flag: u8,
+        battery_life_time: u32,
+        battery_full_life_time: u32,
+    }
+
+    #[repr(C)]
+    struct LastInputInfo {
+        cb_size: u32,
+        dw_time: u32,
+    }
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetSystemPowerStatus(status: *mut SystemPowerStatus) -> i32;
+        fn GetTickCount() -> u32;
+    }
+
+    #[link(name = "user32")]
+    extern "system" {
+        fn GetLastInputInfo(info: *mut LastInputInfo) -> i32;
+    }
+
+    pub fn on_battery() -> bool {
+        let mut status = SystemPowerStatus::default();
+        // SAFETY: `status` is a valid, writable SYSTEM_POWER_STATUS.
+        let ok = unsafe { GetSystemPowerStatus(&mut status) } != 0;
+        ok && status.ac_line_status == 0
+    }
+
+    pub fn idle_for() -> Option<Duration> {
+        let mut info = LastInputInfo {
+            cb_size: std::mem::size_of::<LastInputInfo>() as u32,
+            dw_time: 0,
+        };
+        // SAFETY: `info` is a valid LASTINPUTINFO with `cb_size` set.
+        if unsafe { GetLastInputInfo(&mut info) } == 0 {
+            return None;
+        }
+        // SAFETY: no arguments; always succeeds.
+        let now = unsafe { GetTickCount() };
+        Some(Duration::from_millis(now.wrapping_sub(info.dw_time) as u64))
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+mod platform {
+    use std::time::Duration;
+
+    pub fn on_battery() -> bool {
+        false
+    }
+
+    pub fn idle_for() -> Option<Duration> {
+        None
+    }
+}
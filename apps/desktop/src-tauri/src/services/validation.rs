@@ -0,0 +1,74 @@
+use crate::error::AppError;
+use crate::models::{KeyValidation, ProviderSettings, ProviderType};
+use crate::services::AppState;
+
+/// Rejects keys that cannot be right for `provider_type` before any request
+/// is made, e.g. an OpenAI key pasted into an Anthropic provider.
+pub fn check_key_format(provider_type: &ProviderType, api_key: &str) -> Result<(), AppError> {
+    let invalid = |hint: &str| {
+        Err(AppError::InvalidRequest(format!(
+            "Invalid API key format: {}",
+            hint
+        )))
+    };
+
+    // Google application default credentials are a JSON document.
+    if matches!(provider_type, ProviderType::Gemini) && api_key.trim_start().starts_with('{') {
+        let credential_type = serde_json::from_str::<serde_json::Value>(api_key)
+            .ok()
+            .and_then(|value| value.get("type")?.as_str().map(str::to_string));
+        return match credential_type.as_deref() {
+            Some("authorized_user") => Ok(()),
+            _ => invalid(
+                "expected an access token or the JSON from `gcloud auth application-default login`",
+            ),
+        };
+    }
+
+    if api_key.chars().any(char::is_whitespace) {
+        return invalid("the key contains spaces or line breaks");
+    }
+
+    // Local runtimes usually run without authentication.
+    if api_key.is_empty() {
+        return match provider_type {
+            ProviderType::Local => Ok(()),
+            _ => invalid("the key is empty"),
+        };
+    }
+
+    match provider_type {
+        ProviderType::Anthropic if !api_key.starts_with("sk-ant-") => {
+            invalid("Anthropic keys start with \"sk-ant-\"")
+        }
+        ProviderType::OpenRouter if !api_key.starts_with("sk-or-") => {
+            invalid("OpenRouter keys start with \"sk-or-\"")
+        }
+        ProviderType::OpenAI if !api_key.starts_with("sk-") || api_key.starts_with("sk-ant-") => {
+            invalid("OpenAI keys start with \"sk-\"")
+        }
+        ProviderType::Bedrock
+            if api_key
+                .splitn(3, ':')
+                .filter(|part| !part.is_empty())
+                .count()
+                < 2 =>
+        {
+            invalid("expected ACCESS_KEY_ID:SECRET_ACCESS_KEY[:SESSION_TOKEN]")
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Checks the key's format, then makes the adapter's lightweight
+/// authenticated calls to find out what the key can read.
+pub async fn validate_provider_key(
+    provider_type: &ProviderType,
+    api_key: &str,
+    settings: &ProviderSettings,
+) -> Result<KeyValidation, AppError> {
+    check_key_format(provider_type, api_key)?;
+
+    let adapter = AppState::adapter_for(provider_type, settings)?;
+    Ok(adapter.validate_key(api_key).await?)
+}
@@ -1,11 +1,70 @@
-import React, { useState } from 'react';
+import React, { useEffect, useState } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { ProviderError } from './ProviderCard';
 import '../styles/Modal.css';
 
+export interface ProviderSettings {
+  gcp_project_id?: string;
+  gcp_token_uri?: string;
+  base_url?: string;
+  api_version?: string;
+  custom?: CustomProviderConfig;
+  local_runtime?: 'ollama' | 'llama_cpp';
+  notional_cost_per_million_tokens?: number;
+  aws_region?: string;
+  poll_interval_secs?: number;
+  min_poll_interval_secs?: number;
+  max_poll_interval_secs?: number;
+  http?: HttpSettings;
+}
+
+export interface HttpSettings {
+  proxy_url?: string;
+  ca_bundle_path?: string;
+  timeout_secs?: number;
+  user_agent?: string;
+}
+
+export interface CustomProviderConfig {
+  auth_style:
+    | { type: 'bearer' }
+    | { type: 'header'; name: string }
+    | { type: 'query'; name: string };
+  balance_path?: string;
+  balance_pointer?: string;
+  usage_path?: string;
+  cost_pointer?: string;
+  tokens_in_pointer?: string;
+  tokens_out_pointer?: string;
+}
+
+export interface ProviderCapabilities {
+  balance: boolean;
+  credits: boolean;
+  token_usage: boolean;
+  cost: boolean;
+  estimated_cost: boolean;
+  per_model_breakdown: boolean;
+  backfill_days: number;
+  min_poll_interval_secs: number;
+  requires_api_key: boolean;
+  usage_from_history: boolean;
+  month_to_date_only: boolean;
+}
+
+export interface KeyValidation {
+  usage_read: boolean;
+  cost_read: boolean;
+  balance_read: boolean;
+  admin_key?: boolean;
+}
+
 interface AddProviderModalProps {
   onAdd: (provider: {
     provider_type: string;
     api_key: string;
     name: string;
+    settings: ProviderSettings;
   }) => void;
   onClose: () => void;
 }
@@ -14,16 +73,122 @@ export const AddProviderModal: React.FC<AddProviderModalProps> = ({ onAdd, onClo
   const [providerType, setProviderType] = useState('openai');
   const [apiKey, setApiKey] = useState('');
   const [name, setName] = useState('');
+  const [gcpProjectId, setGcpProjectId] = useState('');
+  const [baseUrl, setBaseUrl] = useState('');
+  const [apiVersion, setApiVersion] = useState('');
+  const [authType, setAuthType] = useState<'bearer' | 'header' | 'query'>('bearer');
+  const [authName, setAuthName] = useState('');
+  const [balancePath, setBalancePath] = useState('');
+  const [balancePointer, setBalancePointer] = useState('');
+  const [usagePath, setUsagePath] = useState('');
+  const [costPointer, setCostPointer] = useState('');
+  const [tokensInPointer, setTokensInPointer] = useState('');
+  const [tokensOutPointer, setTokensOutPointer] = useState('');
+  const [localRuntime, setLocalRuntime] = useState<'ollama' | 'llama_cpp'>('ollama');
+  const [notionalCost, setNotionalCost] = useState('');
+  const [awsRegion, setAwsRegion] = useState('');
+  const [proxyUrl, setProxyUrl] = useState('');
+  const [caBundlePath, setCaBundlePath] = useState('');
+  const [timeoutSecs, setTimeoutSecs] = useState('');
+  const [userAgent, setUserAgent] = useState('');
+  const [pollInterval, setPollInterval] = useState('');
+  const [minPollInterval, setMinPollInterval] = useState('');
+  const [maxPollInterval, setMaxPollInterval] = useState('');
+  const [validating, setValidating] = useState(false);
+  const [validation, setValidation] = useState<KeyValidation | null>(null);
+  const [validationError, setValidationError] = useState<string | null>(null);
+  const [capabilities, setCapabilities] = useState<ProviderCapabilities | null>(null);
+
+  useEffect(() => {
+    invoke<ProviderCapabilities>('get_provider_capabilities', {
+      request: { provider_type: providerType },
+    })
+      .then(setCapabilities)
+      .catch(() => setCapabilities(null));
+  }, [providerType]);
+
+  const requiresApiKey = capabilities?.requires_api_key ?? true;
+
+  // A result only applies to the key and endpoint it was tested with.
+  useEffect(() => {
+    setValidation(null);
+    setValidationError(null);
+  }, [providerType, apiKey, baseUrl, gcpProjectId, awsRegion]);
 
-  const handleSubmit = (e: React.FormEvent) => {
+  // These types take the endpoint as a regular field rather than an override.
+  const hasEndpointField = ['azure_openai', 'custom', 'local', 'bedrock'].includes(providerType);
+
+  const buildSettings = (): ProviderSettings => {
+    const settings: ProviderSettings = {};
+    if (providerType === 'gemini' && gcpProjectId) {
+      settings.gcp_project_id = gcpProjectId;
+    }
+    if (providerType === 'azure_openai') {
+      settings.base_url = baseUrl;
+      if (apiVersion) settings.api_version = apiVersion;
+    }
+    if (providerType === 'custom') {
+      settings.base_url = baseUrl;
+      settings.custom = {
+        auth_style: authType === 'bearer' ? { type: 'bearer' } : { type: authType, name: authName },
+        balance_path: balancePath || undefined,
+        balance_pointer: balancePointer || undefined,
+        usage_path: usagePath || undefined,
+        cost_pointer: costPointer || undefined,
+        tokens_in_pointer: tokensInPointer || undefined,
+        tokens_out_pointer: tokensOutPointer || undefined,
+      };
+    }
+    if (providerType === 'local') {
+      settings.local_runtime = localRuntime;
+      if (baseUrl) settings.base_url = baseUrl;
+      if (notionalCost) settings.notional_cost_per_million_tokens = parseFloat(notionalCost);
+    }
+    if (providerType === 'bedrock') {
+      if (awsRegion) settings.aws_region = awsRegion;
+      if (baseUrl) settings.base_url = baseUrl;
+    }
+    if (!hasEndpointField && baseUrl) {
+      settings.base_url = baseUrl;
+    }
+    if (pollInterval) settings.poll_interval_secs = parseInt(pollInterval, 10);
+    if (minPollInterval) settings.min_poll_interval_secs = parseInt(minPollInterval, 10);
+    if (maxPollInterval) settings.max_poll_interval_secs = parseInt(maxPollInterval, 10);
+    const http: HttpSettings = {};
+    if (proxyUrl) http.proxy_url = proxyUrl;
+    if (caBundlePath) http.ca_bundle_path = caBundlePath;
+    if (timeoutSecs) http.timeout_secs = parseInt(timeoutSecs, 10);
+    if (userAgent) http.user_agent = userAgent;
+    if (Object.keys(http).length > 0) settings.http = http;
+    return settings;
+  };
+
+  const handleSubmit = async (e: React.FormEvent) => {
     e.preventDefault();
-    if (apiKey && name) {
-      onAdd({
-        provider_type: providerType,
-        api_key: apiKey,
-        name: name,
-      });
+    if ((requiresApiKey && !apiKey) || !name) return;
+
+    // First submit tests the key; the second adds the provider.
+    if (!validation) {
+      setValidating(true);
+      try {
+        const result = await invoke<KeyValidation>('validate_provider_key', {
+          request: { provider_type: providerType, api_key: apiKey, settings: buildSettings() },
+        });
+        setValidation(result);
+      } catch (error) {
+        setValidationError((error as ProviderError)?.message ?? String(error));
+      } finally {
+        setValidating(false);
+      }
+      return;
     }
+
+    onAdd({
+      provider_type: providerType,
+      api_key: apiKey,
+      name: name,
+      settings: buildSettings(),
+    });
   };
 
   const getProviderPlaceholder = () => {
@@ -34,6 +199,16 @@ export const AddProviderModal: React.FC<AddProviderModalProps> = ({ onAdd, onClo
         return 'sk-ant-...';
       case 'openrouter':
         return 'sk-or-...';
+      case 'gemini':
+        return gcpProjectId ? 'Access token or application default credentials JSON' : 'AIza...';
+      case 'azure_openai':
+        return 'RESOURCE_KEY[:CLIENT_SECRET]';
+      case 'custom':
+        return 'API Key';
+      case 'local':
+        return 'Optional, if started with --api-key';
+      case 'bedrock':
+        return 'ACCESS_KEY_ID:SECRET_ACCESS_KEY[:SESSION_TOKEN]';
       default:
         return 'API Key';
     }
@@ -58,9 +233,206 @@ export const AddProviderModal: React.FC<AddProviderModalProps> = ({ onAdd, onClo
               <option value="openai">OpenAI</option>
               <option value="anthropic">Anthropic</option>
               <option value="openrouter">OpenRouter</option>
+              <option value="gemini">Google Gemini / Vertex AI</option>
+              <option value="azure_openai">Azure OpenAI</option>
+              <option value="custom">Custom (OpenAI-compatible)</option>
+              <option value="local">Local (Ollama / llama.cpp)</option>
+              <option value="bedrock">AWS Bedrock</option>
             </select>
           </div>
 
+          {providerType === 'gemini' && (
+            <div className="form-group">
+              <label htmlFor="gcp-project-id">GCP Project ID (optional)</label>
+              <input
+                id="gcp-project-id"
+                type="text"
+                value={gcpProjectId}
+                onChange={(e) => setGcpProjectId(e.target.value)}
+                placeholder="e.g., my-vertex-project"
+              />
+              <small className="help-text">
+                Required for token and cost tracking via Vertex AI; without it only budgets are tracked
+              </small>
+            </div>
+          )}
+
+          {providerType === 'azure_openai' && (
+            <>
+              <div className="form-group">
+                <label htmlFor="base-url">Resource Endpoint</label>
+                <input
+                  id="base-url"
+                  type="url"
+                  value={baseUrl}
+                  onChange={(e) => setBaseUrl(e.target.value)}
+                  placeholder="https://my-resource.openai.azure.com"
+                  required
+                />
+              </div>
+              <div className="form-group">
+                <label htmlFor="api-version">API Version (optional)</label>
+                <input
+                  id="api-version"
+                  type="text"
+                  value={apiVersion}
+                  onChange={(e) => setApiVersion(e.target.value)}
+                  placeholder="2024-10-21"
+                />
+              </div>
+            </>
+          )}
+
+          {providerType === 'custom' && (
+            <>
+              <div className="form-group">
+                <label htmlFor="custom-base-url">Base URL</label>
+                <input
+                  id="custom-base-url"
+                  type="url"
+                  value={baseUrl}
+                  onChange={(e) => setBaseUrl(e.target.value)}
+                  placeholder="https://api.deepseek.com"
+                  required
+                />
+              </div>
+              <div className="form-group">
+                <label htmlFor="auth-type">Authentication</label>
+                <select
+                  id="auth-type"
+                  value={authType}
+                  onChange={(e) => setAuthType(e.target.value as 'bearer' | 'header' | 'query')}
+                >
+                  <option value="bearer">Bearer token</option>
+                  <option value="header">Custom header</option>
+                  <option value="query">Query parameter</option>
+                </select>
+                {authType !== 'bearer' && (
+                  <input
+                    type="text"
+                    value={authName}
+                    onChange={(e) => setAuthName(e.target.value)}
+                    placeholder={authType === 'header' ? 'x-api-key' : 'key'}
+                    required
+                  />
+                )}
+              </div>
+              <div className="form-group">
+                <label htmlFor="balance-path">Balance Endpoint</label>
+                <input
+                  id="balance-path"
+                  type="text"
+                  value={balancePath}
+                  onChange={(e) => setBalancePath(e.target.value)}
+                  placeholder="/user/balance"
+                />
+                <input
+                  type="text"
+                  value={balancePointer}
+                  onChange={(e) => setBalancePointer(e.target.value)}
+                  placeholder="/balance_infos/0/total_balance"
+                />
+              </div>
+              <div className="form-group">
+                <label htmlFor="usage-path">Usage Endpoint</label>
+                <input
+                  id="usage-path"
+                  type="text"
+                  value={usagePath}
+                  onChange={(e) => setUsagePath(e.target.value)}
+                  placeholder="/usage?start_date={from}&end_date={to}"
+                />
+                <input
+                  type="text"
+                  value={costPointer}
+                  onChange={(e) => setCostPointer(e.target.value)}
+                  placeholder="Cost pointer, e.g. /total_cost"
+                />
+                <input
+                  type="text"
+                  value={tokensInPointer}
+                  onChange={(e) => setTokensInPointer(e.target.value)}
+                  placeholder="Input tokens pointer, e.g. /usage/prompt_tokens"
+                />
+                <input
+                  type="text"
+                  value={tokensOutPointer}
+                  onChange={(e) => setTokensOutPointer(e.target.value)}
+                  placeholder="Output tokens pointer, e.g. /usage/completion_tokens"
+                />
+                <small className="help-text">
+                  Fields are JSON pointers into the response; leave blank to skip
+                </small>
+              </div>
+            </>
+          )}
+
+          {providerType === 'local' && (
+            <>
+              <div className="form-group">
+                <label htmlFor="local-runtime">Runtime</label>
+                <select
+                  id="local-runtime"
+                  value={localRuntime}
+                  onChange={(e) => setLocalRuntime(e.target.value as 'ollama' | 'llama_cpp')}
+                >
+                  <option value="ollama">Ollama</option>
+                  <option value="llama_cpp">llama.cpp server</option>
+                </select>
+              </div>
+              <div className="form-group">
+                <label htmlFor="local-base-url">Server URL (optional)</label>
+                <input
+                  id="local-base-url"
+                  type="url"
+                  value={baseUrl}
+                  onChange={(e) => setBaseUrl(e.target.value)}
+                  placeholder={localRuntime === 'ollama' ? 'http://localhost:11434' : 'http://localhost:8080'}
+                />
+              </div>
+              <div className="form-group">
+                <label htmlFor="notional-cost">Notional cost per 1M tokens (optional)</label>
+                <input
+                  id="notional-cost"
+                  type="number"
+                  min="0"
+                  step="0.01"
+                  value={notionalCost}
+                  onChange={(e) => setNotionalCost(e.target.value)}
+                  placeholder="0.00"
+                />
+                <small className="help-text">
+                  Used to compare local usage with hosted spend
+                </small>
+              </div>
+            </>
+          )}
+
+          {providerType === 'bedrock' && (
+            <>
+              <div className="form-group">
+                <label htmlFor="aws-region">AWS Region</label>
+                <input
+                  id="aws-region"
+                  type="text"
+                  value={awsRegion}
+                  onChange={(e) => setAwsRegion(e.target.value)}
+                  placeholder="us-east-1"
+                />
+              </div>
+              <div className="form-group">
+                <label htmlFor="cloudwatch-url">CloudWatch Endpoint (optional)</label>
+                <input
+                  id="cloudwatch-url"
+                  type="url"
+                  value={baseUrl}
+                  onChange={(e) => setBaseUrl(e.target.value)}
+                  placeholder="https://monitoring.us-east-1.amazonaws.com"
+                />
+              </div>
+            </>
+          )}
+
           <div className="form-group">
             <label htmlFor="name">Display Name</label>
             <input
@@ -81,19 +453,132 @@ export const AddProviderModal: React.FC<AddProviderModalProps> = ({ onAdd, onClo
               value={apiKey}
               onChange={(e) => setApiKey(e.target.value)}
               placeholder={getProviderPlaceholder()}
-              required
+              required={requiresApiKey}
             />
             <small className="help-text">
               Your API key will be securely stored in your system's keychain
             </small>
           </div>
 
+          <details className="form-group">
+            <summary>Connection settings</summary>
+            {!hasEndpointField && (
+              <div className="form-group">
+                <label htmlFor="base-url-override">Base URL override</label>
+                <input
+                  id="base-url-override"
+                  type="url"
+                  value={baseUrl}
+                  onChange={(e) => setBaseUrl(e.target.value)}
+                  placeholder="Corporate gateway or regional endpoint"
+                />
+              </div>
+            )}
+            <div className="form-group">
+              <label htmlFor="poll-interval">Poll interval (seconds)</label>
+              <input
+                id="poll-interval"
+                type="number"
+                min={capabilities?.min_poll_interval_secs ?? 1}
+                value={pollInterval}
+                onChange={(e) => setPollInterval(e.target.value)}
+                placeholder={String(Math.max(60, capabilities?.min_poll_interval_secs ?? 60))}
+              />
+              <small className="help-text">
+                Polling slows down while usage is flat or on battery, and speeds up when spend
+                climbs, staying within the bounds below
+              </small>
+            </div>
+            <div className="form-group">
+              <label htmlFor="min-poll-interval">Fastest poll (seconds)</label>
+              <input
+                id="min-poll-interval"
+                type="number"
+                min={capabilities?.min_poll_interval_secs ?? 1}
+                value={minPollInterval}
+                onChange={(e) => setMinPollInterval(e.target.value)}
+                placeholder={String(capabilities?.min_poll_interval_secs ?? 60)}
+              />
+            </div>
+            <div className="form-group">
+              <label htmlFor="max-poll-interval">Slowest poll (seconds)</label>
+              <input
+                id="max-poll-interval"
+                type="number"
+                min="1"
+                value={maxPollInterval}
+                onChange={(e) => setMaxPollInterval(e.target.value)}
+                placeholder="900"
+              />
+            </div>
+            <div className="form-group">
+              <label htmlFor="proxy-url">Proxy</label>
+              <input
+                id="proxy-url"
+                type="url"
+                value={proxyUrl}
+                onChange={(e) => setProxyUrl(e.target.value)}
+                placeholder="http://proxy.example.com:3128"
+              />
+            </div>
+            <div className="form-group">
+              <label htmlFor="ca-bundle">CA bundle path</label>
+              <input
+                id="ca-bundle"
+                type="text"
+                value={caBundlePath}
+                onChange={(e) => setCaBundlePath(e.target.value)}
+                placeholder="/etc/ssl/certs/corp-ca.pem"
+              />
+            </div>
+            <div className="form-group">
+              <label htmlFor="timeout">Request timeout (seconds)</label>
+              <input
+                id="timeout"
+                type="number"
+                min="1"
+                value={timeoutSecs}
+                onChange={(e) => setTimeoutSecs(e.target.value)}
+                placeholder="30"
+              />
+            </div>
+            <div className="form-group">
+              <label htmlFor="user-agent">User-Agent</label>
+              <input
+                id="user-agent"
+                type="text"
+                value={userAgent}
+                onChange={(e) => setUserAgent(e.target.value)}
+                placeholder="AIMonitor-Desktop/1.0.0"
+              />
+            </div>
+          </details>
+
+          {validationError && (
+            <div className="validation-result error">{validationError}</div>
+          )}
+          {validation && (
+            <div className="validation-result">
+              <span>Key works. It can read:</span>
+              <ul>
+                <li>{validation.usage_read ? '✓' : '✗'} Token usage</li>
+                <li>{validation.cost_read ? '✓' : '✗'} Cost</li>
+                <li>{validation.balance_read ? '✓' : '✗'} Balance</li>
+              </ul>
+              {validation.admin_key === false && (
+                <small className="help-text">
+                  Usage and cost reports need an Anthropic Admin key (sk-ant-admin...).
+                </small>
+              )}
+            </div>
+          )}
+
           <div className="modal-actions">
             <button type="button" className="btn-secondary" onClick={onClose}>
               Cancel
             </button>
-            <button type="submit" className="btn-primary">
-              Add Provider
+            <button type="submit" className="btn-primary" disabled={validating}>
+              {validating ? 'Testing...' : validation ? 'Add Provider' : 'Test Key'}
             </button>
           </div>
         </form>
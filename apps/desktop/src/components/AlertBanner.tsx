@@ -0,0 +1,45 @@
+import React from 'react';
+
+export interface AlertEvent {
+  alert_id: string;
+  provider_id?: string;
+  group_id?: string;
+  provider_name: string;
+  severity: 'warning' | 'critical';
+  current_value: number;
+  threshold: number;
+  unit: 'usd' | 'credits' | 'tokens' | 'days' | 'value';
+  title: string;
+  message: string;
+  notify_desktop: boolean;
+  triggered_at: string;
+}
+
+interface AlertBannerProps {
+  alerts: AlertEvent[];
+  onSnooze: (alertId: string) => void;
+  onDismiss: (alertId: string) => void;
+}
+
+// Alerts fired while the app is open. OS notifications only offer Snooze on
+// some platforms, so the banner always does.
+export const AlertBanner: React.FC<AlertBannerProps> = ({ alerts, onSnooze, onDismiss }) => {
+  if (alerts.length === 0) return null;
+
+  return (
+    <div className="alert-banner">
+      {alerts.map((alert) => (
+        <div key={alert.alert_id} className={`alert-item ${alert.severity}`}>
+          <div className="alert-text">
+            <strong>{alert.title}</strong>
+            <span>{alert.message}</span>
+          </div>
+          <div className="alert-actions">
+            <button className="link-btn" onClick={() => onSnooze(alert.alert_id)}>Snooze 1h</button>
+            <button className="link-btn" onClick={() => onDismiss(alert.alert_id)}>Acknowledge</button>
+          </div>
+        </div>
+      ))}
+    </div>
+  );
+};
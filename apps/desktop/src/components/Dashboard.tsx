@@ -1,13 +1,22 @@
 import React, { useEffect, useState } from 'react';
 import { invoke } from '@tauri-apps/api/core';
-import { ProviderCard } from './ProviderCard';
-import { AddProviderModal } from './AddProviderModal';
+import { listen } from '@tauri-apps/api/event';
+import { getCurrentWindow } from '@tauri-apps/api/window';
+import {
+  isPermissionGranted,
+  onAction,
+  registerActionTypes,
+  requestPermission,
+} from '@tauri-apps/plugin-notification';
+import { AlertBanner, AlertEvent } from './AlertBanner';
+import { BackfillProgress, ProviderCard, ProviderError } from './ProviderCard';
+import { AddProviderModal, ProviderCapabilities, ProviderSettings } from './AddProviderModal';
 import '../styles/Dashboard.css';
 
 interface Provider {
   id: string;
   name: string;
-  provider_type: 'openai' | 'anthropic' | 'openrouter';
+  provider_type: 'openai' | 'anthropic' | 'openrouter' | 'gemini' | 'azure_openai' | 'custom' | 'local' | 'bedrock';
   enabled: boolean;
 }
 
@@ -20,21 +29,39 @@ interface ProviderUsage {
   balance?: number;
   credits?: number;
   budget_used_percentage?: number;
+  last_error?: ProviderError;
+  capabilities: ProviderCapabilities;
+  last_synced_at?: string;
+  stale: boolean;
 }
 
+interface ProviderUsageResponse {
+  providers: ProviderUsage[];
+  offline_since?: string;
+}
+
+// Commands reject with `{ code, message, retryable }`.
+const errorMessage = (error: unknown) =>
+  (error as ProviderError)?.message ?? String(error);
+
 export const Dashboard: React.FC = () => {
   const [providers, setProviders] = useState<ProviderUsage[]>([]);
   const [loading, setLoading] = useState(true);
   const [showAddProvider, setShowAddProvider] = useState(false);
   const [refreshInterval, setRefreshInterval] = useState<NodeJS.Timeout | null>(null);
+  const [backfills, setBackfills] = useState<Record<string, BackfillProgress>>({});
+  const [refreshing, setRefreshing] = useState<Set<string>>(new Set());
+  const [offlineSince, setOfflineSince] = useState<string | undefined>();
+  const [alerts, setAlerts] = useState<AlertEvent[]>([]);
 
   const fetchUsage = async () => {
     try {
-      const response = await invoke<{ providers: ProviderUsage[] }>('get_usage');
+      const response = await invoke<ProviderUsageResponse>('get_usage');
       setProviders(response.providers);
+      setOfflineSince(response.offline_since);
       setLoading(false);
     } catch (error) {
-      console.error('Failed to fetch usage:', error);
+      console.error('Failed to fetch usage:', errorMessage(error));
       setLoading(false);
     }
   };
@@ -50,17 +77,139 @@ export const Dashboard: React.FC = () => {
     };
   }, []);
 
+  useEffect(() => {
+    const unlisten = listen<BackfillProgress>('backfill-progress', (event) => {
+      setBackfills((current) => ({ ...current, [event.payload.provider_id]: event.payload }));
+      if (event.payload.done) fetchUsage();
+    });
+
+    return () => {
+      unlisten.then((stop) => stop());
+    };
+  }, []);
+
+  useEffect(() => {
+    const unlisten = listen<AlertEvent>('alert-triggered', (event) => {
+      setAlerts((current) => [
+        event.payload,
+        ...current.filter((a) => a.alert_id !== event.payload.alert_id),
+      ]);
+    });
+
+    const setUpNotifications = async () => {
+      if (!(await isPermissionGranted())) await requestPermission();
+      await registerActionTypes([
+        {
+          id: 'alert',
+          actions: [
+            { id: 'snooze', title: 'Snooze 1h' },
+            { id: 'open', title: 'Open Dashboard', foreground: true },
+          ],
+        },
+      ]);
+      // The typings only describe the notification, but the payload also
+      // carries which action was pressed.
+      return onAction((performed) => {
+        const { actionId, notification } = performed as unknown as {
+          actionId: string;
+          notification: { extra?: Record<string, unknown> };
+        };
+        const alertId = notification?.extra?.alert_id as string | undefined;
+        if (actionId === 'snooze' && alertId) handleSnoozeAlert(alertId);
+        if (actionId === 'open') {
+          const window = getCurrentWindow();
+          window.unminimize().then(() => window.show()).then(() => window.setFocus());
+        }
+      });
+    };
+    const actionListener = setUpNotifications().catch((error) => {
+      console.error('Failed to set up notifications:', errorMessage(error));
+      return undefined;
+    });
+
+    return () => {
+      unlisten.then((stop) => stop());
+      actionListener.then((listener) => listener?.unregister());
+    };
+  }, []);
+
+  // Merges polled providers into the list without waiting for the next fetch.
+  const mergeUsage = (updated: ProviderUsage[]) => {
+    setProviders((current) =>
+      current.map((p) => updated.find((u) => u.provider.id === p.provider.id) ?? p)
+    );
+  };
+
+  const handleRefresh = async (providerId?: string) => {
+    const key = providerId ?? '*';
+    setRefreshing((current) => new Set(current).add(key));
+    try {
+      const response = await invoke<ProviderUsageResponse>('poll_now', { providerId });
+      mergeUsage(response.providers);
+      setOfflineSince(response.offline_since);
+    } catch (error) {
+      console.error('Failed to refresh:', errorMessage(error));
+    } finally {
+      setRefreshing((current) => {
+        const next = new Set(current);
+        next.delete(key);
+        return next;
+      });
+    }
+  };
+
+  const dismissAlert = (alertId: string) => {
+    setAlerts((current) => current.filter((a) => a.alert_id !== alertId));
+  };
+
+  const handleAcknowledgeAlert = async (alertId: string) => {
+    try {
+      await invoke('acknowledge_alert', { alertId });
+    } catch (error) {
+      // Already re-armed or acknowledged elsewhere; the banner can still go.
+      console.error('Failed to acknowledge alert:', errorMessage(error));
+    }
+    dismissAlert(alertId);
+  };
+
+  const handleSnoozeAlert = async (alertId: string) => {
+    try {
+      const until = new Date(Date.now() + 60 * 60 * 1000).toISOString();
+      await invoke('snooze_alert', { alertId, until });
+      dismissAlert(alertId);
+    } catch (error) {
+      console.error('Failed to snooze alert:', errorMessage(error));
+    }
+  };
+
+  const handleCancelBackfill = async (providerId: string) => {
+    try {
+      await invoke('cancel_backfill', { providerId });
+    } catch (error) {
+      console.error('Failed to cancel backfill:', errorMessage(error));
+    }
+  };
+
+  const handleStartBackfill = async (providerId: string) => {
+    try {
+      await invoke('start_backfill', { providerId });
+    } catch (error) {
+      console.error('Failed to start backfill:', errorMessage(error));
+    }
+  };
+
   const handleAddProvider = async (providerData: {
     provider_type: string;
     api_key: string;
     name: string;
+    settings: ProviderSettings;
   }) => {
     try {
       await invoke('add_provider', { request: providerData });
       await fetchUsage();
       setShowAddProvider(false);
     } catch (error) {
-      console.error('Failed to add provider:', error);
+      console.error('Failed to add provider:', errorMessage(error));
     }
   };
 
@@ -69,7 +218,7 @@ export const Dashboard: React.FC = () => {
       await invoke('remove_provider', { providerId });
       await fetchUsage();
     } catch (error) {
-      console.error('Failed to remove provider:', error);
+      console.error('Failed to remove provider:', errorMessage(error));
     }
   };
 
@@ -78,7 +227,7 @@ export const Dashboard: React.FC = () => {
       await invoke('toggle_provider', { providerId, enabled });
       await fetchUsage();
     } catch (error) {
-      console.error('Failed to toggle provider:', error);
+      console.error('Failed to toggle provider:', errorMessage(error));
     }
   };
 
@@ -113,6 +262,13 @@ export const Dashboard: React.FC = () => {
             <span className="stat-value">${getTotalMTDCost().toFixed(2)}</span>
           </div>
         </div>
+        <button
+          className="add-provider-btn"
+          onClick={() => handleRefresh()}
+          disabled={refreshing.has('*')}
+        >
+          {refreshing.has('*') ? 'Refreshing…' : '↻ Refresh'}
+        </button>
         <button
           className="add-provider-btn"
           onClick={() => setShowAddProvider(true)}
@@ -121,6 +277,15 @@ export const Dashboard: React.FC = () => {
         </button>
       </header>
 
+      <AlertBanner alerts={alerts} onSnooze={handleSnoozeAlert} onDismiss={handleAcknowledgeAlert} />
+
+      {offlineSince && (
+        <div className="offline-banner">
+          Offline since {new Date(offlineSince).toLocaleTimeString()}. Showing last known values;
+          usage will catch up when the connection is back.
+        </div>
+      )}
+
       <div className="providers-grid">
         {providers.map((usage) => (
           <ProviderCard
@@ -128,6 +293,11 @@ export const Dashboard: React.FC = () => {
             usage={usage}
             onToggle={(enabled) => handleToggleProvider(usage.provider.id, enabled)}
             onRemove={() => handleRemoveProvider(usage.provider.id)}
+            backfill={backfills[usage.provider.id]}
+            onBackfill={() => handleStartBackfill(usage.provider.id)}
+            onCancelBackfill={() => handleCancelBackfill(usage.provider.id)}
+            refreshing={refreshing.has(usage.provider.id) || refreshing.has('*')}
+            onRefresh={() => handleRefresh(usage.provider.id)}
           />
         ))}
 
@@ -1,7 +1,36 @@
 import React from 'react';
 import { Sparkline } from './Sparkline';
+import { ProviderCapabilities } from './AddProviderModal';
 import '../styles/ProviderCard.css';
 
+export interface ProviderError {
+  code:
+    | 'invalid_api_key'
+    | 'insufficient_permissions'
+    | 'token_expired'
+    | 'rate_limited'
+    | 'network_unavailable'
+    | 'provider_unavailable'
+    | 'unexpected_response'
+    | 'invalid_request'
+    | 'not_found'
+    | 'keychain_unavailable'
+    | 'internal';
+  message: string;
+  retryable: boolean;
+  occurred_at?: string;
+}
+
+export interface BackfillProgress {
+  provider_id: string;
+  fetched_back_to: string;
+  target: string;
+  percent: number;
+  done: boolean;
+  cancelled: boolean;
+  error?: ProviderError;
+}
+
 interface ProviderUsage {
   provider: {
     id: string;
@@ -16,15 +45,36 @@ interface ProviderUsage {
   balance?: number;
   credits?: number;
   budget_used_percentage?: number;
+  last_error?: ProviderError;
+  capabilities: ProviderCapabilities;
+  last_synced_at?: string;
+  stale: boolean;
+  projected_run_out_at?: string;
+  projected_period_spend?: number;
+  notional_mtd_cost?: number;
 }
 
 interface ProviderCardProps {
   usage: ProviderUsage;
   onToggle: (enabled: boolean) => void;
   onRemove: () => void;
+  backfill?: BackfillProgress;
+  onBackfill: () => void;
+  onCancelBackfill: () => void;
+  refreshing: boolean;
+  onRefresh: () => void;
 }
 
-export const ProviderCard: React.FC<ProviderCardProps> = ({ usage, onToggle, onRemove }) => {
+export const ProviderCard: React.FC<ProviderCardProps> = ({
+  usage,
+  onToggle,
+  onRemove,
+  backfill,
+  onBackfill,
+  onCancelBackfill,
+  refreshing,
+  onRefresh,
+}) => {
   const getProviderIcon = () => {
     switch (usage.provider.provider_type) {
       case 'openai':
@@ -33,6 +83,14 @@ export const ProviderCard: React.FC<ProviderCardProps> = ({ usage, onToggle, onR
         return '🧠';
       case 'openrouter':
         return '🌐';
+      case 'gemini':
+        return '✨';
+      case 'azure_openai':
+        return '☁️';
+      case 'local':
+        return '🖥️';
+      case 'bedrock':
+        return '🪨';
       default:
         return '📊';
     }
@@ -46,6 +104,24 @@ export const ProviderCard: React.FC<ProviderCardProps> = ({ usage, onToggle, onR
     return 'healthy';
   };
 
+  const { capabilities } = usage;
+  const backfillRunning = backfill !== undefined && !backfill.done && !backfill.cancelled && !backfill.error;
+
+  // Estimated costs come from a price table rather than the provider's bill.
+  const formatCost = (cost: number) => {
+    if (!capabilities.cost) return '—';
+    return `${capabilities.estimated_cost ? '~' : ''}$${cost.toFixed(2)}`;
+  };
+
+  const formatSyncedAt = (syncedAt?: string) => {
+    if (!syncedAt) return 'Not synced yet';
+    const minutes = Math.floor((Date.now() - new Date(syncedAt).getTime()) / 60000);
+    if (minutes < 1) return 'Synced just now';
+    if (minutes < 60) return `Synced ${minutes} min ago`;
+    if (minutes < 24 * 60) return `Synced ${Math.floor(minutes / 60)} h ago`;
+    return `Synced ${new Date(syncedAt).toLocaleDateString()}`;
+  };
+
   const formatTokens = (tokens: number) => {
     if (tokens >= 1000000) return `${(tokens / 1000000).toFixed(1)}M`;
     if (tokens >= 1000) return `${(tokens / 1000).toFixed(1)}K`;
@@ -53,7 +129,7 @@ export const ProviderCard: React.FC<ProviderCardProps> = ({ usage, onToggle, onR
   };
 
   return (
-    <div className={`provider-card ${getStatusColor()}`}>
+    <div className={`provider-card ${getStatusColor()}${usage.stale ? ' stale' : ''}`}>
       <div className="provider-header">
         <div className="provider-title">
           <span className="provider-icon">{getProviderIcon()}</span>
@@ -68,35 +144,89 @@ export const ProviderCard: React.FC<ProviderCardProps> = ({ usage, onToggle, onR
             />
             <span className="toggle-slider"></span>
           </label>
+          <button
+            className="remove-btn"
+            onClick={onRefresh}
+            disabled={refreshing}
+            title="Refresh now"
+          >
+            ↻
+          </button>
+          {usage.capabilities.backfill_days > 0 && (
+            <button
+              className="remove-btn"
+              onClick={onBackfill}
+              disabled={backfillRunning}
+              title={`Import up to ${usage.capabilities.backfill_days} days of history`}
+            >
+              ⟲
+            </button>
+          )}
           <button className="remove-btn" onClick={onRemove} title="Remove provider">
             ×
           </button>
         </div>
       </div>
 
+      {usage.last_error && (
+        <div className={`provider-error ${usage.last_error.retryable ? 'retryable' : 'action-needed'}`}>
+          <span className="error-message">{usage.last_error.message}</span>
+          {usage.last_error.retryable && (
+            <span className="sub-label">Showing last known data; will retry automatically</span>
+          )}
+        </div>
+      )}
+
+      {backfill && !backfill.done && !backfill.cancelled && (
+        <div className="backfill-status">
+          {backfill.error ? (
+            <span className="sub-label">History import stopped: {backfill.error.message}</span>
+          ) : (
+            <>
+              <span className="sub-label">
+                Importing history… {backfill.percent.toFixed(0)}%{' '}
+                <button className="link-btn" onClick={onCancelBackfill}>Cancel</button>
+              </span>
+              <div className="progress-bar">
+                <div className="progress-fill" style={{ width: `${backfill.percent}%` }} />
+              </div>
+            </>
+          )}
+        </div>
+      )}
+
       <div className="provider-stats">
         <div className="stat-row">
           <div className="stat">
             <span className="label">Today</span>
-            <span className="value">${usage.today_cost.toFixed(2)}</span>
-            <span className="sub-label">{formatTokens(usage.today_tokens)} tokens</span>
+            <span className="value">
+              {capabilities.month_to_date_only ? '—' : formatCost(usage.today_cost)}
+            </span>
+            {capabilities.token_usage && !capabilities.month_to_date_only && (
+              <span className="sub-label">{formatTokens(usage.today_tokens)} tokens</span>
+            )}
           </div>
           <div className="stat">
             <span className="label">MTD</span>
-            <span className="value">${usage.mtd_cost.toFixed(2)}</span>
-            <span className="sub-label">{formatTokens(usage.mtd_tokens)} tokens</span>
+            <span className="value">{formatCost(usage.mtd_cost)}</span>
+            {capabilities.token_usage && (
+              <span className="sub-label">{formatTokens(usage.mtd_tokens)} tokens</span>
+            )}
+            {usage.notional_mtd_cost != null && (
+              <span className="sub-label">~${usage.notional_mtd_cost.toFixed(2)} notional</span>
+            )}
           </div>
         </div>
 
-        {(usage.balance !== undefined || usage.credits !== undefined) && (
+        {(capabilities.balance || capabilities.credits) && (
           <div className="balance-row">
-            {usage.credits !== undefined && (
+            {capabilities.credits && usage.credits != null && (
               <div className="stat">
                 <span className="label">Credits</span>
                 <span className="value">{usage.credits.toFixed(2)}</span>
               </div>
             )}
-            {usage.balance !== undefined && (
+            {capabilities.balance && usage.balance != null && (
               <div className="stat">
                 <span className="label">Balance</span>
                 <span className="value">${usage.balance.toFixed(2)}</span>
@@ -105,6 +235,19 @@ export const ProviderCard: React.FC<ProviderCardProps> = ({ usage, onToggle, onR
           </div>
         )}
 
+        {(usage.projected_period_spend != null || usage.projected_run_out_at) && (
+          <div className="forecast-row">
+            {usage.projected_period_spend != null && (
+              <span className="sub-label">Projected {formatCost(usage.projected_period_spend)} this month</span>
+            )}
+            {usage.projected_run_out_at && (
+              <span className="sub-label run-out">
+                Runs out around {new Date(usage.projected_run_out_at).toLocaleDateString()}
+              </span>
+            )}
+          </div>
+        )}
+
         {usage.budget_used_percentage !== undefined && (
           <div className="budget-progress">
             <div className="budget-header">
@@ -124,6 +267,16 @@ export const ProviderCard: React.FC<ProviderCardProps> = ({ usage, onToggle, onR
       <div className="sparkline-container">
         <Sparkline data={[]} label="24h Usage" />
       </div>
+
+      {usage.provider.enabled && (
+        <div
+          className={`sync-status${usage.stale ? ' stale' : ''}`}
+          title={usage.last_synced_at ? new Date(usage.last_synced_at).toLocaleString() : undefined}
+        >
+          {usage.stale && '⚠ '}
+          {formatSyncedAt(usage.last_synced_at)}
+        </div>
+      )}
     </div>
   );
 };
\ No newline at end of file